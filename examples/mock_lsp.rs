@@ -0,0 +1,192 @@
+//! A tiny language server for trying out eep's LSP client without a real
+//! one installed. It flags every line containing `TODO`, answers hover and
+//! go-to-definition for the word under the cursor, renames every
+//! occurrence of a word, offers a code action replacing `TODO`, and
+//! completes a few keywords.
+//!
+//! ```toml
+//! [lsp.rust]
+//! command = "target/debug/examples/mock_lsp"
+//! ```
+
+use serde_json::{json, Value};
+use std::io::{self, BufRead, Write};
+
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).ok()? == 0 {
+            return None;
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok()?;
+        }
+    }
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+
+fn send(message: Value) {
+    let body = message.to_string();
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = stdout.flush();
+}
+
+/// Byte offset of an LSP position, counting columns in bytes as negotiated.
+fn offset(text: &str, position: &Value) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+    let start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    std::cmp::min(start + character, text.len())
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn word_at(text: &str, position: &Value) -> Option<String> {
+    let line = text.lines().nth(position["line"].as_u64()? as usize)?;
+    let x = std::cmp::min(position["character"].as_u64()? as usize, line.len());
+    let start = line[..x].rfind(|c| !is_word(c)).map_or(0, |i| i + 1);
+    let end = line[x..]
+        .find(|c| !is_word(c))
+        .map_or(line.len(), |i| x + i);
+    (start < end).then(|| line[start..end].to_string())
+}
+
+/// Ranges of every whole-word occurrence of `word`.
+fn occurrences(text: &str, word: &str) -> Vec<Value> {
+    let mut ranges = Vec::new();
+    for (y, line) in text.lines().enumerate() {
+        for (x, _) in line.match_indices(word) {
+            let before = line[..x].chars().next_back().is_some_and(is_word);
+            let after = line[x + word.len()..].chars().next().is_some_and(is_word);
+            if !before && !after {
+                ranges.push(json!({
+                    "start": { "line": y, "character": x },
+                    "end": { "line": y, "character": x + word.len() },
+                }));
+            }
+        }
+    }
+    ranges
+}
+
+fn publish_diagnostics(uri: &Value, text: &str) {
+    let diagnostics: Vec<Value> = text
+        .lines()
+        .enumerate()
+        .filter_map(|(y, line)| {
+            let x = line.find("TODO")?;
+            Some(json!({
+                "range": {
+                    "start": { "line": y, "character": x },
+                    "end": { "line": y, "character": x + 4 },
+                },
+                "severity": 2,
+                "message": "unfinished TODO",
+            }))
+        })
+        .collect();
+    send(json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    }));
+}
+
+fn main() {
+    let mut input = io::stdin().lock();
+    let mut uri = Value::Null;
+    let mut text = String::new();
+
+    while let Some(message) = read_message(&mut input) {
+        let params = &message["params"];
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "positionEncoding": "utf-8",
+                    "textDocumentSync": 2,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                    "completionProvider": {},
+                }
+            }),
+            "textDocument/didOpen" => {
+                uri = params["textDocument"]["uri"].clone();
+                text = params["textDocument"]["text"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                publish_diagnostics(&uri, &text);
+                continue;
+            }
+            "textDocument/didChange" => {
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let new_text = change["text"].as_str().unwrap_or_default();
+                    match change.get("range") {
+                        Some(range) => {
+                            let start = offset(&text, &range["start"]);
+                            let end = offset(&text, &range["end"]);
+                            text.replace_range(start..end, new_text);
+                        }
+                        None => text = new_text.to_string(),
+                    }
+                }
+                publish_diagnostics(&uri, &text);
+                continue;
+            }
+            "textDocument/hover" => match word_at(&text, &params["position"]) {
+                Some(word) => json!({ "contents": format!("mock hover: {}", word) }),
+                None => Value::Null,
+            },
+            "textDocument/definition" => word_at(&text, &params["position"])
+                .and_then(|word| occurrences(&text, &word).into_iter().next())
+                .map_or(Value::Null, |range| json!({ "uri": uri, "range": range })),
+            "textDocument/rename" => {
+                let new_name = params["newName"].as_str().unwrap_or_default();
+                let edits: Vec<Value> = word_at(&text, &params["position"])
+                    .map(|word| occurrences(&text, &word))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|range| json!({ "range": range, "newText": new_name }))
+                    .collect();
+                json!({ "changes": { uri.as_str().unwrap_or_default(): edits } })
+            }
+            "textDocument/codeAction" => {
+                let line = params["range"]["start"]["line"].as_u64();
+                let edits: Vec<Value> = occurrences(&text, "TODO")
+                    .into_iter()
+                    .filter(|range| range["start"]["line"].as_u64() == line)
+                    .map(|range| json!({ "range": range, "newText": "DONE" }))
+                    .collect();
+                if edits.is_empty() {
+                    json!([])
+                } else {
+                    json!([{
+                        "title": "Mark as DONE",
+                        "kind": "quickfix",
+                        "edit": { "changes": { uri.as_str().unwrap_or_default(): edits } },
+                    }])
+                }
+            }
+            "textDocument/completion" => json!(["match", "mut", "macro_rules!"]
+                .iter()
+                .map(|keyword| json!({ "label": keyword, "detail": "keyword" }))
+                .collect::<Vec<_>>()),
+            "exit" => return,
+            _ if message.get("id").is_none() => continue,
+            _ => Value::Null,
+        };
+        send(json!({ "jsonrpc": "2.0", "id": message["id"], "result": result }));
+    }
+}
@@ -0,0 +1,1144 @@
+use crate::*;
+use crossterm::event::KeyCode;
+
+pub type ActionResult = Result<(), Box<dyn std::error::Error>>;
+
+/// A named editor behavior that keys can be bound to.
+pub struct Action {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub run: fn(&mut Editor) -> ActionResult,
+}
+
+pub const ACTIONS: &[Action] = &[
+    Action {
+        name: "quit",
+        description: "Quit the editor",
+        run: |editor| {
+            editor.should_quit = true;
+            Ok(())
+        },
+    },
+    Action {
+        name: "write_quit",
+        description: "Write the buffer and quit",
+        run: |editor| {
+            match editor.save_file() {
+                Ok(()) => editor.should_quit = true,
+                Err(e) => editor.set_status(&format!("Error saving file: {}", e)),
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_mode",
+        description: "Open the : command line",
+        run: |editor| {
+            editor.open_command_line();
+            Ok(())
+        },
+    },
+    Action {
+        name: "visual_command_mode",
+        description: "Open the : command line with the selected lines as its range",
+        run: |editor| {
+            let (start, end) = editor.selection();
+            editor.marks.insert('<', start);
+            editor.marks.insert('>', end);
+            editor.open_command_line();
+            editor.command_buffer = "'<,'>".to_string();
+            editor.command_cursor = editor.command_buffer.len();
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_mode",
+        description: "Enter insert mode before the cursor",
+        run: |editor| {
+            editor.mode = Mode::Insert;
+            Ok(())
+        },
+    },
+    Action {
+        name: "append",
+        description: "Enter insert mode after the cursor",
+        run: |editor| {
+            editor.append();
+            Ok(())
+        },
+    },
+    Action {
+        name: "append_line_end",
+        description: "Enter insert mode at the end of the line",
+        run: |editor| {
+            editor.append_line_end();
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_line_start",
+        description: "Enter insert mode before the first non-blank character",
+        run: |editor| {
+            editor.insert_line_start();
+            Ok(())
+        },
+    },
+    Action {
+        name: "open_line_below",
+        description: "Open a line below the cursor and enter insert mode",
+        run: |editor| {
+            editor.open_line(true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "open_line_above",
+        description: "Open a line above the cursor and enter insert mode",
+        run: |editor| {
+            editor.open_line(false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "replace_mode",
+        description: "Enter replace mode",
+        run: |editor| {
+            editor.enter_replace();
+            Ok(())
+        },
+    },
+    Action {
+        name: "replace_char",
+        description: "Replace the character under the cursor with the one typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(Editor::replace_chars);
+            Ok(())
+        },
+    },
+    Action {
+        name: "join_lines",
+        description: "Join the line below, or the selected lines, onto the line",
+        run: |editor| {
+            editor.join_lines();
+            Ok(())
+        },
+    },
+    Action {
+        name: "toggle_case",
+        description: "Toggle the case of the character under the cursor",
+        run: |editor| {
+            editor.toggle_case();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_operator",
+        description: "Delete the text the next motion moves over",
+        run: |editor| {
+            editor.start_operator("delete_operator", Editor::delete_range);
+            Ok(())
+        },
+    },
+    Action {
+        name: "change_operator",
+        description: "Delete the text the next motion moves over and enter insert mode",
+        run: |editor| {
+            editor.start_operator("change_operator", Editor::change_range);
+            Ok(())
+        },
+    },
+    Action {
+        name: "yank_operator",
+        description: "Copy the text the next motion moves over",
+        run: |editor| {
+            editor.start_operator("yank_operator", Editor::yank_range);
+            Ok(())
+        },
+    },
+    Action {
+        name: "toggle_case_operator",
+        description: "Toggle the case of the text the next motion moves over",
+        run: |editor| {
+            editor.start_operator("toggle_case_operator", |editor, from, to, _| {
+                editor.change_case(from, to, Case::Toggle)
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "lowercase_operator",
+        description: "Lowercase the text the next motion moves over",
+        run: |editor| {
+            editor.start_operator("lowercase_operator", |editor, from, to, _| {
+                editor.change_case(from, to, Case::Lower)
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "uppercase_operator",
+        description: "Uppercase the text the next motion moves over",
+        run: |editor| {
+            editor.start_operator("uppercase_operator", |editor, from, to, _| {
+                editor.change_case(from, to, Case::Upper)
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "toggle_case_line",
+        description: "Toggle the case of the line",
+        run: |editor| {
+            editor.change_case_lines(Case::Toggle);
+            Ok(())
+        },
+    },
+    Action {
+        name: "lowercase_line",
+        description: "Lowercase the line",
+        run: |editor| {
+            editor.change_case_lines(Case::Lower);
+            Ok(())
+        },
+    },
+    Action {
+        name: "uppercase_line",
+        description: "Uppercase the line",
+        run: |editor| {
+            editor.change_case_lines(Case::Upper);
+            Ok(())
+        },
+    },
+    Action {
+        name: "toggle_case_selection",
+        description: "Toggle the case of the selection",
+        run: |editor| {
+            editor.change_case_selection(Case::Toggle);
+            Ok(())
+        },
+    },
+    Action {
+        name: "lowercase_selection",
+        description: "Lowercase the selection",
+        run: |editor| {
+            editor.change_case_selection(Case::Lower);
+            Ok(())
+        },
+    },
+    Action {
+        name: "uppercase_selection",
+        description: "Uppercase the selection",
+        run: |editor| {
+            editor.change_case_selection(Case::Upper);
+            Ok(())
+        },
+    },
+    Action {
+        name: "substitute_char",
+        description: "Delete the character under the cursor and enter insert mode",
+        run: |editor| {
+            editor.substitute_chars();
+            Ok(())
+        },
+    },
+    Action {
+        name: "substitute_line",
+        description: "Clear the line, keeping its indentation, and enter insert mode",
+        run: |editor| {
+            editor.substitute_lines();
+            Ok(())
+        },
+    },
+    Action {
+        name: "change_to_line_end",
+        description: "Delete to the end of the line and enter insert mode",
+        run: |editor| {
+            editor.change_to_line_end();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_to_line_end",
+        description: "Delete to the end of the line",
+        run: |editor| {
+            editor.delete_to_line_end();
+            Ok(())
+        },
+    },
+    Action {
+        name: "normal_mode",
+        description: "Leave the current mode",
+        run: |editor| {
+            if editor.mode == Mode::Command {
+                editor.command_buffer.clear();
+                editor.show_command = false;
+            }
+            if matches!(editor.mode, Mode::Insert | Mode::Replace) {
+                editor.repeat_insert()?;
+                let cursor = (editor.cursor_y, editor.cursor_x);
+                editor.marks.insert('^', cursor);
+            }
+            editor.mode = editor.resting_mode();
+            Ok(())
+        },
+    },
+    Action {
+        name: "move_left",
+        description: "Move the cursor left",
+        run: |editor| {
+            editor.move_cursor(KeyCode::Left);
+            Ok(())
+        },
+    },
+    Action {
+        name: "move_down",
+        description: "Move the cursor down",
+        run: |editor| {
+            editor.move_cursor(KeyCode::Down);
+            Ok(())
+        },
+    },
+    Action {
+        name: "move_up",
+        description: "Move the cursor up",
+        run: |editor| {
+            editor.move_cursor(KeyCode::Up);
+            Ok(())
+        },
+    },
+    Action {
+        name: "move_right",
+        description: "Move the cursor right",
+        run: |editor| {
+            editor.move_cursor(KeyCode::Right);
+            Ok(())
+        },
+    },
+    Action {
+        name: "line_start",
+        description: "Move to the start of the line",
+        run: |editor| {
+            editor.cursor_x = 0;
+            Ok(())
+        },
+    },
+    Action {
+        name: "line_end",
+        description: "Move to the end of the line",
+        run: |editor| {
+            editor.cursor_x = editor.content[editor.cursor_y].len();
+            Ok(())
+        },
+    },
+    Action {
+        name: "goto_first_line",
+        description: "Move to the first line, or with a count to that line",
+        run: |editor| {
+            let line = editor.take_count().unwrap_or(1);
+            editor.goto_line(line - 1);
+            Ok(())
+        },
+    },
+    Action {
+        name: "goto_last_line",
+        description: "Move to the last line, or with a count to that line",
+        run: |editor| {
+            let line = editor.take_count().unwrap_or(editor.content.len());
+            editor.goto_line(line - 1);
+            Ok(())
+        },
+    },
+    Action {
+        name: "word_forward",
+        description: "Move to the start of the next word",
+        run: |editor| {
+            editor.move_by(|e, pos| e.next_word_start(pos, false), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "big_word_forward",
+        description: "Move to the start of the next WORD",
+        run: |editor| {
+            editor.move_by(|e, pos| e.next_word_start(pos, true), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "word_backward",
+        description: "Move to the start of the previous word",
+        run: |editor| {
+            editor.move_by(|e, pos| e.previous_word_start(pos, false), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "big_word_backward",
+        description: "Move to the start of the previous WORD",
+        run: |editor| {
+            editor.move_by(|e, pos| e.previous_word_start(pos, true), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "word_end",
+        description: "Move to the end of the word",
+        run: |editor| {
+            editor.move_by(|e, pos| e.next_word_end(pos, false), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "big_word_end",
+        description: "Move to the end of the WORD",
+        run: |editor| {
+            editor.move_by(|e, pos| e.next_word_end(pos, true), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "word_end_backward",
+        description: "Move to the end of the previous word",
+        run: |editor| {
+            editor.move_by(|e, pos| e.previous_word_end(pos, false), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "big_word_end_backward",
+        description: "Move to the end of the previous WORD",
+        run: |editor| {
+            editor.move_by(|e, pos| e.previous_word_end(pos, true), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "first_non_blank",
+        description: "Move to the first non-blank character of the line",
+        run: |editor| {
+            (editor.cursor_y, editor.cursor_x) = editor.first_non_blank(editor.cursor_y);
+            Ok(())
+        },
+    },
+    Action {
+        name: "line_down_first_non_blank",
+        description: "Move to the first non-blank character, a count minus one lines down",
+        run: |editor| {
+            let lines = editor.take_count().unwrap_or(1) - 1;
+            let y = std::cmp::min(editor.cursor_y + lines, editor.content.len() - 1);
+            (editor.cursor_y, editor.cursor_x) = editor.first_non_blank(y);
+            Ok(())
+        },
+    },
+    Action {
+        name: "paragraph_forward",
+        description: "Move to the end of the paragraph",
+        run: |editor| {
+            editor.move_by(Editor::next_paragraph, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "paragraph_backward",
+        description: "Move to the start of the paragraph",
+        run: |editor| {
+            editor.move_by(Editor::previous_paragraph, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "sentence_forward",
+        description: "Move to the start of the next sentence",
+        run: |editor| {
+            editor.move_by(Editor::next_sentence, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "sentence_backward",
+        description: "Move to the start of the sentence",
+        run: |editor| {
+            editor.move_by(Editor::previous_sentence, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "screen_top",
+        description: "Move to the top of the screen, or a count of lines below it",
+        run: |editor| {
+            editor.goto_screen_line('H');
+            Ok(())
+        },
+    },
+    Action {
+        name: "screen_middle",
+        description: "Move to the middle of the screen",
+        run: |editor| {
+            editor.goto_screen_line('M');
+            Ok(())
+        },
+    },
+    Action {
+        name: "screen_bottom",
+        description: "Move to the bottom of the screen, or a count of lines above it",
+        run: |editor| {
+            editor.goto_screen_line('L');
+            Ok(())
+        },
+    },
+    Action {
+        name: "find_char",
+        description: "Move to the next occurrence of the character typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| {
+                editor.find_char(
+                    c,
+                    Find {
+                        forward: true,
+                        till: false,
+                    },
+                )
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "find_char_backward",
+        description: "Move to the previous occurrence of the character typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| {
+                editor.find_char(
+                    c,
+                    Find {
+                        forward: false,
+                        till: false,
+                    },
+                )
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "till_char",
+        description: "Move to just before the next occurrence of the character typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| {
+                editor.find_char(
+                    c,
+                    Find {
+                        forward: true,
+                        till: true,
+                    },
+                )
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "till_char_backward",
+        description: "Move to just after the previous occurrence of the character typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| {
+                editor.find_char(
+                    c,
+                    Find {
+                        forward: false,
+                        till: true,
+                    },
+                )
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "repeat_find",
+        description: "Repeat the last f, F, t or T",
+        run: |editor| {
+            editor.repeat_find(false, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "repeat_find_reverse",
+        description: "Repeat the last f, F, t or T the other way",
+        run: |editor| {
+            editor.repeat_find(true, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "half_page_down",
+        description: "Scroll down half a screen",
+        run: |editor| {
+            editor.scroll_by(editor.screen_rows / 2, true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "half_page_up",
+        description: "Scroll up half a screen",
+        run: |editor| {
+            editor.scroll_by(editor.screen_rows / 2, false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "page_down",
+        description: "Scroll down a screen",
+        run: |editor| {
+            editor.scroll_by(editor.screen_rows.saturating_sub(2), true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "page_up",
+        description: "Scroll up a screen",
+        run: |editor| {
+            editor.scroll_by(editor.screen_rows.saturating_sub(2), false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "repeat_change",
+        description: "Repeat the last change",
+        run: |editor| {
+            editor.repeat_change();
+            Ok(())
+        },
+    },
+    Action {
+        name: "record_macro",
+        description: "Record keys into the register typed next, or stop recording",
+        run: |editor| {
+            match editor.recording {
+                Some(_) => editor.stop_recording(),
+                None => editor.awaiting_char = Some(Editor::start_recording),
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "play_macro",
+        description: "Replay the register typed next, or the last one with @",
+        run: |editor| {
+            editor.awaiting_char = Some(Editor::play_macro);
+            Ok(())
+        },
+    },
+    Action {
+        name: "set_mark",
+        description: "Mark the cursor position with the next letter typed",
+        run: |editor| {
+            editor.awaiting_char = Some(Editor::set_mark);
+            Ok(())
+        },
+    },
+    Action {
+        name: "goto_mark",
+        description: "Jump to the mark named by the next key",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| editor.goto_mark(c, false));
+            Ok(())
+        },
+    },
+    Action {
+        name: "goto_mark_line",
+        description: "Jump to the line of the mark named by the next key",
+        run: |editor| {
+            editor.awaiting_char = Some(|editor, c| editor.goto_mark(c, true));
+            Ok(())
+        },
+    },
+    Action {
+        name: "jump_back",
+        description: "Go back to the previous position in the jump list",
+        run: |editor| {
+            editor.jump_back();
+            Ok(())
+        },
+    },
+    Action {
+        name: "jump_forward",
+        description: "Go forward to the next position in the jump list",
+        run: |editor| {
+            editor.jump_forward();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_char_forward",
+        description: "Delete the character under the cursor",
+        run: |editor| {
+            let line = &editor.content[editor.cursor_y];
+            let x = line.floor_char_boundary(editor.cursor_x);
+            if let Some(c) = line[x..].chars().next() {
+                let mut line = line.clone();
+                line.replace_range(x..x + c.len_utf8(), "");
+                editor.cursor_x = x;
+                editor.set_line(editor.cursor_y, line);
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_line",
+        description: "Delete the current line",
+        run: |editor| {
+            if editor.content.len() > 1 {
+                editor.replace_lines(editor.cursor_y, editor.cursor_y + 1, Vec::new());
+                if editor.cursor_y >= editor.content.len() {
+                    editor.cursor_y = editor.content.len() - 1;
+                }
+                editor.cursor_x = 0;
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "shift_right",
+        description: "Indent the line or selection by one level",
+        run: |editor| {
+            editor.shift_lines(true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "shift_left",
+        description: "Remove one level of indentation from the line or selection",
+        run: |editor| {
+            editor.shift_lines(false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "shift_right_operator",
+        description: "Indent the lines the next motion moves over by one level",
+        run: |editor| {
+            editor.start_operator("shift_right_operator", |editor, start, end, _| {
+                editor.shift_range(start.0..end.0 + 1, true, 1);
+                editor.cursor_to_indent();
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "shift_left_operator",
+        description: "Dedent the lines the next motion moves over by one level",
+        run: |editor| {
+            editor.start_operator("shift_left_operator", |editor, start, end, _| {
+                editor.shift_range(start.0..end.0 + 1, false, 1);
+                editor.cursor_to_indent();
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "reindent_operator",
+        description: "Reindent the lines the next motion moves over",
+        run: |editor| {
+            editor.start_operator("reindent_operator", |editor, start, end, _| {
+                editor.reindent_range(start.0..end.0 + 1);
+            });
+            Ok(())
+        },
+    },
+    Action {
+        name: "reindent",
+        description: "Reindent the line or selection from the line above",
+        run: |editor| {
+            editor.reindent_lines();
+            Ok(())
+        },
+    },
+    Action {
+        name: "undo",
+        description: "Undo the last change",
+        run: |editor| {
+            editor.set_status("Undo not implemented yet");
+            Ok(())
+        },
+    },
+    Action {
+        name: "search",
+        description: "Search forward",
+        run: |editor| {
+            editor.set_status("Search not implemented yet");
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_char",
+        description: "Delete the character before the cursor",
+        run: |editor| {
+            editor.delete_char();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_word_before",
+        description: "Delete the word before the cursor",
+        run: |editor| {
+            editor.delete_word_before();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_to_line_start",
+        description: "Delete back to the indentation or the start of the line",
+        run: |editor| {
+            editor.delete_to_line_start();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_forward",
+        description: "Delete the character under the cursor, joining lines at the end of one",
+        run: |editor| {
+            editor.delete_forward();
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_register",
+        description: "Insert the register typed next",
+        run: |editor| {
+            editor.awaiting_char = Some(Editor::insert_register);
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_normal_command",
+        description: "Run one normal mode command, then return to insert mode",
+        run: |editor| {
+            editor.mode = Mode::Normal;
+            editor.insert_oneshot = true;
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_newline",
+        description: "Split the line at the cursor",
+        run: |editor| {
+            editor.insert_newline();
+            Ok(())
+        },
+    },
+    Action {
+        name: "insert_tab",
+        description: "Insert a tab, expanded to spaces with expandtab",
+        run: |editor| {
+            editor.insert_char('\t');
+            Ok(())
+        },
+    },
+    Action {
+        name: "complete_next",
+        description: "Open the completion popup or select the next entry",
+        run: |editor| {
+            editor.cycle_completion(1);
+            Ok(())
+        },
+    },
+    Action {
+        name: "complete_previous",
+        description: "Open the completion popup or select the previous entry",
+        run: |editor| {
+            editor.cycle_completion(-1);
+            Ok(())
+        },
+    },
+    Action {
+        name: "complete_or_tab",
+        description: "Select the next completion if the popup is open, else insert a tab",
+        run: |editor| {
+            match editor.completion {
+                Some(ref completion) if !completion.matches.is_empty() => {
+                    editor.cycle_completion(1)
+                }
+                _ => editor.insert_char('\t'),
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "accept_or_newline",
+        description: "Accept the selected completion, else close the popup and split the line",
+        run: |editor| {
+            if !editor.accept_completion() {
+                editor.insert_newline();
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_backspace",
+        description: "Delete the character before the command line cursor",
+        run: |editor| {
+            editor.command_backspace();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_delete",
+        description: "Delete the character under the command line cursor",
+        run: |editor| {
+            editor.command_delete();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_delete_word",
+        description: "Delete the word before the command line cursor",
+        run: |editor| {
+            editor.command_delete_word();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_delete_to_start",
+        description: "Delete the command line before the cursor",
+        run: |editor| {
+            editor.command_delete_to_start();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_left",
+        description: "Move the command line cursor left",
+        run: |editor| {
+            editor.command_cursor_left();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_right",
+        description: "Move the command line cursor right",
+        run: |editor| {
+            editor.command_cursor_right();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_word_left",
+        description: "Move the command line cursor back a word",
+        run: |editor| {
+            editor.command_word_motion(false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_word_right",
+        description: "Move the command line cursor forward a word",
+        run: |editor| {
+            editor.command_word_motion(true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_home",
+        description: "Move the command line cursor to the start",
+        run: |editor| {
+            editor.command_cursor_home();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_end",
+        description: "Move the command line cursor to the end",
+        run: |editor| {
+            editor.command_cursor_end();
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_history_older",
+        description: "Recall an older command starting with the typed text",
+        run: |editor| {
+            editor.command_history_step(true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_history_newer",
+        description: "Recall a newer command starting with the typed text",
+        run: |editor| {
+            editor.command_history_step(false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_complete_next",
+        description: "Complete the word before the command line cursor",
+        run: |editor| {
+            editor.complete_command(true);
+            Ok(())
+        },
+    },
+    Action {
+        name: "command_complete_previous",
+        description: "Complete the word before the command line cursor, backwards",
+        run: |editor| {
+            editor.complete_command(false);
+            Ok(())
+        },
+    },
+    Action {
+        name: "process_command",
+        description: "Run the command line",
+        run: |editor| {
+            editor.run_command_line()?;
+            if editor.mode == Mode::Command {
+                editor.mode = editor.resting_mode();
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "save",
+        description: "Write the buffer to its file",
+        run: |editor| {
+            if let Err(e) = editor.save_file() {
+                editor.set_status(&format!("Error saving file: {}", e));
+            }
+            Ok(())
+        },
+    },
+    Action {
+        name: "toggle_hex",
+        description: "Switch between text and hex editing",
+        run: |editor| {
+            editor.toggle_hex();
+            Ok(())
+        },
+    },
+    Action {
+        name: "visual_mode",
+        description: "Start selecting text",
+        run: |editor| {
+            editor.enter_visual();
+            Ok(())
+        },
+    },
+    Action {
+        name: "delete_selection",
+        description: "Delete the selected text",
+        run: |editor| {
+            editor.delete_selection();
+            Ok(())
+        },
+    },
+    Action {
+        name: "match_bracket",
+        description: "Jump to the bracket matching the next one on the line",
+        run: |editor| {
+            editor.jump_to_bracket();
+            Ok(())
+        },
+    },
+    Action {
+        name: "goto_definition",
+        description: "Jump to the definition of the symbol under the cursor",
+        run: |editor| {
+            editor.lsp_definition();
+            Ok(())
+        },
+    },
+    Action {
+        name: "hover",
+        description: "Show documentation for the symbol under the cursor",
+        run: |editor| {
+            editor.lsp_hover();
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "expand_selection",
+        description: "Select the enclosing syntax node",
+        run: |editor| {
+            editor.expand_selection();
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "next_function",
+        description: "Jump to the start of the next function",
+        run: |editor| {
+            editor.goto_function(true);
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "previous_function",
+        description: "Jump to the start of the previous function",
+        run: |editor| {
+            editor.goto_function(false);
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "select_function",
+        description: "Select the function around the cursor",
+        run: |editor| {
+            editor.select_text_object(false, false);
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "select_function_body",
+        description: "Select the body of the function around the cursor",
+        run: |editor| {
+            editor.select_text_object(false, true);
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "select_class",
+        description: "Select the type, impl or table around the cursor",
+        run: |editor| {
+            editor.select_text_object(true, false);
+            Ok(())
+        },
+    },
+    #[cfg(feature = "treesitter")]
+    Action {
+        name: "select_class_body",
+        description: "Select the body of the type, impl or table around the cursor",
+        run: |editor| {
+            editor.select_text_object(true, true);
+            Ok(())
+        },
+    },
+];
+
+pub fn find_action(name: &str) -> Option<&'static Action> {
+    ACTIONS.iter().find(|action| action.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn x_deletes_a_whole_character() {
+        let mut editor = editor_with("é");
+        editor.type_keys("hx");
+        assert_eq!(editor.text(), "\n");
+
+        let mut editor = editor_with("aéb");
+        editor.type_keys("lx");
+        assert_eq!(editor.text(), "ab\n");
+
+        // a cursor left inside a character deletes all of it
+        let mut editor = editor_with("aéb");
+        editor.cursor_x = 2;
+        editor.type_keys("x");
+        assert_eq!(editor.text(), "ab\n");
+        assert_eq!(editor.cursor_x, 1);
+    }
+}
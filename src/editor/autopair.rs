@@ -0,0 +1,143 @@
+use crate::*;
+
+/// Pairs for buffers whose grammar doesn't list its own.
+pub const DEFAULT_PAIRS: &[(char, char)] =
+    &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+/// Reads a grammar's `pairs`, written as openers followed by their closers
+/// like `()[]""`.
+pub fn parse_pairs(pairs: &str) -> Result<Vec<(char, char)>, String> {
+    let chars: Vec<char> = pairs.chars().collect();
+    if !chars.len().is_multiple_of(2) {
+        return Err(format!(
+            "pairs '{}' has a character without a partner",
+            pairs
+        ));
+    }
+    Ok(chars.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
+impl Editor {
+    /// The pairs auto-paired in this buffer.
+    pub fn pairs(&self) -> &[(char, char)] {
+        match self.highlighter {
+            Some(ref highlighter) => &highlighter.grammar.pairs,
+            None => DEFAULT_PAIRS,
+        }
+    }
+
+    /// The characters either side of the insert mode cursor, taking a
+    /// cursor inside a character to be before it.
+    fn chars_around_cursor(&self) -> (Option<char>, Option<char>) {
+        let line = &self.content[self.cursor_y];
+        let x = line.floor_char_boundary(self.cursor_x);
+        (line[..x].chars().next_back(), line[x..].chars().next())
+    }
+
+    /// The pair the cursor sits in the middle of, as in `(|)`.
+    pub fn empty_pair(&self) -> Option<(char, char)> {
+        if !self.config.autopairs {
+            return None;
+        }
+        let (Some(before), Some(after)) = self.chars_around_cursor() else {
+            return None;
+        };
+        self.pairs()
+            .iter()
+            .find(|&&pair| pair == (before, after))
+            .copied()
+    }
+
+    /// Whether text typed at the cursor lands in a comment, or between the
+    /// quotes of a string.
+    fn typing_in_string_or_comment(&mut self) -> bool {
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        let Some(before) = self.content[y][..x].chars().next_back() else {
+            return false;
+        };
+        match self.scope_at(y, x - before.len_utf8()) {
+            Some("comment") => true,
+            Some("string") => self.scope_at(y, x) == Some("string"),
+            _ => false,
+        }
+    }
+
+    /// Handles `c` typed in insert mode when it belongs to a pair: typing a
+    /// closer the editor inserted steps over it, and typing an opener before
+    /// whitespace or a closer inserts its closer too. Returns whether `c`
+    /// was dealt with.
+    pub fn insert_pair(&mut self, c: char) -> bool {
+        if !self.config.autopairs {
+            return false;
+        }
+        self.cursor_x = self.content[self.cursor_y].floor_char_boundary(self.cursor_x);
+        let (before, after) = self.chars_around_cursor();
+        let line = &self.content[self.cursor_y];
+        // closers are tracked by their distance from the line end, which
+        // typing in front of them doesn't change
+        let from_end = line.len() - self.cursor_x;
+        let closer = (self.cursor_y, from_end);
+        if after == Some(c) && self.auto_closers.contains(&closer) {
+            self.auto_closers.retain(|&p| p != closer);
+            self.cursor_x += c.len_utf8();
+            return true;
+        }
+
+        let Some(&(_, close)) = self.pairs().iter().find(|&&(open, _)| open == c) else {
+            return false;
+        };
+        if after.is_some_and(|a| !a.is_whitespace() && !self.pairs().iter().any(|p| p.1 == a)) {
+            return false;
+        }
+        // a quote after a word is an apostrophe, and after an odd number
+        // of quotes it closes a string
+        if c == close
+            && (before.is_some_and(is_word_char)
+                || !line[..self.cursor_x].matches(c).count().is_multiple_of(2))
+        {
+            return false;
+        }
+        if self.typing_in_string_or_comment() {
+            return false;
+        }
+
+        let mut line = self.content[self.cursor_y].clone();
+        line.insert(self.cursor_x, close);
+        line.insert(self.cursor_x, c);
+        self.set_line(self.cursor_y, line);
+        self.cursor_x += c.len_utf8();
+        self.auto_closers
+            .push((self.cursor_y, from_end + close.len_utf8()));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn pairs_next_to_multibyte_characters() {
+        let mut editor = editor_with("");
+        editor.type_keys("ié<Left>x<Esc>");
+        assert_eq!(editor.text(), "xé\n");
+
+        let mut editor = editor_with("");
+        editor.type_keys("ié(ö)ü");
+        assert_eq!(editor.text(), "é(ö)ü\n");
+        editor.type_keys("<Left>(");
+        assert_eq!(editor.text(), "é(ö)(ü\n");
+        editor.type_keys("<End> (<BS>");
+        assert_eq!(editor.text(), "é(ö)(ü \n");
+    }
+
+    #[test]
+    fn cursor_inside_a_character_counts_as_before_it() {
+        let mut editor = editor_with("ö");
+        editor.type_keys("i");
+        editor.cursor_x = 1;
+        editor.type_keys("[");
+        assert_eq!(editor.text(), "[ö\n");
+        assert_eq!(editor.cursor_x, 1);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::*;
+
+/// The bracket pairs `%` jumps between.
+pub const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+fn is_bracket(c: char) -> bool {
+    BRACKETS
+        .iter()
+        .any(|&(open, close)| c == open || c == close)
+}
+
+impl Editor {
+    /// Whether byte `x` of line `y` is highlighted as a string or comment.
+    fn in_string_or_comment(&mut self, y: usize, x: usize) -> bool {
+        matches!(self.scope_at(y, x), Some("string" | "comment"))
+    }
+
+    /// The bracket matching the one at `pos`, searching at most `max_lines`
+    /// lines away. Brackets in code only match brackets in code, and those
+    /// in strings or comments only match each other.
+    pub fn matching_bracket(&mut self, pos: Pos, max_lines: usize) -> Option<Pos> {
+        let c = self.content[pos.0][pos.1..].chars().next()?;
+        let (&(open, close), forward) = BRACKETS
+            .iter()
+            .find(|&&(open, close)| c == open || c == close)
+            .map(|pair| (pair, c == pair.0))?;
+        let quoted = self.in_string_or_comment(pos.0, pos.1);
+        let lines: Vec<usize> = if forward {
+            (pos.0..self.content.len())
+                .take(max_lines.saturating_add(1))
+                .collect()
+        } else {
+            (0..=pos.0)
+                .rev()
+                .take(max_lines.saturating_add(1))
+                .collect()
+        };
+
+        let mut depth = 0;
+        for y in lines {
+            let mut candidates: Vec<(usize, char)> = self.content[y]
+                .char_indices()
+                .filter(|&(_, c)| c == open || c == close)
+                .filter(|&(x, _)| match (y == pos.0, forward) {
+                    (false, _) => true,
+                    (true, true) => x >= pos.1,
+                    (true, false) => x <= pos.1,
+                })
+                .collect();
+            if !forward {
+                candidates.reverse();
+            }
+            for (x, c) in candidates {
+                if self.in_string_or_comment(y, x) != quoted {
+                    continue;
+                }
+                if (c == open) == forward {
+                    depth += 1;
+                } else {
+                    depth -= 1;
+                }
+                if depth == 0 {
+                    return Some((y, x));
+                }
+            }
+        }
+        None
+    }
+
+    /// Where `%` goes: the match of the first bracket at or after the
+    /// cursor on its line.
+    pub fn bracket_target(&mut self) -> Option<Pos> {
+        let line = &self.content[self.cursor_y];
+        // a cursor past the end, as `$` leaves it, is on the last character
+        let start = match self.cursor_x < line.len() {
+            true => self.cursor_x,
+            false => line.floor_char_boundary(line.len().saturating_sub(1)),
+        };
+        let x = start + line[start..].find(is_bracket)?;
+        self.matching_bracket((self.cursor_y, x), usize::MAX)
+    }
+
+    pub fn jump_to_bracket(&mut self) {
+        match self.bracket_target() {
+            Some((y, x)) => {
+                self.push_jump();
+                (self.cursor_y, self.cursor_x) = (y, x);
+            }
+            None => self.fail("No matching bracket"),
+        }
+    }
+
+    /// The bracket to highlight as matching the one under the cursor, or in
+    /// insert mode the one just before it, looking no further than a screen
+    /// away.
+    pub fn highlighted_bracket(&mut self) -> Option<Pos> {
+        if !matches!(self.mode, Mode::Normal | Mode::Visual | Mode::Insert) {
+            return None;
+        }
+        let line = &self.content[self.cursor_y];
+        let under = line[self.cursor_x..]
+            .chars()
+            .next()
+            .filter(|&c| is_bracket(c));
+        let x = match under {
+            Some(_) => self.cursor_x,
+            None if self.mode == Mode::Insert => {
+                let before = line[..self.cursor_x].chars().next_back()?;
+                if !is_bracket(before) {
+                    return None;
+                }
+                self.cursor_x - before.len_utf8()
+            }
+            None => return None,
+        };
+        self.matching_bracket((self.cursor_y, x), self.screen_rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    fn after(text: &str, keys: &str) -> String {
+        let mut editor = editor_with(text);
+        editor.type_keys(keys);
+        editor.text()
+    }
+
+    #[test]
+    fn percent_is_an_inclusive_motion() {
+        assert_eq!(after("f(a, (b)) c", "d%"), " c\n");
+        assert_eq!(after("f(a, (b)) c", "$hhhd%"), "f c\n");
+        assert_eq!(after("x [a] y", "c%z<Esc>"), "z y\n");
+        assert_eq!(after("(a b) c", "gU%"), "(A B) c\n");
+        // no bracket to match leaves the text alone
+        assert_eq!(after("a (b", "d%"), "a (b\n");
+    }
+
+    #[test]
+    fn percent_with_linewise_operators() {
+        let text = "fn f() {\nx\n}\ny";
+        assert_eq!(after(text, "$>%"), "    fn f() {\n    x\n    }\ny\n");
+        assert_eq!(after(text, "$=%"), "fn f() {\n    x\n}\ny\n");
+        assert_eq!(after("    a {\n    }", "$<lt>%"), "a {\n}\n");
+
+        let mut editor = editor_with(text);
+        editor.type_keys("f{y%");
+        assert_eq!(editor.registers[&'"'], "{\nx\n}");
+    }
+}
@@ -0,0 +1,332 @@
+use crate::*;
+use std::fs;
+use std::path::PathBuf;
+
+/// Most commands the `:` history keeps.
+const HISTORY_SIZE: usize = 200;
+
+/// Tab completion in progress on the command line.
+pub struct CommandCompletion {
+    /// Where the completed word starts in the command line.
+    start: usize,
+    /// The word as typed, which cycling past the last candidate goes back to.
+    typed: String,
+    candidates: Vec<String>,
+    /// The candidate shown, or `None` for the typed word.
+    index: Option<usize>,
+}
+
+/// Where the command history is kept: `$XDG_STATE_HOME/eep/history`,
+/// falling back to `~/.local/state/eep/history`.
+fn history_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/state"),
+    };
+    Some(base.join("eep").join("history"))
+}
+
+/// Paths starting with `word`, with a `/` after directories. Hidden files
+/// are left out unless `word` names them.
+fn complete_path(word: &str) -> Vec<String> {
+    let (dir, base) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let Ok(entries) = fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(base) || (name.starts_with('.') && !base.starts_with('.')) {
+                return None;
+            }
+            let slash = match entry.path().is_dir() {
+                true => "/",
+                false => "",
+            };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect()
+}
+
+impl Editor {
+    /// Reads the command history saved by earlier sessions.
+    pub fn load_command_history(&mut self) {
+        if let Some(source) = history_path().and_then(|path| fs::read_to_string(path).ok()) {
+            self.command_history = source.lines().map(String::from).collect();
+        }
+    }
+
+    /// Adds a command that was run to the history, moving it to the end if
+    /// it is already there, and saves the history.
+    fn push_history(&mut self, cmd: &str) {
+        self.command_history.retain(|old| old != cmd);
+        self.command_history.push(cmd.to_string());
+        let excess = self.command_history.len().saturating_sub(HISTORY_SIZE);
+        self.command_history.drain(..excess);
+        // the history is a convenience, so failing to save it isn't reported
+        if let Some(path) = history_path() {
+            let _ = path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::write(&path, self.command_history.join("\n") + "\n"));
+        }
+    }
+
+    /// Opens the `:` command line.
+    pub fn open_command_line(&mut self) {
+        self.mode = Mode::Command;
+        self.show_command = true;
+        self.command_buffer.clear();
+        self.command_cursor = 0;
+        self.history_index = None;
+        self.command_completion = None;
+    }
+
+    /// Runs the command line, remembering it in the history.
+    pub fn run_command_line(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let cmd = self.command_buffer.trim().to_string();
+        if !cmd.is_empty() {
+            self.push_history(&cmd);
+        }
+        self.process_command()
+    }
+
+    /// Replaces the command line, putting the cursor at `cursor`.
+    fn set_command_line(&mut self, text: String, cursor: usize) {
+        self.command_buffer = text;
+        self.command_cursor = cursor;
+        self.command_completion = None;
+    }
+
+    /// Replaces bytes `range` of the command line with `text`, leaving the
+    /// cursor after it.
+    fn edit_command_line(&mut self, range: std::ops::Range<usize>, text: &str) {
+        let mut line = self.command_buffer.clone();
+        line.replace_range(range.clone(), text);
+        self.set_command_line(line, range.start + text.len());
+        self.history_index = None;
+    }
+
+    pub fn command_insert(&mut self, c: char) {
+        let x = self.command_cursor;
+        self.edit_command_line(x..x, c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Backspace on the command line. On an empty one it closes it.
+    pub fn command_backspace(&mut self) {
+        let x = self.command_cursor;
+        if self.command_buffer.is_empty() {
+            self.show_command = false;
+            self.mode = self.resting_mode();
+            return;
+        }
+        if x > 0 {
+            let start = self.command_buffer.floor_char_boundary(x - 1);
+            self.edit_command_line(start..x, "");
+        }
+    }
+
+    pub fn command_delete(&mut self) {
+        let x = self.command_cursor;
+        if let Some(c) = self.command_buffer[x..].chars().next() {
+            self.edit_command_line(x..x + c.len_utf8(), "");
+        }
+    }
+
+    /// `Ctrl-w` on the command line: deletes the word before the cursor.
+    pub fn command_delete_word(&mut self) {
+        let x = self.command_cursor;
+        let before = self.command_buffer[..x].trim_end();
+        let start = match before.chars().next_back() {
+            Some(c) if is_word_char(c) => before
+                .char_indices()
+                .rfind(|&(_, c)| !is_word_char(c))
+                .map_or(0, |(i, c)| i + c.len_utf8()),
+            Some(c) => before.len() - c.len_utf8(),
+            None => 0,
+        };
+        self.edit_command_line(start..x, "");
+    }
+
+    /// `Ctrl-u` on the command line: deletes everything before the cursor.
+    pub fn command_delete_to_start(&mut self) {
+        self.edit_command_line(0..self.command_cursor, "");
+    }
+
+    pub fn command_cursor_left(&mut self) {
+        let x = self.command_cursor;
+        if x > 0 {
+            self.command_cursor = self.command_buffer.floor_char_boundary(x - 1);
+        }
+    }
+
+    pub fn command_cursor_right(&mut self) {
+        let x = self.command_cursor;
+        if let Some(c) = self.command_buffer[x..].chars().next() {
+            self.command_cursor = x + c.len_utf8();
+        }
+    }
+
+    /// `Shift-Left` and `Shift-Right`: moves the command line cursor by
+    /// whitespace separated words.
+    pub fn command_word_motion(&mut self, forward: bool) {
+        let line = &self.command_buffer;
+        let x = self.command_cursor;
+        self.command_cursor = match forward {
+            true => {
+                let rest = &line[x..];
+                let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                let blanks = rest[word_end..].len() - rest[word_end..].trim_start().len();
+                x + word_end + blanks
+            }
+            false => line[..x]
+                .trim_end()
+                .rfind(char::is_whitespace)
+                .map_or(0, |i| i + 1),
+        };
+    }
+
+    pub fn command_cursor_home(&mut self) {
+        self.command_cursor = 0;
+    }
+
+    pub fn command_cursor_end(&mut self) {
+        self.command_cursor = self.command_buffer.len();
+    }
+
+    /// `Up` and `Down` on the command line: steps through earlier commands
+    /// that start with what was typed before browsing.
+    pub fn command_history_step(&mut self, older: bool) {
+        let index = match self.history_index {
+            Some(index) => index,
+            None => {
+                self.history_prefix = self.command_buffer.clone();
+                self.command_history.len()
+            }
+        };
+        let prefix = self.history_prefix.as_str();
+        let matches = |i: &usize| self.command_history[*i].starts_with(prefix);
+        let found = match older {
+            true => (0..index).rev().find(matches),
+            false => (index + 1..self.command_history.len()).find(matches),
+        };
+        match found {
+            Some(i) => {
+                let text = self.command_history[i].clone();
+                let end = text.len();
+                self.set_command_line(text, end);
+                self.history_index = Some(i);
+            }
+            // past the newest match is what was typed
+            None if !older && self.history_index.is_some() => {
+                let text = self.history_prefix.clone();
+                let end = text.len();
+                self.set_command_line(text, end);
+                self.history_index = None;
+            }
+            None => {}
+        }
+    }
+
+    /// Where the word ending at the command line cursor starts and what it
+    /// could complete to: a command name, or an argument of the command
+    /// before it.
+    fn command_candidates(&self) -> (usize, Vec<String>) {
+        let line = &self.command_buffer[..self.command_cursor];
+        let mut start = line.rfind(' ').map_or(0, |i| i + 1);
+        let before = skip_ex_range(line[..start].trim_start_matches([' ', ':']));
+        let complete = match before.split(|c: char| !c.is_ascii_alphabetic()).next() {
+            // a command name, after any range typed in front of it
+            Some("") | None => {
+                start = line.len() - skip_ex_range(&line[start..]).len();
+                None
+            }
+            Some(name) => match find_ex_command(name) {
+                Some(command) => Some(command.complete),
+                None => return (start, Vec::new()),
+            },
+        };
+        let word = &line[start..];
+        let mut candidates: Vec<String> = match complete {
+            None => EX_COMMANDS
+                .iter()
+                .map(|command| command.name.to_string())
+                .collect(),
+            Some(ExCompletion::File) => return (start, sorted(complete_path(word))),
+            Some(ExCompletion::Option) => OPTIONS.iter().map(|name| name.to_string()).collect(),
+            Some(ExCompletion::Colorscheme) => Theme::available(),
+            Some(ExCompletion::Syntax) => self
+                .grammars
+                .iter()
+                .map(|grammar| grammar.name.clone())
+                .chain(["off".to_string()])
+                .collect(),
+            Some(ExCompletion::None) => Vec::new(),
+        };
+        candidates.retain(|candidate| candidate.starts_with(word));
+        (start, sorted(candidates))
+    }
+
+    /// `Tab` and `Shift-Tab` on the command line: completes the word before
+    /// the cursor, then cycles through the other completions.
+    pub fn complete_command(&mut self, forward: bool) {
+        let mut completion = match self.command_completion.take() {
+            Some(completion) => completion,
+            None => {
+                let (start, candidates) = self.command_candidates();
+                if candidates.is_empty() {
+                    return;
+                }
+                CommandCompletion {
+                    start,
+                    typed: self.command_buffer[start..self.command_cursor].to_string(),
+                    candidates,
+                    index: None,
+                }
+            }
+        };
+        let len = completion.candidates.len();
+        completion.index = match (completion.index, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(len - 1),
+            (Some(i), true) if i + 1 < len => Some(i + 1),
+            (Some(i), false) if i > 0 => Some(i - 1),
+            (Some(_), _) => None,
+        };
+        let text = match completion.index {
+            Some(i) => completion.candidates[i].clone(),
+            None => completion.typed.clone(),
+        };
+        let start = completion.start;
+        self.edit_command_line(start..self.command_cursor, &text);
+        // a single match is done with, so Tab can go on to the next word
+        if len > 1 {
+            self.command_completion = Some(completion);
+        }
+    }
+
+    /// `:history`, and `q:` in normal mode: lists the command history,
+    /// newest last.
+    pub fn list_command_history(&mut self) {
+        let lines: Vec<String> = self
+            .command_history
+            .iter()
+            .enumerate()
+            .map(|(i, cmd)| format!("{:>4}  {}", i + 1, cmd))
+            .collect();
+        match lines.is_empty() {
+            true => self.set_status("No command history"),
+            false => self.show_lines(lines),
+        }
+    }
+}
+
+fn sorted(mut candidates: Vec<String>) -> Vec<String> {
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
@@ -1,46 +1,545 @@
 use crate::*;
-use crossterm::{
-    cursor::Show,
-    event::DisableMouseCapture,
-    execute,
-    terminal::{disable_raw_mode, LeaveAlternateScreen},
-};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-impl Editor {
-    pub fn process_command(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let cmd = self.command_buffer.trim();
-        match cmd {
-            "q" => {
-                disable_raw_mode()?;
-                execute!(self.stdout, LeaveAlternateScreen, DisableMouseCapture, Show)?;
-                std::process::exit(0);
+/// The ex commands. Abbreviations match the first command they fit, so a
+/// command that shares a prefix with another is ordered by which one the
+/// short form should mean.
+pub const EX_COMMANDS: &[ExCommand] = &[
+    ExCommand {
+        name: "write",
+        abbrev: 1,
+        range: false,
+        bang: true,
+        bar: true,
+        complete: ExCompletion::File,
+        run: |editor, call| match call.arg.strip_prefix('!') {
+            Some(cmd) => editor.ex_write_shell(cmd.trim()),
+            None => editor.write_to(&call.arg),
+        },
+    },
+    ExCommand {
+        name: "wq",
+        abbrev: 2,
+        range: false,
+        bang: true,
+        bar: true,
+        complete: ExCompletion::File,
+        run: |editor, call| {
+            editor.write_to(&call.arg)?;
+            editor.should_quit = true;
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "quit",
+        abbrev: 1,
+        range: false,
+        bang: true,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| {
+            if editor.modified() && !call.bang {
+                return Err(ExError::Failed(
+                    "No write since last change (add ! to override)".into(),
+                ));
             }
-            "w" => {
-                if let Err(e) = self.save_file() {
-                    self.set_status(&format!("Error saving file: {}", e));
-                }
+            editor.should_quit = true;
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "edit",
+        abbrev: 1,
+        range: false,
+        bang: true,
+        bar: true,
+        complete: ExCompletion::File,
+        run: |editor, call| {
+            if call.arg.is_empty() {
+                return Err(ExError::ArgumentRequired);
             }
-            "wq" => {
-                if let Err(e) = self.save_file() {
-                    self.set_status(&format!("Error saving file: {}", e));
-                } else {
-                    disable_raw_mode()?;
-                    execute!(self.stdout, LeaveAlternateScreen, DisableMouseCapture, Show)?;
-                    std::process::exit(0);
-                }
+            if editor.modified() && !call.bang {
+                return Err(ExError::Failed(
+                    "No write since last change (add ! to override)".into(),
+                ));
             }
-            _ if cmd.starts_with("w ") => {
-                let filename = cmd[2..].trim();
-                self.filename = Some(PathBuf::from(filename));
-                if let Err(e) = self.save_file() {
-                    self.set_status(&format!("Error saving file: {}", e));
-                }
+            editor
+                .open_file(&call.arg)
+                .map_err(|e| ExError::Failed(format!("Error opening file: {}", e)))
+        },
+    },
+    ExCommand {
+        name: "set",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::Option,
+        run: |editor, call| Ok(editor.set_option(&call.arg)?),
+    },
+    ExCommand {
+        name: "colorscheme",
+        abbrev: 4,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::Colorscheme,
+        run: |editor, call| {
+            if call.arg.is_empty() {
+                let msg = format!(
+                    "{} (available: {})",
+                    editor.theme.name,
+                    Theme::available().join(", ")
+                );
+                editor.set_status(&msg);
+                return Ok(());
             }
-            _ => self.set_status(&format!("Unknown command: {}", cmd)),
-        }
+            Ok(editor.set_colorscheme(&call.arg)?)
+        },
+    },
+    ExCommand {
+        name: "syntax",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::Syntax,
+        run: |editor, call| {
+            if call.arg.is_empty() {
+                let msg = match editor.highlighter {
+                    Some(ref highlighter) => format!("syntax={}", highlighter.grammar.name),
+                    None => "syntax=off".to_string(),
+                };
+                editor.set_status(&msg);
+                return Ok(());
+            }
+            Ok(editor.set_syntax(&call.arg)?)
+        },
+    },
+    ExCommand {
+        name: "hex",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            editor.toggle_hex();
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "let",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| Ok(editor.set_register(&call.arg)?),
+    },
+    ExCommand {
+        name: "registers",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            editor.list_registers();
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "marks",
+        abbrev: 5,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            editor.list_marks();
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "history",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            editor.list_command_history();
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "lsp",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            let msg = match editor.lsp {
+                Some(ref client) => format!("{} ({})", client.command, client.language),
+                None => "No language server for this buffer".to_string(),
+            };
+            editor.set_status(&msg);
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "codeaction",
+        abbrev: 5,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| {
+            editor.lsp_code_action(&call.arg);
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "rename",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| {
+            editor.lsp_rename(&call.arg);
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "diagnostics",
+        abbrev: 4,
+        range: false,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, _| {
+            editor.list_diagnostics();
+            Ok(())
+        },
+    },
+    ExCommand {
+        name: "delete",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_delete(call),
+    },
+    ExCommand {
+        name: "yank",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_yank(call),
+    },
+    ExCommand {
+        name: "move",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_move(call),
+    },
+    ExCommand {
+        name: "copy",
+        abbrev: 2,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_copy(call),
+    },
+    ExCommand {
+        name: "t",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_copy(call),
+    },
+    ExCommand {
+        name: ">",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_shift(call, true),
+    },
+    ExCommand {
+        name: "<",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_shift(call, false),
+    },
+    ExCommand {
+        name: "sort",
+        abbrev: 3,
+        range: true,
+        bang: true,
+        bar: true,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_sort(call),
+    },
+    // these take commands or keys, `|` included
+    ExCommand {
+        name: "normal",
+        abbrev: 4,
+        range: true,
+        bang: true,
+        bar: false,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_normal(call),
+    },
+    ExCommand {
+        name: "global",
+        abbrev: 1,
+        range: true,
+        bang: true,
+        bar: false,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_global(call, false),
+    },
+    ExCommand {
+        name: "vglobal",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: |editor, call| editor.ex_global(call, true),
+    },
+    ExCommand {
+        name: "read",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::File,
+        run: |editor, call| editor.ex_read(call),
+    },
+    ExCommand {
+        name: "!",
+        abbrev: 1,
+        range: true,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::File,
+        run: |editor, call| editor.ex_shell(call),
+    },
+    // mappings take `|` as part of the keys
+    ExCommand {
+        name: "map",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "nmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "imap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "cmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "vmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "xmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "noremap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "nnoremap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "inoremap",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "cnoremap",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "vnoremap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "xnoremap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "unmap",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "nunmap",
+        abbrev: 3,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "iunmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "cunmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "vunmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+    ExCommand {
+        name: "xunmap",
+        abbrev: 2,
+        range: false,
+        bang: false,
+        bar: false,
+        complete: ExCompletion::None,
+        run: run_map_command,
+    },
+];
+
+impl Editor {
+    pub fn process_command(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let cmd = self.command_buffer.trim().to_string();
         self.command_buffer.clear();
         self.show_command = false;
+        if let Err(e) = self.run_ex(&cmd) {
+            self.fail(&e.to_string());
+        }
         Ok(())
     }
+
+    /// `:w` and `:wq`: writes the buffer, to `filename` if one is given,
+    /// which becomes the buffer's file.
+    fn write_to(&mut self, filename: &str) -> Result<(), ExError> {
+        if !filename.is_empty() {
+            self.filename = Some(PathBuf::from(filename));
+            self.editorconfig = EditorConfig::for_path(Path::new(filename));
+        }
+        self.save_file()
+            .map_err(|e| ExError::Failed(format!("Error saving file: {}", e)))
+    }
+}
+
+fn run_map_command(editor: &mut Editor, call: &ExCall) -> Result<(), ExError> {
+    let (prefix, kind) = map_command_name(call.command.name);
+    Ok(editor.map_command(prefix, kind, &call.arg)?)
+}
+
+/// Splits `nmap` into `("n", "map")`.
+fn map_command_name(name: &str) -> (&str, &str) {
+    ["noremap", "unmap", "map"]
+        .iter()
+        .find_map(|kind| Some((name.strip_suffix(kind)?, *kind)))
+        .unwrap_or(("", name))
 }
@@ -0,0 +1,341 @@
+use crate::*;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// Most entries the popup shows at once.
+const POPUP_HEIGHT: usize = 10;
+const POPUP_MAX_WIDTH: usize = 50;
+/// Characters that end a file path when looking back from the cursor.
+const PATH_DELIMITERS: &[char] = &[
+    '"', '\'', '`', '(', ')', '[', ']', '{', '}', '<', '>', ',', ';', '=',
+];
+
+#[derive(Clone)]
+pub struct CompletionItem {
+    pub label: String,
+    /// Text that replaces the typed prefix when the item is accepted.
+    pub insert: String,
+    pub detail: Option<String>,
+}
+
+/// The insert mode completion popup. Typing narrows `items` down to those
+/// starting with the text between `start` and the cursor.
+pub struct Completion {
+    pub y: usize,
+    pub start: usize,
+    prefix: String,
+    items: Vec<CompletionItem>,
+    pub matches: Vec<usize>,
+    pub selected: Option<usize>,
+}
+
+impl Completion {
+    fn refilter(&mut self, prefix: &str) {
+        let lower = prefix.to_lowercase();
+        self.matches = (0..self.items.len())
+            .filter(|&i| {
+                let label = &self.items[i].insert;
+                label.starts_with(prefix) || label.to_lowercase().starts_with(&lower)
+            })
+            // the word being typed is not a candidate for itself
+            .filter(|&i| self.items[i].insert != prefix)
+            .collect();
+        self.prefix = prefix.to_string();
+        self.selected = None;
+    }
+
+    /// The selected candidate. Typing clears the selection, so none is
+    /// chosen until one is picked again.
+    pub fn current(&self) -> Option<&CompletionItem> {
+        let i = *self.matches.get(self.selected?)?;
+        Some(&self.items[i])
+    }
+}
+
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Where the whitespace or delimiter separated token ending `before` starts.
+fn token_start(before: &str) -> usize {
+    before
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace() || PATH_DELIMITERS.contains(c))
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// Words in `content`, nearest to line `y` first.
+fn buffer_words(content: &[String], y: usize) -> Vec<CompletionItem> {
+    let mut lines: Vec<usize> = (0..content.len()).collect();
+    lines.sort_by_key(|&line| line.abs_diff(y));
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for line in lines {
+        for word in content[line].split(|c: char| !is_word_char(c)) {
+            if word.len() > 1 && seen.insert(word) {
+                items.push(CompletionItem {
+                    label: word.to_string(),
+                    insert: word.to_string(),
+                    detail: None,
+                });
+            }
+        }
+    }
+    items
+}
+
+/// Entries of the directory named by `typed` up to its last `/`.
+fn path_entries(typed: &str) -> Vec<CompletionItem> {
+    let dir = &typed[..typed.rfind('/').map_or(0, |i| i + 1)];
+    let path = match dir.strip_prefix("~/") {
+        Some(rest) => std::env::var_os("HOME")
+            .map_or(PathBuf::from(dir), |home| PathBuf::from(home).join(rest)),
+        None if dir.is_empty() => PathBuf::from("."),
+        None => PathBuf::from(dir),
+    };
+    let Ok(entries) = fs::read_dir(path) else {
+        return Vec::new();
+    };
+    let mut items: Vec<CompletionItem> = entries
+        .flatten()
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            CompletionItem {
+                label: if is_dir {
+                    format!("{}/", name)
+                } else {
+                    name.clone()
+                },
+                insert: name,
+                detail: None,
+            }
+        })
+        .collect();
+    items.sort_by(|a, b| a.label.cmp(&b.label));
+    items
+}
+
+impl Editor {
+    /// Where the text being completed starts on the cursor line, and whether
+    /// it is a file path rather than a word.
+    fn completion_start(&self) -> (usize, bool) {
+        let before = &self.content[self.cursor_y][..self.cursor_x];
+        let token = token_start(before);
+        if let Some(slash) = before[token..].rfind('/') {
+            return (token + slash + 1, true);
+        }
+        let word_start = before
+            .char_indices()
+            .rfind(|(_, c)| !is_word_char(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        (word_start, false)
+    }
+
+    /// Opens the popup with candidates for the text before the cursor.
+    pub fn start_completion(&mut self) {
+        let (start, is_path) = self.completion_start();
+        let line = &self.content[self.cursor_y];
+        let prefix = line[start..self.cursor_x].to_string();
+        let items = if is_path {
+            path_entries(&line[token_start(&line[..start])..start])
+        } else {
+            buffer_words(&self.content, self.cursor_y)
+        };
+
+        let mut completion = Completion {
+            y: self.cursor_y,
+            start,
+            prefix: String::new(),
+            items,
+            matches: Vec::new(),
+            selected: None,
+        };
+        completion.refilter(&prefix);
+        self.completion = Some(completion);
+        if !is_path {
+            self.lsp_completion();
+        }
+        if self.lsp.is_none()
+            && self
+                .completion
+                .as_ref()
+                .is_some_and(|c| c.matches.is_empty())
+        {
+            self.completion = None;
+            self.set_status("No completions");
+        }
+    }
+
+    /// Adds candidates that arrived late, such as from a language server.
+    pub fn add_completion_items(&mut self, items: Vec<CompletionItem>) {
+        let Some(ref mut completion) = self.completion else {
+            return;
+        };
+        let known: HashSet<String> = completion.items.iter().map(|i| i.insert.clone()).collect();
+        let mut items: Vec<CompletionItem> = items
+            .into_iter()
+            .filter(|i| !known.contains(&i.insert))
+            .collect();
+        items.append(&mut completion.items);
+        completion.items = items;
+        let prefix = completion.prefix.clone();
+        completion.refilter(&prefix);
+    }
+
+    /// Selects the next (or with a negative `step`, previous) candidate,
+    /// opening the popup first if it isn't open.
+    pub fn cycle_completion(&mut self, step: isize) {
+        let Some(ref mut completion) = self.completion else {
+            self.start_completion();
+            if let Some(ref mut completion) = self.completion
+                && !completion.matches.is_empty()
+            {
+                let last = completion.matches.len() - 1;
+                completion.selected = Some(if step < 0 { last } else { 0 });
+            }
+            return;
+        };
+        let count = completion.matches.len() as isize;
+        if count == 0 {
+            return;
+        }
+        completion.selected = Some(match completion.selected {
+            Some(i) => (i as isize + step).rem_euclid(count) as usize,
+            None if step < 0 => count as usize - 1,
+            None => 0,
+        });
+    }
+
+    /// Replaces the typed prefix with the selected candidate. The popup is
+    /// closed either way; returns whether a candidate was inserted.
+    pub fn accept_completion(&mut self) -> bool {
+        let Some(completion) = self.completion.take() else {
+            return false;
+        };
+        let Some(item) = completion.current() else {
+            return false;
+        };
+        let mut line = self.content[self.cursor_y].clone();
+        line.replace_range(completion.start..self.cursor_x, &item.insert);
+        self.cursor_x = completion.start + item.insert.len();
+        self.set_line(self.cursor_y, line);
+        true
+    }
+
+    /// Refilters the popup after a key, closing it once the cursor has left
+    /// the text being completed or nothing matches any more.
+    pub fn update_completion(&mut self) {
+        let Some(ref mut completion) = self.completion else {
+            return;
+        };
+        let line = &self.content[self.cursor_y];
+        if self.mode != Mode::Insert
+            || self.cursor_y != completion.y
+            || self.cursor_x < completion.start
+            || !line.is_char_boundary(self.cursor_x)
+        {
+            self.completion = None;
+            return;
+        }
+        let prefix = &line[completion.start..self.cursor_x];
+        if prefix == completion.prefix {
+            return;
+        }
+        if prefix.contains(|c: char| c == '/' || c.is_whitespace()) {
+            self.completion = None;
+            return;
+        }
+        let prefix = prefix.to_string();
+        completion.refilter(&prefix);
+        if completion.matches.is_empty() {
+            self.completion = None;
+        }
+    }
+
+    /// Draws the popup under the cursor, or above it when there is no room
+    /// below. `cursor_row` and `cursor_col` are 1-based screen coordinates.
+    pub fn render_completion(&self, output: &mut String, cursor_row: usize, cursor_col: usize) {
+        let Some(ref completion) = self.completion else {
+            return;
+        };
+        if completion.matches.is_empty() {
+            return;
+        }
+        let height = std::cmp::min(POPUP_HEIGHT, completion.matches.len());
+        let selected = completion.selected.unwrap_or(0);
+        let first = (selected + 1).saturating_sub(height);
+        let entries: Vec<(String, bool)> = completion.matches[first..first + height]
+            .iter()
+            .enumerate()
+            .map(|(i, &item)| {
+                let item = &completion.items[item];
+                let text = match item.detail {
+                    Some(ref detail) => format!(" {}  {} ", item.label, detail),
+                    None => format!(" {} ", item.label),
+                };
+                (text, completion.selected == Some(first + i))
+            })
+            .collect();
+        let width = entries
+            .iter()
+            .map(|(text, _)| text.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(POPUP_MAX_WIDTH)
+            .min(self.screen_cols);
+
+        let typed = self.content[completion.y][completion.start..self.cursor_x]
+            .chars()
+            .count();
+        let col = cursor_col
+            .saturating_sub(typed)
+            .max(1)
+            .min(self.screen_cols + 1 - width);
+        let top = if cursor_row + height <= self.screen_rows {
+            cursor_row + 1
+        } else {
+            cursor_row.saturating_sub(height).max(1)
+        };
+        for (i, (text, is_selected)) in entries.into_iter().enumerate() {
+            let group = if is_selected {
+                "popup_selected"
+            } else {
+                "popup"
+            };
+            let text: String = text.chars().take(width).collect();
+            output.push_str(&format!(
+                "\x1B[{};{}H{}{:<width$}{}",
+                top + i,
+                col,
+                self.theme.sgr(group),
+                text,
+                RESET,
+                width = width
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn enter_accepts_only_a_selected_candidate() {
+        let mut editor = editor_with("format forward\n");
+        editor.type_keys("o<C-n><CR>x<Esc>");
+        assert_eq!(editor.text(), "format forward\nformatx\n");
+
+        // typing clears the selection, so Enter ends the line instead
+        let mut editor = editor_with("format forward\n");
+        editor.type_keys("of<C-n>o<CR>x<Esc>");
+        assert!(editor.completion.is_none());
+        assert_eq!(editor.text(), "format forward\nfo\nx\n");
+
+        let mut editor = editor_with("format forward\n");
+        editor.type_keys("of<C-n>o<C-n><C-n><CR><Esc>");
+        assert_eq!(editor.text(), "format forward\nforward\n");
+    }
+}
@@ -0,0 +1,390 @@
+use crate::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+/// Names accepted by `:set`, in the order `:set` lists them.
+pub const OPTIONS: &[&str] = &[
+    "tabstop",
+    "shiftwidth",
+    "softtabstop",
+    "expandtab",
+    "detect_indent",
+    "list",
+    "listchars",
+    "autoindent",
+    "smartindent",
+    "autopairs",
+    "iskeyword",
+    "line_numbers",
+    "wrap",
+    "scrolloff",
+    "mouse_scroll",
+    "key_timeout",
+    "theme",
+];
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(alias = "tab_width")]
+    pub tabstop: usize,
+    /// Columns per indent level; 0 follows `tabstop`.
+    pub shiftwidth: usize,
+    /// Columns `Tab` and `Backspace` move by in spaces; 0 turns it off.
+    pub softtabstop: usize,
+    pub expandtab: bool,
+    /// Guess `expandtab` and `shiftwidth` from the indentation of a file
+    /// when opening it.
+    pub detect_indent: bool,
+    /// Show tabs with the glyphs in `listchars`.
+    pub list: bool,
+    pub listchars: String,
+    pub autoindent: bool,
+    pub smartindent: bool,
+    /// Close brackets and quotes as they are typed.
+    pub autopairs: bool,
+    /// The characters word motions treat as part of a word.
+    pub iskeyword: String,
+    #[serde(alias = "number", alias = "nu")]
+    pub line_numbers: bool,
+    pub wrap: bool,
+    pub scrolloff: usize,
+    pub mouse_scroll: usize,
+    pub key_timeout: u64,
+    pub theme: String,
+    pub keymap: KeymapConfig,
+    /// Language servers, keyed by the name of the grammar they serve.
+    pub lsp: HashMap<String, LspServerConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tabstop: 4,
+            shiftwidth: 0,
+            softtabstop: 4,
+            expandtab: true,
+            detect_indent: true,
+            list: false,
+            listchars: "tab:> ".to_string(),
+            autoindent: true,
+            smartindent: false,
+            autopairs: true,
+            iskeyword: DEFAULT_ISKEYWORD.to_string(),
+            line_numbers: true,
+            wrap: false,
+            scrolloff: 0,
+            mouse_scroll: 3,
+            key_timeout: 1000,
+            theme: "default".to_string(),
+            keymap: KeymapConfig::default(),
+            lsp: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(message: impl Into<String>) -> Self {
+        ConfigError {
+            path: None,
+            line: None,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(ref path) = self.path {
+            write!(f, "{}:", path.display())?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// `$XDG_CONFIG_HOME/eep`, falling back to `~/.config/eep`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("eep"))
+}
+
+/// Parses TOML into `T`, pointing errors at the offending line of `path`.
+pub fn parse_toml<T: for<'de> Deserialize<'de>>(
+    source: &str,
+    path: PathBuf,
+) -> Result<T, ConfigError> {
+    toml::from_str(source).map_err(|e| ConfigError {
+        line: e
+            .span()
+            .map(|span| source[..span.start].matches('\n').count() + 1),
+        path: Some(path),
+        message: e.message().trim().to_string(),
+    })
+}
+
+impl Config {
+    /// Loads `config.toml` from the config directory. A missing file is not
+    /// an error and yields the defaults.
+    pub fn load() -> Result<Config, ConfigError> {
+        let Some(path) = config_dir().map(|dir| dir.join("config.toml")) else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(source) => parse_toml(&source, path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(ConfigError {
+                path: Some(path),
+                line: None,
+                message: e.to_string(),
+            }),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "tabstop" | "tab_width" => self.tabstop.to_string(),
+            "shiftwidth" => self.shiftwidth.to_string(),
+            "softtabstop" => self.softtabstop.to_string(),
+            "detect_indent" => self.detect_indent.to_string(),
+            "list" => self.list.to_string(),
+            "listchars" => self.listchars.clone(),
+            "expandtab" => self.expandtab.to_string(),
+            "autoindent" => self.autoindent.to_string(),
+            "smartindent" => self.smartindent.to_string(),
+            "autopairs" => self.autopairs.to_string(),
+            "iskeyword" => self.iskeyword.clone(),
+            "line_numbers" | "number" | "nu" => self.line_numbers.to_string(),
+            "wrap" => self.wrap.to_string(),
+            "scrolloff" => self.scrolloff.to_string(),
+            "mouse_scroll" => self.mouse_scroll.to_string(),
+            "key_timeout" => self.key_timeout.to_string(),
+            "theme" => self.theme.clone(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        match name {
+            "tabstop" | "tab_width" => self.tabstop = parse_number(name, value, 1)?,
+            "shiftwidth" => self.shiftwidth = parse_number(name, value, 0)?,
+            "softtabstop" => self.softtabstop = parse_number(name, value, 0)?,
+            "detect_indent" => self.detect_indent = parse_bool(name, value)?,
+            "list" => self.list = parse_bool(name, value)?,
+            "listchars" => {
+                tab_glyphs(value)?;
+                self.listchars = value.to_string();
+            }
+            "expandtab" => self.expandtab = parse_bool(name, value)?,
+            "autoindent" => self.autoindent = parse_bool(name, value)?,
+            "smartindent" => self.smartindent = parse_bool(name, value)?,
+            "autopairs" => self.autopairs = parse_bool(name, value)?,
+            "iskeyword" => {
+                Keywords::parse(value).map_err(ConfigError::new)?;
+                self.iskeyword = value.to_string();
+            }
+            "line_numbers" | "number" | "nu" => self.line_numbers = parse_bool(name, value)?,
+            "wrap" => self.wrap = parse_bool(name, value)?,
+            "scrolloff" => self.scrolloff = parse_number(name, value, 0)?,
+            "mouse_scroll" => self.mouse_scroll = parse_number(name, value, 1)?,
+            "key_timeout" => self.key_timeout = parse_number(name, value, 0)? as u64,
+            "theme" => self.theme = value.to_string(),
+            _ => return Err(ConfigError::new(format!("Unknown option: {}", name))),
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Columns per indent level.
+    pub fn shiftwidth(&self) -> usize {
+        if self.shiftwidth == 0 {
+            self.tabstop
+        } else {
+            self.shiftwidth
+        }
+    }
+}
+
+/// The two glyphs of the `tab:` entry in `listchars`: one drawn where the
+/// tab starts and one repeated over the rest of its width.
+pub fn tab_glyphs(listchars: &str) -> Result<(char, char), ConfigError> {
+    let invalid = || ConfigError::new(format!("Invalid listchars: '{}'", listchars));
+    let Some(glyphs) = listchars
+        .split(',')
+        .find_map(|entry| entry.strip_prefix("tab:"))
+    else {
+        return Ok((' ', ' '));
+    };
+    let mut chars = glyphs.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(first), Some(fill), None) => Ok((first, fill)),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, ConfigError> {
+    match value {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => Err(ConfigError::new(format!(
+            "Invalid value for {}: expected true or false, got '{}'",
+            name, value
+        ))),
+    }
+}
+
+fn parse_number(name: &str, value: &str, min: usize) -> Result<usize, ConfigError> {
+    match value.parse::<usize>() {
+        Ok(n) if n >= min => Ok(n),
+        _ => Err(ConfigError::new(format!(
+            "Invalid value for {}: expected a number >= {}, got '{}'",
+            name, min, value
+        ))),
+    }
+}
+
+impl Editor {
+    /// Handles the argument of `:set`: `name=value`, `name`, `noname`,
+    /// `invname`, `name?`, or nothing to list every option.
+    pub fn set_option(&mut self, arg: &str) -> Result<(), ConfigError> {
+        let arg = arg.trim();
+        if arg.is_empty() {
+            let all: Vec<String> = OPTIONS
+                .iter()
+                .map(|name| format!("{}={}", name, self.config.get(name).unwrap_or_default()))
+                .collect();
+            self.set_status(&all.join(" "));
+            return Ok(());
+        }
+
+        if let Some((name, value)) = arg.split_once('=') {
+            let (name, value) = (name.trim(), value.trim());
+            if name == "theme" {
+                return self.set_colorscheme(value);
+            }
+            self.config.set(name, value)?;
+        } else if let Some(name) = arg.strip_suffix('?') {
+            let value = self
+                .config
+                .get(name)
+                .ok_or_else(|| ConfigError::new(format!("Unknown option: {}", name)))?;
+            self.set_status(&format!("{}={}", name, value));
+            return Ok(());
+        } else if let Some(name) = arg.strip_prefix("inv") {
+            let current = self.config.get(name).unwrap_or_default();
+            let flipped = if current == "true" { "false" } else { "true" };
+            self.config.set(name, flipped)?;
+        } else if let Some(name) = arg
+            .strip_prefix("no")
+            .filter(|name| self.config.get(name).is_some())
+        {
+            self.config.set(name, "false")?;
+        } else {
+            self.config.set(arg, "true")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    fn parse_error(source: &str) -> ConfigError {
+        match parse_toml::<Config>(source, PathBuf::from("config.toml")) {
+            Ok(_) => panic!("{:?} parsed", source),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn toml_errors_point_at_their_line() {
+        let e = parse_error("tabstop = 4\nbogus = 1\n");
+        assert_eq!(e.line, Some(2));
+        assert!(e
+            .to_string()
+            .starts_with("config.toml:2: unknown field `bogus`"));
+
+        let e = parse_error("# indentation\n\nexpandtab = \"yes\"\n");
+        assert_eq!(e.line, Some(3));
+        assert!(e.message.starts_with("invalid type"));
+
+        let e = parse_error("wrap = true\n[lsp.rust]\ncommand = 3\n");
+        assert_eq!(e.line, Some(3));
+    }
+
+    #[test]
+    fn toml_accepts_option_aliases() {
+        let path = PathBuf::from("config.toml");
+        let config: Config = parse_toml("tab_width = 2\nnumber = false\n", path).unwrap();
+        assert_eq!(config.tabstop, 2);
+        assert!(!config.line_numbers);
+    }
+
+    #[test]
+    fn set_takes_every_form_of_an_option() {
+        let mut editor = editor_with("");
+        editor.set_option("nonumber").unwrap();
+        assert!(!editor.config.line_numbers);
+        editor.set_option("nu").unwrap();
+        assert!(editor.config.line_numbers);
+        editor.set_option("invnumber").unwrap();
+        assert!(!editor.config.line_numbers);
+        editor.set_option("invline_numbers").unwrap();
+        assert!(editor.config.line_numbers);
+        editor.set_option("nonu").unwrap();
+        editor.set_option("number?").unwrap();
+        assert_eq!(editor.status_msg, "number=false");
+
+        editor.set_option("tab_width = 8").unwrap();
+        editor.set_option("tabstop?").unwrap();
+        assert_eq!(editor.status_msg, "tabstop=8");
+        editor.set_option("  nowrap ").unwrap();
+        assert!(!editor.config.wrap);
+        editor.set_option("").unwrap();
+        assert!(editor.status_msg.starts_with("tabstop=8 shiftwidth=0 "));
+    }
+
+    #[test]
+    fn set_reports_bad_names_and_values() {
+        let mut editor = editor_with("");
+        let error = |editor: &mut Editor, arg| editor.set_option(arg).unwrap_err().to_string();
+        assert_eq!(error(&mut editor, "bogus"), "Unknown option: bogus");
+        assert_eq!(error(&mut editor, "nobogus"), "Unknown option: nobogus");
+        assert_eq!(error(&mut editor, "bogus?"), "Unknown option: bogus");
+        assert_eq!(
+            error(&mut editor, "tabstop=0"),
+            "Invalid value for tabstop: expected a number >= 1, got '0'"
+        );
+        assert_eq!(
+            error(&mut editor, "wrap=maybe"),
+            "Invalid value for wrap: expected true or false, got 'maybe'"
+        );
+        assert_eq!(
+            error(&mut editor, "notabstop"),
+            "Invalid value for tabstop: expected a number >= 1, got 'false'"
+        );
+        assert_eq!(editor.config.tabstop, 4);
+    }
+}
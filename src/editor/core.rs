@@ -1,20 +1,28 @@
+use crate::*;
 use crossterm::{
     cursor::{SetCursorStyle, Show},
     event::{
-        read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers,
+        poll, read, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::io::{self, stdout};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Mode {
     Normal,
     Insert,
     Command,
+    Hex,
+    Visual,
+    /// Insert mode typing over the text, with insert mode's keys.
+    Replace,
 }
 
 pub struct Editor {
@@ -29,9 +37,90 @@ pub struct Editor {
     pub screen_rows: usize,
     pub screen_cols: usize,
     pub command_buffer: String,
+    /// Byte offset of the cursor in `command_buffer`.
+    pub command_cursor: usize,
     pub show_command: bool,
-    pub tabbed: bool,
-    pub show_line_numbers: bool,
+    /// Commands run from the command line, oldest first.
+    pub command_history: Vec<String>,
+    /// The history entry shown while browsing with `Up` and `Down`.
+    pub history_index: Option<usize>,
+    /// What was typed before browsing, which entries have to start with.
+    pub history_prefix: String,
+    pub command_completion: Option<CommandCompletion>,
+    pub config: Config,
+    pub theme: Theme,
+    pub hex: Option<HexView>,
+    pub keymaps: Keymaps,
+    pub pending_keys: Vec<QueuedKey>,
+    pub input_queue: VecDeque<QueuedKey>,
+    pub map_depth: usize,
+    pub pager: Option<Pager>,
+    pub grammars: Vec<Rc<Grammar>>,
+    pub highlighter: Option<Highlighter>,
+    #[cfg(feature = "treesitter")]
+    pub syntax_tree: Option<SyntaxTree>,
+    pub visual_anchor: (usize, usize),
+    pub lsp: Option<LspClient>,
+    pub completion: Option<Completion>,
+    /// The count typed before a normal mode command, like the 42 in `42G`.
+    pub count: Option<usize>,
+    /// Positions to go back to with `Ctrl-o`, oldest first.
+    pub jumps: Vec<Pos>,
+    /// Where `Ctrl-o` and `Ctrl-i` are in `jumps`; its length when not
+    /// moving through it.
+    pub jump_index: usize,
+    /// Marks in the current file, including the special `.`, `^` and `'`.
+    pub marks: HashMap<char, Pos>,
+    /// The marks of files opened before, by absolute path, kept for when
+    /// they are opened again.
+    pub file_marks: HashMap<PathBuf, HashMap<char, Pos>>,
+    /// Uppercase marks, which remember their file.
+    pub global_marks: HashMap<char, (PathBuf, Pos)>,
+    /// Set by `Ctrl-o` in insert mode while running one normal mode
+    /// command.
+    pub insert_oneshot: bool,
+    /// An operator like `gU` waiting for its motion.
+    pub pending_operator: Option<PendingOperator>,
+    /// The characters replace mode typed over, `None` where it added one,
+    /// for backspace to restore.
+    pub replaced: Vec<Option<char>>,
+    /// The last `f`, `F`, `t` or `T`, for `;` and `,`.
+    pub last_find: Option<(char, Find)>,
+    /// Register contents, as key notation for macros.
+    pub registers: HashMap<char, String>,
+    /// The register being recorded into and the keys typed so far.
+    pub recording: Option<(char, Vec<Key>)>,
+    pub last_macro: Option<char>,
+    pub macro_depth: usize,
+    /// Counts every edit to the buffer, so a command can tell whether it
+    /// changed anything.
+    pub changes: usize,
+    /// `changes` when the file was last opened or saved.
+    pub saved_changes: usize,
+    /// The keys of the command being handled, for `.`.
+    pub change_keys: Vec<QueuedKey>,
+    /// `changes` and the count when that command started.
+    pub change_start: usize,
+    pub change_count: Option<usize>,
+    /// Cleared when the command went through command mode.
+    pub change_repeatable: bool,
+    /// The count given to a command that started insert mode, where the
+    /// inserted keys start in `change_keys`, and whether each repeat goes
+    /// on a new line as for `o`.
+    pub insert_repeat: Option<(usize, usize, bool)>,
+    /// What `.` repeats.
+    pub last_change: Option<Change>,
+    /// A command waiting for a character argument, like the mark name
+    /// after `m`.
+    pub awaiting_char: Option<fn(&mut Editor, char)>,
+    /// Closers inserted by autopairs, as (line, bytes from the line end).
+    pub auto_closers: Vec<Pos>,
+    pub editorconfig: EditorConfig,
+    /// Whether the file ended with a newline when it was opened.
+    pub final_newline: bool,
+    /// Lines `:global` has still to run its command on.
+    pub global_lines: Option<Vec<usize>>,
+    pub should_quit: bool,
     pub stdout: io::Stdout,
 }
 
@@ -43,7 +132,8 @@ impl Default for Editor {
 
 impl Editor {
     pub fn new() -> Self {
-        let (cols, rows) = crossterm::terminal::size().unwrap();
+        // without a terminal, as in tests, assume a common size
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
         Editor {
             content: vec![String::new()],
             cursor_x: 0,
@@ -56,31 +146,184 @@ impl Editor {
             screen_rows: rows as usize - 2,
             screen_cols: cols as usize,
             command_buffer: String::new(),
+            command_cursor: 0,
             show_command: false,
-            tabbed: false,
-            show_line_numbers: true,
+            command_history: Vec::new(),
+            history_index: None,
+            history_prefix: String::new(),
+            command_completion: None,
+            config: Config::default(),
+            theme: Theme::default(),
+            hex: None,
+            keymaps: Keymaps::default(),
+            pending_keys: Vec::new(),
+            input_queue: VecDeque::new(),
+            map_depth: 0,
+            pager: None,
+            grammars: Grammar::builtin(),
+            highlighter: None,
+            #[cfg(feature = "treesitter")]
+            syntax_tree: None,
+            visual_anchor: (0, 0),
+            lsp: None,
+            completion: None,
+            count: None,
+            jumps: Vec::new(),
+            jump_index: 0,
+            marks: HashMap::new(),
+            file_marks: HashMap::new(),
+            global_marks: HashMap::new(),
+            insert_oneshot: false,
+            pending_operator: None,
+            replaced: Vec::new(),
+            last_find: None,
+            registers: HashMap::new(),
+            recording: None,
+            last_macro: None,
+            macro_depth: 0,
+            changes: 0,
+            saved_changes: 0,
+            change_keys: Vec::new(),
+            change_start: 0,
+            change_count: None,
+            change_repeatable: true,
+            last_change: None,
+            insert_repeat: None,
+            awaiting_char: None,
+            auto_closers: Vec::new(),
+            editorconfig: EditorConfig::default(),
+            final_newline: true,
+            global_lines: None,
+            should_quit: false,
             stdout: stdout(),
         }
     }
 
     pub fn open_file(&mut self, filename: &str) -> io::Result<()> {
-        let content = fs::read_to_string(filename)?;
+        let bytes = fs::read(filename)?;
+        self.stash_marks();
+        self.editorconfig = EditorConfig::for_path(Path::new(filename));
+        let charset = self.editorconfig.charset.unwrap_or(Charset::Utf8);
+        match charset.decode(bytes) {
+            Ok(mut content) => {
+                self.final_newline = content.is_empty() || content.ends_with(['\n', '\r']);
+                // `lines` only splits on \n and \r\n
+                if self.editorconfig.end_of_line == Some(LineEnding::Cr) {
+                    content = content.replace('\r', "\n");
+                }
+                self.set_content(&content);
+                self.hex = None;
+                self.mode = Mode::Normal;
+            }
+            Err(bytes) => {
+                self.set_content("");
+                self.hex = Some(HexView::new(bytes));
+                self.mode = Mode::Hex;
+                self.set_status("Binary file, opened in hex mode");
+            }
+        }
+        self.filename = Some(PathBuf::from(filename));
+        self.jumps.clear();
+        self.jump_index = 0;
+        self.restore_marks();
+        if self.config.detect_indent {
+            self.detect_indent();
+        }
+        self.apply_editorconfig();
+        self.detect_syntax();
+        self.start_lsp();
+        self.saved_changes = self.changes;
+        Ok(())
+    }
+
+    /// Whether the buffer has changed since it was opened or saved.
+    pub fn modified(&self) -> bool {
+        self.changes != self.saved_changes
+    }
+
+    /// Opens `filename` in place of the current file, as jumping to a mark
+    /// or definition in another file does, unless that would throw away
+    /// unsaved changes.
+    pub fn switch_file(&mut self, filename: &str) -> Result<(), String> {
+        if self.modified() {
+            return Err("No write since last change".to_string());
+        }
+        self.open_file(filename)
+            .map_err(|e| format!("Cannot open {}: {}", filename, e))
+    }
+
+    pub fn set_content(&mut self, content: &str) {
         self.content = content.lines().map(|s| s.to_string()).collect();
         if self.content.is_empty() {
             self.content.push(String::new());
         }
-        self.filename = Some(PathBuf::from(filename));
-        Ok(())
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.offset_x = 0;
+        self.offset_y = 0;
+        if let Some(ref mut highlighter) = self.highlighter {
+            *highlighter = Highlighter::new(highlighter.grammar.clone(), self.content.len());
+        }
+        #[cfg(feature = "treesitter")]
+        if self.syntax_tree.is_some() {
+            self.attach_syntax_tree();
+        }
+    }
+
+    /// Replaces lines `start..end` with `lines`. Edits to `content` go
+    /// through here so everything tracking the buffer can follow them.
+    pub fn replace_lines(&mut self, start: usize, end: usize, lines: Vec<String>) {
+        let inserted = lines.len();
+        #[cfg(feature = "treesitter")]
+        if let Some(ref mut tree) = self.syntax_tree {
+            tree.edit(&self.content, start, end, &lines);
+        }
+        self.content.splice(start..end, lines);
+        if let Some(ref mut highlighter) = self.highlighter {
+            highlighter.edit(start, end - start, inserted);
+        }
+        if let Some(ref mut client) = self.lsp {
+            client.did_change(&self.content, start, end, inserted);
+        }
+        self.adjust_marks(start, end, inserted);
+        self.marks.insert('.', (start, self.cursor_x));
+        self.changes += 1;
+    }
+
+    pub fn set_line(&mut self, y: usize, line: String) {
+        self.replace_lines(y, y + 1, vec![line]);
     }
 
     pub fn save_file(&mut self) -> io::Result<()> {
-        if let Some(ref filename) = self.filename {
-            let mut content = self.content.join("\n");
-            if content.chars().last().is_some_and(|c| c != '\n') {
-                content.push('\n');
+        if let Some(filename) = self.filename.clone() {
+            if let Some(ref view) = self.hex {
+                fs::write(&filename, &view.bytes)?;
+                self.status_msg = format!(
+                    "Saved '{}' ({} bytes)",
+                    filename.display(),
+                    view.bytes.len()
+                );
+                self.saved_changes = self.changes;
+                return Ok(());
+            }
+            if self.editorconfig.trim_trailing_whitespace == Some(true) {
+                self.trim_trailing_whitespace();
+            }
+            let ending = self.editorconfig.end_of_line.unwrap_or(LineEnding::Lf);
+            let mut content = self.content.join(ending.as_str());
+            // insert_final_newline = false keeps whatever the file had
+            let final_newline =
+                self.editorconfig.insert_final_newline != Some(false) || self.final_newline;
+            if final_newline && !content.is_empty() && !content.ends_with(ending.as_str()) {
+                content.push_str(ending.as_str());
             }
-            fs::write(filename, content)?;
+            let charset = self.editorconfig.charset.unwrap_or(Charset::Utf8);
+            fs::write(&filename, charset.encode(&content)?)?;
+            self.saved_changes = self.changes;
             self.status_msg = format!("Saved '{}'", filename.display());
+            if let Some(ref mut client) = self.lsp {
+                client.did_save();
+            }
         } else {
             self.set_status("No filename specified. Use :w <filename>");
         }
@@ -91,217 +334,314 @@ impl Editor {
         self.status_msg = msg.to_string();
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Reports a command that failed with `msg`, if there is one, and drops
+    /// the keys still queued, so a macro, mapping or `.` running it stops
+    /// there as in vi.
+    pub fn fail(&mut self, msg: &str) {
+        self.input_queue.clear();
+        if !msg.is_empty() {
+            self.set_status(msg);
+        }
+    }
+
+    /// The mode to fall back to after leaving command or insert mode.
+    pub fn resting_mode(&self) -> Mode {
+        if self.hex.is_some() {
+            Mode::Hex
+        } else {
+            Mode::Normal
+        }
+    }
+
+    /// Takes over the terminal: raw mode, the alternate screen and mouse
+    /// events.
+    pub fn enter_terminal(&mut self) -> io::Result<()> {
         enable_raw_mode()?;
         execute!(self.stdout, EnterAlternateScreen, EnableMouseCapture,)?;
-        execute!(self.stdout, SetCursorStyle::SteadyBlock)?;
+        execute!(self.stdout, SetCursorStyle::SteadyBlock)
+    }
 
-        self.render()?;
+    /// Gives the terminal back as it was before [`Editor::enter_terminal`].
+    pub fn leave_terminal(&mut self) -> io::Result<()> {
+        disable_raw_mode()?;
+        execute!(self.stdout, LeaveAlternateScreen, DisableMouseCapture, Show)
+    }
 
-        loop {
-            match read()? {
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('q'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => break,
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(':'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.mode = Mode::Command;
-                    self.show_command = true;
-                    self.command_buffer.clear();
-                }
+    pub fn run(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        self.enter_terminal()?;
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('i'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    execute!(self.stdout, SetCursorStyle::SteadyBar)?;
-                    self.mode = Mode::Insert;
-                }
+        self.render()?;
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc,
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) => {
-                    execute!(self.stdout, SetCursorStyle::SteadyBlock)?;
-                    if self.mode == Mode::Command {
-                        self.command_buffer.clear();
-                        self.show_command = false;
+        while !self.should_quit {
+            let key = match self.input_queue.pop_front() {
+                Some(key) => key,
+                None => {
+                    self.map_depth = 0;
+                    self.macro_depth = 0;
+                    if self.poll_lsp() {
+                        self.render()?;
+                    }
+                    let timeout = Duration::from_millis(self.config.key_timeout);
+                    if !self.pending_keys.is_empty() && !poll(timeout)? {
+                        let oneshot = self.insert_oneshot;
+                        self.flush_pending_keys()?;
+                        self.finish_key(oneshot);
+                        self.scroll();
+                        self.render()?;
+                        continue;
+                    }
+                    // wake up now and then to handle language server messages
+                    if self.lsp.is_some()
+                        && self.pending_keys.is_empty()
+                        && !poll(LSP_POLL_INTERVAL)?
+                    {
+                        continue;
+                    }
+                    match read()? {
+                        Event::Key(event) if event.kind != KeyEventKind::Release => {
+                            let key = Key::from(event);
+                            self.record_key(key);
+                            QueuedKey { key, remap: true }
+                        }
+                        Event::Mouse(event) => {
+                            self.handle_mouse_event(event);
+                            self.scroll();
+                            self.render()?;
+                            continue;
+                        }
+                        Event::Resize(..) => {
+                            self.render()?;
+                            continue;
+                        }
+                        _ => continue,
                     }
-                    self.mode = Mode::Normal;
                 }
+            };
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Backspace,
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Insert => {
-                    self.delete_char();
-                }
+            self.handle_key(key)?;
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Backspace,
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Command => {
-                    self.command_buffer.pop();
-                }
+            if self.input_queue.is_empty() {
+                self.scroll();
+                self.render()?;
+            }
+        }
+        self.stop_lsp();
+        self.leave_terminal()?;
+        Ok(())
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Insert => {
-                    self.insert_newline();
-                }
+    /// Feeds one key through the current mode's keymap, waiting for more
+    /// keys while it is the start of a longer mapping.
+    pub fn handle_key(&mut self, key: QueuedKey) -> Result<(), Box<dyn std::error::Error>> {
+        let oneshot = self.insert_oneshot;
+        let result = self.dispatch_key(key);
+        self.finish_key(oneshot);
+        result
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Enter,
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Command => {
-                    self.process_command()?;
-                    self.mode = Mode::Normal;
-                }
+    /// Whether the editor is in normal mode with no command half typed.
+    pub fn between_commands(&self) -> bool {
+        self.mode == Mode::Normal
+            && self.pending_keys.is_empty()
+            && self.awaiting_char.is_none()
+            && self.count.is_none()
+            && self.pending_operator.is_none()
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Insert => {
-                    self.insert_char(c);
-                }
+    /// Wraps up after a key: ends the command `.` records once it is
+    /// complete, and goes back to insert mode after the command `Ctrl-o`
+    /// ran. `oneshot` is whether `Ctrl-o` was used before the key.
+    fn finish_key(&mut self, oneshot: bool) {
+        if oneshot && self.between_commands() {
+            self.insert_oneshot = false;
+            self.mode = Mode::Insert;
+        }
+        self.finish_change();
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char(c),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Command => {
-                    self.command_buffer.push(c);
-                }
+    fn dispatch_key(&mut self, key: QueuedKey) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pager.is_some() {
+            self.handle_pager_key(key.key);
+            return Ok(());
+        }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('h'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.move_cursor(KeyCode::Left);
-                }
+        if let Some(command) = self.awaiting_char.take() {
+            self.record_change_key(key);
+            // anything but a character, like Esc, cancels
+            if let Some(c) = key.key.printable() {
+                command(self, c);
+            }
+            self.count = None;
+            if let Some(operator) = self.pending_operator.take() {
+                self.finish_operator(operator);
+            }
+            return Ok(());
+        }
+        if self.pending_keys.is_empty() && self.push_count_digit(key.key) {
+            // a count before a command is kept apart from its keys
+            if !self.change_keys.is_empty() {
+                self.record_change_key(key);
+            }
+            return Ok(());
+        }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('j'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.move_cursor(KeyCode::Down);
+        self.record_change_key(key);
+        self.pending_keys.push(key);
+        let keys: Vec<Key> = self.pending_keys.iter().map(|k| k.key).collect();
+        let remap = self.pending_keys.iter().all(|k| k.remap);
+        let operator_pending = self.pending_operator.is_some();
+        let result = match self
+            .keymaps
+            .lookup(self.mode, operator_pending, &keys, remap)
+        {
+            Lookup::Prefix | Lookup::Ambiguous(_) => Ok(()),
+            Lookup::Exact(binding) => {
+                let binding = binding.clone();
+                // a mapping is recorded as the keys it stands for
+                if let Binding::Keys { .. } = binding {
+                    self.unrecord_change_keys(self.pending_keys.len());
                 }
+                self.pending_keys.clear();
+                self.run_binding(binding)
+            }
+            Lookup::None => self.flush_pending_keys(),
+        };
+        self.update_completion();
+        if self.mode != Mode::Insert {
+            self.auto_closers.clear();
+        }
+        result
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('k'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.move_cursor(KeyCode::Up);
-                }
+    /// Resolves the pending keys without waiting for more: runs the longest
+    /// mapped prefix (or the first key on its own) and requeues the rest.
+    pub fn flush_pending_keys(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let pending = std::mem::take(&mut self.pending_keys);
+        let Some(first) = pending.first() else {
+            return Ok(());
+        };
+        let keys: Vec<Key> = pending.iter().map(|k| k.key).collect();
+        let remap = pending.iter().all(|k| k.remap);
+        let keymap = self.keymaps.get(self.mode);
+        let matched = (1..=keys.len())
+            .rev()
+            .find_map(|n| keymap.exact(&keys[..n], remap).map(|b| (n, b.clone())));
+
+        let handled = matched.as_ref().map_or(1, |(n, _)| *n);
+        for key in pending[handled..].iter().rev() {
+            self.input_queue.push_front(*key);
+        }
+        // requeued keys are recorded when they are handled again
+        let recorded = match matched {
+            Some((_, Binding::Keys { .. })) => 0,
+            _ => handled,
+        };
+        self.unrecord_change_keys(pending.len() - recorded);
+        match matched {
+            Some((_, binding)) => self.run_binding(binding),
+            None => {
+                self.handle_unmapped_key(first.key);
+                Ok(())
+            }
+        }
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('l'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.move_cursor(KeyCode::Right);
-                }
+    /// Adds a digit typed in normal or visual mode to the count, unless the
+    /// digit is mapped or is a `0` starting a command. Returns whether it
+    /// was taken.
+    fn push_count_digit(&mut self, key: Key) -> bool {
+        let KeyCode::Char(c @ '0'..='9') = key.code else {
+            return false;
+        };
+        if !matches!(self.mode, Mode::Normal | Mode::Visual)
+            || !key.modifiers.is_empty()
+            || (c == '0' && self.count.is_none())
+            || (c != '0' && self.keymaps.get(self.mode).exact(&[key], true).is_some())
+        {
+            return false;
+        }
+        let digit = c.to_digit(10).unwrap_or(0) as usize;
+        self.count = Some(
+            self.count
+                .unwrap_or(0)
+                .saturating_mul(10)
+                .saturating_add(digit),
+        );
+        true
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('0'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.cursor_x = 0;
+    pub fn run_binding(&mut self, binding: Binding) -> Result<(), Box<dyn std::error::Error>> {
+        match binding {
+            Binding::Action(action) => {
+                if let Some(operator) = self.pending_operator.take() {
+                    return self.run_operator_motion(operator, action);
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('$'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.cursor_x = self.content[self.cursor_y].len();
+                let mode = self.mode;
+                let before = (self.cursor_y, self.cursor_x);
+                let mut result = (action.run)(self);
+                // an action that doesn't take the count itself runs count
+                // times, stopping early once it stops doing anything
+                if let Some(count) = self.count
+                    && self.awaiting_char.is_none()
+                {
+                    if self.mode == mode {
+                        for _ in 1..count {
+                            let before = (self.cursor_y, self.cursor_x, self.changes);
+                            result = (action.run)(self);
+                            if result.is_err()
+                                || self.mode != mode
+                                || before == (self.cursor_y, self.cursor_x, self.changes)
+                            {
+                                break;
+                            }
+                        }
+                    } else if matches!(self.mode, Mode::Insert | Mode::Replace) && count > 1 {
+                        self.insert_repeat = Some((count, self.change_keys.len(), false));
+                    }
+                    self.count = None;
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('G'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.cursor_y = self.content.len() - 1;
+                if matches!(mode, Mode::Normal | Mode::Visual)
+                    && self.motion_failed(action.name, before)
+                {
+                    self.fail("");
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('g'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.cursor_y = 0;
-                    self.cursor_x = 0;
+                result
+            }
+            Binding::Keys { keys, remap } => {
+                self.map_depth += 1;
+                if self.map_depth > MAX_MAP_DEPTH {
+                    self.input_queue.clear();
+                    self.set_status("Recursive mapping");
+                    return Ok(());
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('x'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    if self.cursor_x < self.content[self.cursor_y].len() {
-                        self.content[self.cursor_y].remove(self.cursor_x);
-                    }
+                for key in keys.into_iter().rev() {
+                    self.input_queue.push_front(QueuedKey { key, remap });
                 }
+                Ok(())
+            }
+        }
+    }
 
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('d'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    if self.content.len() > 1 {
-                        self.content.remove(self.cursor_y);
-                        if self.cursor_y >= self.content.len() {
-                            self.cursor_y = self.content.len() - 1;
-                        }
-                        self.cursor_x = 0;
-                    }
+    /// Default handling for keys with no binding in the current mode.
+    fn handle_unmapped_key(&mut self, key: Key) {
+        match self.mode {
+            Mode::Insert => {
+                if let Some(c) = key.printable() {
+                    self.insert_char(c);
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('u'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.set_status("Undo not implemented yet");
+            }
+            Mode::Replace => {
+                if let Some(c) = key.printable() {
+                    self.replace_char(c);
                 }
-
-                Event::Key(KeyEvent {
-                    code: KeyCode::Char('/'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                }) if self.mode == Mode::Normal => {
-                    self.set_status("Search not implemented yet");
+            }
+            Mode::Command => {
+                if let Some(c) = key.printable() {
+                    self.command_insert(c);
                 }
-
-                Event::Key(key_event) => self.move_cursor(key_event.code),
-                Event::Mouse(event) => self.handle_mouse_event(event),
-                _ => {}
             }
-
-            self.scroll();
-            self.render()?;
+            Mode::Hex => self.handle_hex_key(KeyEvent::new(key.code, key.modifiers)),
+            Mode::Normal | Mode::Visual => self.count = None,
         }
-        disable_raw_mode()?;
-        execute!(self.stdout, LeaveAlternateScreen, DisableMouseCapture, Show)?;
-        Ok(())
     }
 }
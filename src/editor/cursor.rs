@@ -4,27 +4,31 @@ use crossterm::event::{KeyCode, MouseEvent, MouseEventKind};
 impl Editor {
     pub fn move_cursor(&mut self, direction: KeyCode) {
         match direction {
-            KeyCode::Up => {
-                if self.cursor_y > 0 {
-                    self.cursor_y -= 1;
-                }
+            // vertical moves keep the screen column across tabs
+            KeyCode::Up if self.cursor_y > 0 => {
+                let col = self.display_col(self.cursor_y, self.cursor_x);
+                self.cursor_y -= 1;
+                self.cursor_x = self.byte_at_col(self.cursor_y, col);
             }
-            KeyCode::Down => {
-                if self.cursor_y < self.content.len() - 1 {
-                    self.cursor_y += 1;
-                }
+            KeyCode::Down if self.cursor_y < self.content.len() - 1 => {
+                let col = self.display_col(self.cursor_y, self.cursor_x);
+                self.cursor_y += 1;
+                self.cursor_x = self.byte_at_col(self.cursor_y, col);
             }
+            // horizontal moves go a whole character at a time
             KeyCode::Left => {
+                let line = &self.content[self.cursor_y];
                 if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
+                    self.cursor_x = line.floor_char_boundary(self.cursor_x - 1);
                 } else if self.cursor_y > 0 {
                     self.cursor_y -= 1;
                     self.cursor_x = self.content[self.cursor_y].len();
                 }
             }
             KeyCode::Right => {
-                if self.cursor_x < self.content[self.cursor_y].len() {
-                    self.cursor_x += 1;
+                let line = &self.content[self.cursor_y];
+                if self.cursor_x < line.len() {
+                    self.cursor_x = line.ceil_char_boundary(self.cursor_x + 1);
                 } else if self.cursor_y < self.content.len() - 1 {
                     self.cursor_y += 1;
                     self.cursor_x = 0;
@@ -33,79 +37,326 @@ impl Editor {
             _ => {}
         }
 
-        let line_len = self.content[self.cursor_y].len();
-        if self.cursor_x > line_len {
-            self.cursor_x = line_len;
-        }
+        let line = &self.content[self.cursor_y];
+        self.cursor_x = line.floor_char_boundary(self.cursor_x);
     }
 
     pub fn scroll(&mut self) {
-        if self.cursor_y < self.offset_y {
-            self.offset_y = self.cursor_y;
-        } else if self.cursor_y >= self.offset_y + self.screen_rows {
-            self.offset_y = self.cursor_y - self.screen_rows + 1;
+        let scrolloff = std::cmp::min(
+            self.config.scrolloff,
+            self.screen_rows.saturating_sub(1) / 2,
+        );
+        if self.cursor_y < self.offset_y + scrolloff {
+            self.offset_y = self.cursor_y.saturating_sub(scrolloff);
+        } else if self.cursor_y + scrolloff >= self.offset_y + self.screen_rows {
+            // don't scroll past the end of the buffer just to honour scrolloff
+            let last_top = self.content.len().saturating_sub(self.screen_rows);
+            let wanted = self.cursor_y + scrolloff + 1 - self.screen_rows;
+            self.offset_y = std::cmp::max(self.offset_y, std::cmp::min(wanted, last_top));
         }
 
-        if self.cursor_x < self.offset_x {
-            self.offset_x = self.cursor_x;
-        } else if self.cursor_x >= self.offset_x + self.screen_cols {
-            self.offset_x = self.cursor_x - self.screen_cols + 1;
+        let col = self.display_col(self.cursor_y, self.cursor_x);
+        if self.config.wrap {
+            self.offset_x = 0;
+            // long lines above the cursor can still push it off screen
+            let text_width = self.text_width();
+            while self.offset_y < self.cursor_y
+                && (self.offset_y..self.cursor_y)
+                    .map(|y| self.line_height(y, text_width))
+                    .sum::<usize>()
+                    + col / text_width
+                    >= self.screen_rows
+            {
+                self.offset_y += 1;
+            }
+            return;
         }
+
+        let text_width = self.text_width();
+        if col < self.offset_x {
+            self.offset_x = col;
+        } else if col >= self.offset_x + text_width {
+            self.offset_x = col - text_width + 1;
+        }
+    }
+
+    /// Number of screen rows content line `y` occupies when wrapping.
+    pub fn line_height(&self, y: usize, text_width: usize) -> usize {
+        let width = self.display_col(y, self.content[y].len());
+        std::cmp::max(1, width.div_ceil(text_width))
+    }
+
+    /// Screen column of byte `x` of line `y`, with tabs reaching to the
+    /// next tab stop.
+    pub fn display_col(&self, y: usize, x: usize) -> usize {
+        let line = &self.content[y];
+        let tabstop = self.config.tabstop;
+        line[..line.floor_char_boundary(x)]
+            .chars()
+            .fold(0, |col, c| match c {
+                '\t' => col + tabstop - col % tabstop,
+                _ => col + 1,
+            })
+    }
+
+    /// The byte of line `y` drawn at screen column `col`, or the end of the
+    /// line if it is shorter.
+    pub fn byte_at_col(&self, y: usize, col: usize) -> usize {
+        let tabstop = self.config.tabstop;
+        let mut start = 0;
+        for (i, c) in self.content[y].char_indices() {
+            let width = match c {
+                '\t' => tabstop - start % tabstop,
+                _ => 1,
+            };
+            if start + width > col {
+                return i;
+            }
+            start += width;
+        }
+        self.content[y].len()
     }
 
     pub fn insert_char(&mut self, c: char) {
         if self.cursor_y >= self.content.len() {
-            self.content.push(String::new());
+            self.replace_lines(self.content.len(), self.content.len(), vec![String::new()]);
         }
-        if c == '\t' {
-            for _ in 0..4 {
-                self.content[self.cursor_y].insert(self.cursor_x, ' ');
-            }
-            self.tabbed = true;
-            self.cursor_x += 4;
+        if self.insert_pair(c) {
             return;
-        } else {
-            self.tabbed = false;
         }
-        self.content[self.cursor_y].insert(self.cursor_x, c);
-        self.cursor_x += 1;
+        self.dedent_for_closer(c);
+        let mut line = self.content[self.cursor_y].clone();
+        if c == '\t' && self.config.expandtab {
+            // spaces up to the next soft tab stop
+            let stop = match self.config.softtabstop {
+                0 => self.config.tabstop,
+                n => n,
+            };
+            let width = stop - self.display_col(self.cursor_y, self.cursor_x) % stop;
+            line.insert_str(self.cursor_x, &" ".repeat(width));
+            self.set_line(self.cursor_y, line);
+            self.cursor_x += width;
+            return;
+        }
+        line.insert(self.cursor_x, c);
+        self.set_line(self.cursor_y, line);
+        self.cursor_x += c.len_utf8();
     }
 
     pub fn delete_char(&mut self) {
+        if self.mode == Mode::Replace {
+            self.restore_replaced();
+            return;
+        }
         if self.cursor_x == 0 && self.cursor_y == 0 {
             return;
         }
+        if let Some((open, close)) = self.empty_pair() {
+            let mut line = self.content[self.cursor_y].clone();
+            let start = self.cursor_x - open.len_utf8();
+            line.replace_range(start..self.cursor_x + close.len_utf8(), "");
+            self.set_line(self.cursor_y, line);
+            self.cursor_x = start;
+            return;
+        }
         if self.cursor_x > 0 {
-            self.content[self.cursor_y].remove(self.cursor_x - 1);
-            self.cursor_x -= 1;
+            let mut line = self.content[self.cursor_y].clone();
+            let mut start = line.floor_char_boundary(self.cursor_x - 1);
+            // with softtabstop, spaces go back to the previous soft tab stop
+            let stop = self.config.softtabstop;
+            if stop > 0 && line[..self.cursor_x].ends_with(' ') {
+                let col = self.display_col(self.cursor_y, self.cursor_x);
+                let target = (col - 1) / stop * stop;
+                let spaces =
+                    line[..self.cursor_x].len() - line[..self.cursor_x].trim_end_matches(' ').len();
+                start = self.cursor_x - std::cmp::min(spaces, col - target);
+                line.replace_range(start..self.cursor_x, "");
+                self.set_line(self.cursor_y, line);
+                self.cursor_x = start;
+                return;
+            }
+            line.remove(start);
+            self.set_line(self.cursor_y, line);
+            self.cursor_x = start;
         } else if self.cursor_y > 0 {
-            let current_line = self.content.remove(self.cursor_y);
+            let joined = self.content[self.cursor_y - 1].clone() + &self.content[self.cursor_y];
             self.cursor_y -= 1;
             self.cursor_x = self.content[self.cursor_y].len();
-            self.content[self.cursor_y].push_str(&current_line);
+            self.replace_lines(self.cursor_y, self.cursor_y + 2, vec![joined]);
+        }
+    }
+
+    /// `Ctrl-w`: deletes the word before the cursor, and the blanks after
+    /// it. At the start of a line it joins the line to the one above.
+    pub fn delete_word_before(&mut self) {
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        if x == 0 {
+            self.delete_char();
+            return;
+        }
+        let start = match self.previous_word_start((y, x), false) {
+            (start_y, start_x) if start_y == y => start_x,
+            _ => 0,
+        };
+        let mut line = self.content[y].clone();
+        line.replace_range(start..x, "");
+        self.set_line(y, line);
+        self.cursor_x = start;
+    }
+
+    /// `Ctrl-u`: deletes back to the indentation, or from there to the
+    /// start of the line. At the start of a line it joins the line to the
+    /// one above.
+    pub fn delete_to_line_start(&mut self) {
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        if x == 0 {
+            self.delete_char();
+            return;
         }
+        let indent = leading_whitespace(&self.content[y]).len();
+        let start = if x > indent { indent } else { 0 };
+        let mut line = self.content[y].clone();
+        line.replace_range(start..x, "");
+        self.set_line(y, line);
+        self.cursor_x = start;
     }
 
+    /// `Delete`: deletes the character under the cursor, or joins the next
+    /// line on at the end of a line.
+    pub fn delete_forward(&mut self) {
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        let line = &self.content[y];
+        match line[x..].chars().next() {
+            Some(c) => {
+                let mut line = line.clone();
+                line.replace_range(x..x + c.len_utf8(), "");
+                self.set_line(y, line);
+            }
+            None if y + 1 < self.content.len() => {
+                let joined = line.clone() + &self.content[y + 1];
+                self.replace_lines(y, y + 2, vec![joined]);
+            }
+            None => {}
+        }
+    }
+
+    /// Inserts `text`, which may span lines, at the cursor as it is,
+    /// without autoindent or autopairs, leaving the cursor after it.
+    pub fn insert_text(&mut self, text: &str) {
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        let line = &self.content[y];
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let last = lines.len() - 1;
+        let end_x = lines[last].len() + if last == 0 { x } else { 0 };
+        lines[0].insert_str(0, &line[..x]);
+        lines[last].push_str(&line[x..]);
+        self.replace_lines(y, y + 1, lines);
+        (self.cursor_y, self.cursor_x) = (y + last, end_x);
+    }
+
+    /// Splits the line at the cursor, carrying the indentation over with
+    /// autoindent. Splitting between a pair of brackets with smartindent,
+    /// or right between an empty pair with autopairs, puts the closing one
+    /// on a line of its own.
     pub fn insert_newline(&mut self) {
-        let current_line = self.content[self.cursor_y].split_off(self.cursor_x);
-        self.content.insert(self.cursor_y + 1, current_line);
+        // backspace in replace mode doesn't go back across lines
+        self.replaced.clear();
+        let in_pair = self.empty_pair().is_some_and(|(open, close)| open != close);
+        let mut line = self.content[self.cursor_y].clone();
+        let rest = line.split_off(self.cursor_x);
+        let split = (self.cursor_y, self.cursor_x);
+        if !self.config.autoindent && !in_pair {
+            self.replace_lines(self.cursor_y, self.cursor_y + 1, vec![line, rest]);
+            self.split_marks(split, 0, (self.cursor_y + 1, 0));
+            self.cursor_y += 1;
+            self.cursor_x = 0;
+            return;
+        }
+
+        let base = leading_whitespace(&line).to_string();
+        let mut indent = self.indent_after(&line);
+        if in_pair && indent == base {
+            indent = self.indented(&base);
+        }
+        let skipped = rest.len() - rest.trim_start().len();
+        let rest = rest.trim_start();
+        let mut lines = vec![line.clone()];
+        if (self.config.smartindent || in_pair) && indent != base && rest.starts_with(CLOSERS) {
+            lines.push(indent.clone());
+            lines.push(format!("{}{}", base, rest));
+        } else if self.config.smartindent && rest.starts_with(CLOSERS) {
+            lines.push(format!("{}{}", self.dedented(&indent), rest));
+        } else {
+            lines.push(format!("{}{}", indent, rest));
+        }
+        let last = lines.len() - 1;
+        let rest_x = lines[last].len() - rest.len();
+        self.replace_lines(self.cursor_y, self.cursor_y + 1, lines);
+        self.split_marks(split, skipped, (split.0 + last, rest_x));
         self.cursor_y += 1;
-        self.cursor_x = 0;
+        self.cursor_x = std::cmp::min(indent.len(), self.content[self.cursor_y].len());
     }
 
     pub fn handle_mouse_event(&mut self, event: MouseEvent) {
         match event.kind {
             MouseEventKind::ScrollUp => {
-                self.move_cursor(KeyCode::Up);
-                self.move_cursor(KeyCode::Up);
-                self.move_cursor(KeyCode::Up);
+                for _ in 0..self.config.mouse_scroll {
+                    self.move_cursor(KeyCode::Up);
+                }
             }
             MouseEventKind::ScrollDown => {
-                self.move_cursor(KeyCode::Down);
-                self.move_cursor(KeyCode::Down);
-                self.move_cursor(KeyCode::Down);
+                for _ in 0..self.config.mouse_scroll {
+                    self.move_cursor(KeyCode::Down);
+                }
             }
             _ => (),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn left_and_right_move_by_characters() {
+        let mut editor = editor_with("héllo wörld");
+        editor.type_keys("l");
+        assert_eq!(editor.cursor_x, 1);
+        editor.type_keys("l");
+        assert_eq!(editor.cursor_x, 3);
+        editor.type_keys("h");
+        assert_eq!(editor.cursor_x, 1);
+        editor.type_keys("$hhhh");
+        assert_eq!(editor.cursor_x, "héllo w".len());
+    }
+
+    #[test]
+    fn motions_after_moving_over_multibyte_characters() {
+        let mut editor = editor_with("héllo world");
+        editor.type_keys("llhw");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (0, "héllo ".len()));
+        editor.type_keys("hhhb");
+        assert_eq!(editor.cursor_x, 0);
+    }
+
+    #[test]
+    fn backspace_removes_a_soft_tab() {
+        let mut editor = editor_with("x");
+        editor.type_keys("A<Tab><Tab>");
+        assert_eq!(editor.text(), "x       \n");
+        editor.type_keys("<BS>");
+        assert_eq!(editor.text(), "x   \n");
+        editor.type_keys("<BS><Esc>");
+        assert_eq!(editor.text(), "x\n");
+
+        // back to the previous stop, not a whole softtabstop
+        let mut editor = editor_with("      y");
+        editor.type_keys("wi<BS><Esc>");
+        assert_eq!(editor.text(), "    y\n");
+        // a single space goes when softtabstop is off
+        editor.config.softtabstop = 0;
+        editor.type_keys("^i<BS><Esc>");
+        assert_eq!(editor.text(), "   y\n");
+    }
+}
@@ -0,0 +1,557 @@
+use crate::*;
+
+/// How `~`, `g~`, `gu` and `gU` change letters.
+#[derive(Clone, Copy)]
+pub enum Case {
+    Toggle,
+    Lower,
+    Upper,
+}
+
+impl Case {
+    fn apply(self, text: &str) -> String {
+        match self {
+            Case::Lower => text.to_lowercase(),
+            Case::Upper => text.to_uppercase(),
+            Case::Toggle => text
+                .chars()
+                .map(|c| match c.is_uppercase() {
+                    true => c.to_lowercase().collect::<String>(),
+                    false => c.to_uppercase().collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// An operator like `d` or `gU` waiting for the motion that picks its
+/// text.
+#[derive(Clone, Copy)]
+pub struct PendingOperator {
+    /// The action that started it, which typed again as in `dd` works on
+    /// whole lines.
+    pub name: &'static str,
+    /// Runs the operator from the first position up to the second, which
+    /// for a linewise motion cover whole lines.
+    pub apply: fn(&mut Editor, Pos, Pos, MotionKind),
+    pub from: Pos,
+    pub count: Option<usize>,
+    /// The motion, once one that waits for a character like `f` has run.
+    pub motion: Option<&'static str>,
+}
+
+impl Editor {
+    /// `a`: inserts after the character under the cursor.
+    pub fn append(&mut self) {
+        let line = &self.content[self.cursor_y];
+        self.cursor_x += line[self.cursor_x..]
+            .chars()
+            .next()
+            .map_or(0, char::len_utf8);
+        self.mode = Mode::Insert;
+    }
+
+    /// `A`: inserts at the end of the line.
+    pub fn append_line_end(&mut self) {
+        self.cursor_x = self.content[self.cursor_y].len();
+        self.mode = Mode::Insert;
+    }
+
+    /// `I`: inserts before the first non-blank character of the line.
+    pub fn insert_line_start(&mut self) {
+        self.cursor_to_indent();
+        self.mode = Mode::Insert;
+    }
+
+    /// `o` and `O`: opens a new line below or above the cursor, indented
+    /// like the cursor line with autoindent. With a count, leaving insert
+    /// mode opens that many lines of the typed text.
+    pub fn open_line(&mut self, below: bool) {
+        let count = self.take_count().unwrap_or(1);
+        if below {
+            self.cursor_x = self.content[self.cursor_y].len();
+            self.insert_newline();
+        } else {
+            let indent = match self.config.autoindent {
+                true => leading_whitespace(&self.content[self.cursor_y]).to_string(),
+                false => String::new(),
+            };
+            self.cursor_x = indent.len();
+            self.replace_lines(self.cursor_y, self.cursor_y, vec![indent]);
+        }
+        self.mode = Mode::Insert;
+        if count > 1 {
+            self.insert_repeat = Some((count, self.change_keys.len(), true));
+        }
+    }
+
+    /// `r{char}`: replaces the character under the cursor, or count
+    /// characters, leaving the cursor on the last one.
+    pub fn replace_chars(&mut self, c: char) {
+        let count = self.take_count().unwrap_or(1);
+        let mut line = self.content[self.cursor_y].clone();
+        let rest = &line[self.cursor_x..];
+        let Some(end) = rest
+            .char_indices()
+            .map(|(i, ch)| self.cursor_x + i + ch.len_utf8())
+            .nth(count - 1)
+        else {
+            return;
+        };
+        line.replace_range(self.cursor_x..end, &c.to_string().repeat(count));
+        self.set_line(self.cursor_y, line);
+        self.cursor_x += (count - 1) * c.len_utf8();
+    }
+
+    /// `R`: enters replace mode, where typing overwrites text.
+    pub fn enter_replace(&mut self) {
+        self.replaced.clear();
+        self.mode = Mode::Replace;
+    }
+
+    /// Types `c` over the character under the cursor in replace mode,
+    /// remembering it for backspace. Past the end of the line it is added.
+    pub fn replace_char(&mut self, c: char) {
+        let mut line = self.content[self.cursor_y].clone();
+        let old = line[self.cursor_x..].chars().next();
+        let end = self.cursor_x + old.map_or(0, char::len_utf8);
+        line.replace_range(self.cursor_x..end, c.encode_utf8(&mut [0; 4]));
+        self.set_line(self.cursor_y, line);
+        self.cursor_x += c.len_utf8();
+        self.replaced.push(old);
+    }
+
+    /// Backspace in replace mode: moves back over the last character typed
+    /// and puts back what it overwrote. Before the first one it only moves.
+    pub fn restore_replaced(&mut self) {
+        if self.cursor_x == 0 {
+            return;
+        }
+        let mut line = self.content[self.cursor_y].clone();
+        let start = line.floor_char_boundary(self.cursor_x - 1);
+        if let Some(old) = self.replaced.pop() {
+            let restored = old.map(String::from).unwrap_or_default();
+            line.replace_range(start..self.cursor_x, &restored);
+            self.set_line(self.cursor_y, line);
+        }
+        self.cursor_x = start;
+    }
+
+    /// `J`: joins the cursor line with the ones below it, or the selected
+    /// lines, dropping the next line's indentation. A space goes between
+    /// them unless the line already ends in a blank, is empty, or the next
+    /// one is empty or starts with `)`.
+    pub fn join_lines(&mut self) {
+        // `J` joins two lines, like `2J`
+        if self.mode != Mode::Visual {
+            self.count = Some(std::cmp::max(self.count.unwrap_or(2), 2));
+        }
+        let lines = self.target_lines();
+        if lines.len() < 2 {
+            self.fail("Cannot join at the last line");
+            return;
+        }
+        let mut joined = self.content[lines.start].clone();
+        let mut join_x = joined.len();
+        for y in lines.start + 1..lines.end {
+            let next = self.content[y].trim_start();
+            let space = !joined.is_empty()
+                && !joined.ends_with(char::is_whitespace)
+                && !next.is_empty()
+                && !next.starts_with(')');
+            join_x = joined.len();
+            if space {
+                joined.push(' ');
+            }
+            joined.push_str(next);
+        }
+        self.replace_lines(lines.start, lines.end, vec![joined]);
+        (self.cursor_y, self.cursor_x) = (lines.start, join_x);
+    }
+
+    /// Changes the case of the text from `from` up to `to`, leaving the
+    /// cursor at `from`.
+    pub fn change_case(&mut self, from: Pos, to: Pos, case: Case) {
+        for y in from.0..=to.0 {
+            let line = &self.content[y];
+            let start = if y == from.0 { from.1 } else { 0 };
+            let end = if y == to.0 { to.1 } else { line.len() };
+            let changed = case.apply(&line[start..end]);
+            if changed != line[start..end] {
+                let mut line = line.clone();
+                line.replace_range(start..end, &changed);
+                self.set_line(y, line);
+            }
+        }
+        (self.cursor_y, self.cursor_x) = from;
+    }
+
+    /// `~`: toggles the case of the character under the cursor, or count
+    /// characters, and moves past them.
+    pub fn toggle_case(&mut self) {
+        let count = self.take_count().unwrap_or(1);
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        let line = &self.content[y];
+        let end = line[x..]
+            .char_indices()
+            .map(|(i, c)| x + i + c.len_utf8())
+            .take(count)
+            .last()
+            .unwrap_or(x);
+        self.change_case((y, x), (y, end), Case::Toggle);
+        self.cursor_x = std::cmp::min(end, self.content[y].len());
+    }
+
+    /// `guu` and friends: changes the case of the cursor line, or count
+    /// lines.
+    pub fn change_case_lines(&mut self, case: Case) {
+        let lines = self.target_lines();
+        let last = lines.end - 1;
+        self.change_case((lines.start, 0), (last, self.content[last].len()), case);
+    }
+
+    /// `~`, `u` and `U` in visual mode.
+    pub fn change_case_selection(&mut self, case: Case) {
+        let (from, (end_y, end_x)) = self.selection();
+        let line = &self.content[end_y];
+        let end = line.ceil_char_boundary(end_x + 1).min(line.len());
+        self.change_case(from, (end_y, end), case);
+        self.mode = self.resting_mode();
+    }
+
+    /// Starts the operator the action `name` stands for, such as `gU`, to
+    /// be run over the text the next motion moves across.
+    pub fn start_operator(
+        &mut self,
+        name: &'static str,
+        apply: fn(&mut Editor, Pos, Pos, MotionKind),
+    ) {
+        self.pending_operator = Some(PendingOperator {
+            name,
+            apply,
+            from: (self.cursor_y, self.cursor_x),
+            count: self.take_count(),
+            motion: None,
+        });
+    }
+
+    /// Runs `action` as the motion of a pending operator. Anything that
+    /// isn't a motion cancels the operator instead.
+    pub fn run_operator_motion(
+        &mut self,
+        mut operator: PendingOperator,
+        action: &'static Action,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // a count before the operator and one before the motion multiply,
+        // as in `2d3w`
+        self.count = match (operator.count, self.count) {
+            (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+            (a, b) => b.or(a),
+        };
+        if action.name == operator.name {
+            self.operate_on_lines(operator);
+            return Ok(());
+        }
+        match self.motion_kind(action.name) {
+            None => {
+                self.count = None;
+                return Ok(());
+            }
+            #[cfg(feature = "treesitter")]
+            Some(MotionKind::TextObject) => {
+                self.count = None;
+                self.operate_on_text_object(operator, action.name);
+                return Ok(());
+            }
+            Some(_) => {}
+        }
+        let (y, x) = (self.cursor_y, self.cursor_x);
+        let on_word = self.content[y][x..]
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_whitespace());
+        if operator.name == "change_operator"
+            && on_word
+            && matches!(action.name, "word_forward" | "big_word_forward")
+        {
+            self.change_to_word_end(operator, action.name == "big_word_forward");
+            return Ok(());
+        }
+        let before = (self.cursor_y, self.cursor_x);
+        self.run_binding(Binding::Action(action))?;
+        if self.motion_failed(action.name, before) {
+            return Ok(());
+        }
+        operator.motion = Some(action.name);
+        match self.awaiting_char {
+            // `f` and friends finish it once they have their character
+            Some(_) => self.pending_operator = Some(operator),
+            None => self.finish_operator(operator),
+        }
+        Ok(())
+    }
+
+    /// `dd`, `cc`, `yy` and the like: runs an operator over the cursor line
+    /// and count minus one lines below it.
+    fn operate_on_lines(&mut self, operator: PendingOperator) {
+        let count = self.take_count().unwrap_or(1);
+        let last = std::cmp::min(self.cursor_y + count - 1, self.content.len() - 1);
+        self.apply_operator(operator, (last, 0), MotionKind::Linewise);
+    }
+
+    /// `cw` on a word: changes to the end of the word, like `ce`, but
+    /// without moving on to the next word when already at its end.
+    fn change_to_word_end(&mut self, operator: PendingOperator, big: bool) {
+        let count = self.take_count().unwrap_or(1);
+        let mut pos = self.current_word_end((self.cursor_y, self.cursor_x), big);
+        for _ in 1..count {
+            pos = self.next_word_end(pos, big);
+        }
+        self.apply_operator(operator, pos, MotionKind::Inclusive);
+    }
+
+    /// Runs a pending operator over the text between where it started and
+    /// where its motion left the cursor. A motion that didn't move does
+    /// nothing unless it is linewise.
+    pub fn finish_operator(&mut self, operator: PendingOperator) {
+        let to = (self.cursor_y, self.cursor_x);
+        let Some(kind) = operator.motion.and_then(|motion| self.motion_kind(motion)) else {
+            return;
+        };
+        if to == operator.from && kind != MotionKind::Linewise {
+            return;
+        }
+        self.apply_operator(operator, to, kind);
+    }
+
+    /// Runs an operator from where it started to `to`, leaving the cursor
+    /// at the start of the text.
+    pub fn apply_operator(&mut self, operator: PendingOperator, to: Pos, mut kind: MotionKind) {
+        let (start, end) = match operator.from <= to {
+            true => (operator.from, to),
+            false => (to, operator.from),
+        };
+        // an exclusive motion from the indentation to the start of a later
+        // line, like `d}` at the start of a paragraph, works on whole lines
+        let word = matches!(operator.motion, Some("word_forward" | "big_word_forward"));
+        // a text object ending at the start of a line works the same way
+        let (start, end) = match kind {
+            MotionKind::Exclusive | MotionKind::TextObject
+                if !word
+                    && end.1 == 0
+                    && end.0 > start.0
+                    && start.1 <= self.first_non_blank(start.0).1 =>
+            {
+                kind = MotionKind::Linewise;
+                (start, (end.0 - 1, 0))
+            }
+            _ => (start, end),
+        };
+        (self.cursor_y, self.cursor_x) = start;
+        let (start, end) = match kind {
+            MotionKind::Linewise => ((start.0, 0), (end.0, self.content[end.0].len())),
+            MotionKind::Inclusive => {
+                let line = &self.content[end.0];
+                (
+                    start,
+                    (end.0, line.ceil_char_boundary(end.1 + 1).min(line.len())),
+                )
+            }
+            // stopping in the indentation of a later line, as `w` does after
+            // the last word of a line, leaves that line alone
+            MotionKind::Exclusive | MotionKind::TextObject => {
+                let mut end = end;
+                while end.0 > start.0 && self.content[end.0][..end.1].trim().is_empty() {
+                    end = (end.0 - 1, self.content[end.0 - 1].len());
+                }
+                (start, end)
+            }
+        };
+        (operator.apply)(self, start, end, kind);
+    }
+
+    /// The text from `start` up to `end` as a register holds it, whole
+    /// lines ending in a newline for a linewise motion.
+    fn range_text(&self, start: Pos, end: Pos, kind: MotionKind) -> String {
+        if kind == MotionKind::Linewise {
+            return self.lines_text(start.0..end.0 + 1);
+        }
+        match start.0 == end.0 {
+            true => self.content[start.0][start.1..end.1].to_string(),
+            false => {
+                let mut text = self.content[start.0][start.1..].to_string();
+                for line in &self.content[start.0 + 1..end.0] {
+                    text.push('\n');
+                    text.push_str(line);
+                }
+                text.push('\n');
+                text.push_str(&self.content[end.0][..end.1]);
+                text
+            }
+        }
+    }
+
+    /// `d`: deletes the text an operator covers into the unnamed register.
+    pub fn delete_range(&mut self, start: Pos, end: Pos, kind: MotionKind) {
+        let text = self.range_text(start, end, kind);
+        self.registers.insert('"', text);
+        if kind == MotionKind::Linewise {
+            // the buffer always keeps one line
+            let keep = match end.0 + 1 - start.0 == self.content.len() {
+                true => vec![String::new()],
+                false => Vec::new(),
+            };
+            self.replace_lines(start.0, end.0 + 1, keep);
+            self.cursor_y = std::cmp::min(start.0, self.content.len() - 1);
+            self.cursor_to_indent();
+            return;
+        }
+        let joined = self.content[start.0][..start.1].to_string() + &self.content[end.0][end.1..];
+        self.replace_lines(start.0, end.0 + 1, vec![joined]);
+        (self.cursor_y, self.cursor_x) = start;
+    }
+
+    /// `c`: deletes the text like `d` and starts inserting. Whole lines are
+    /// replaced by one keeping the first one's indentation with autoindent.
+    pub fn change_range(&mut self, start: Pos, end: Pos, kind: MotionKind) {
+        match kind {
+            MotionKind::Linewise => {
+                let text = self.range_text(start, end, kind);
+                self.registers.insert('"', text);
+                let indent = match self.config.autoindent {
+                    true => leading_whitespace(&self.content[start.0]).to_string(),
+                    false => String::new(),
+                };
+                (self.cursor_y, self.cursor_x) = (start.0, indent.len());
+                self.replace_lines(start.0, end.0 + 1, vec![indent]);
+            }
+            _ => self.delete_range(start, end, kind),
+        }
+        self.mode = Mode::Insert;
+    }
+
+    /// `y`: copies the text an operator covers into the unnamed register.
+    pub fn yank_range(&mut self, start: Pos, end: Pos, kind: MotionKind) {
+        let text = self.range_text(start, end, kind);
+        self.registers.insert('"', text);
+    }
+
+    /// `D`: deletes from the cursor to the end of the line, and count minus
+    /// one lines below it.
+    pub fn delete_to_line_end(&mut self) {
+        let count = self.take_count().unwrap_or(1);
+        let last = std::cmp::min(self.cursor_y + count, self.content.len());
+        let kept = self.content[self.cursor_y][..self.cursor_x].to_string();
+        self.replace_lines(self.cursor_y, last, vec![kept]);
+    }
+
+    /// `C`: deletes to the end of the line and starts inserting.
+    pub fn change_to_line_end(&mut self) {
+        self.delete_to_line_end();
+        self.mode = Mode::Insert;
+    }
+
+    /// `s`: deletes the character under the cursor, or count characters,
+    /// and starts inserting.
+    pub fn substitute_chars(&mut self) {
+        let count = self.take_count().unwrap_or(1);
+        let mut line = self.content[self.cursor_y].clone();
+        let end = line[self.cursor_x..]
+            .char_indices()
+            .map(|(i, c)| self.cursor_x + i + c.len_utf8())
+            .take(count)
+            .last()
+            .unwrap_or(self.cursor_x);
+        if end > self.cursor_x {
+            line.replace_range(self.cursor_x..end, "");
+            self.set_line(self.cursor_y, line);
+        }
+        self.mode = Mode::Insert;
+    }
+
+    /// `S`: replaces the cursor line, or count lines, with an empty line
+    /// keeping the indentation and starts inserting.
+    pub fn substitute_lines(&mut self) {
+        let lines = self.target_lines();
+        let indent = match self.config.autoindent {
+            true => leading_whitespace(&self.content[lines.start]).to_string(),
+            false => String::new(),
+        };
+        (self.cursor_y, self.cursor_x) = (lines.start, indent.len());
+        self.replace_lines(lines.start, lines.end, vec![indent]);
+        self.mode = Mode::Insert;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    fn after(text: &str, keys: &str) -> String {
+        let mut editor = editor_with(text);
+        editor.type_keys(keys);
+        editor.text()
+    }
+
+    #[test]
+    fn delete_with_motions() {
+        assert_eq!(after("one two three", "dw"), "two three\n");
+        assert_eq!(after("one two three", "wd$"), "one \n");
+        assert_eq!(after("one two three", "dfo"), " three\n");
+        assert_eq!(after("one two three", "dtt"), "two three\n");
+        assert_eq!(after("one two\nthree", "wdw"), "one \nthree\n");
+        assert_eq!(after("a\nb\nc\nd", "jdj"), "a\nd\n");
+        assert_eq!(after("a\nb\n\nc", "d}"), "\nc\n");
+        assert_eq!(after("a\nb\nc", "Gdgg"), "\n");
+    }
+
+    #[test]
+    fn counts_before_and_after_the_operator() {
+        let text = "a b c d e f g h";
+        assert_eq!(after(text, "2dw"), "c d e f g h\n");
+        assert_eq!(after(text, "d3w"), "d e f g h\n");
+        assert_eq!(after(text, "2d3w"), "g h\n");
+        assert_eq!(after("1\n2\n3\n4", "3dd"), "4\n");
+        assert_eq!(after("1\n2\n3\n4", "d2d"), "3\n4\n");
+    }
+
+    #[test]
+    fn change_with_motions() {
+        assert_eq!(after("one two", "cwnew<Esc>"), "new two\n");
+        assert_eq!(after("one two", "llcwx<Esc>"), "onx two\n");
+        assert_eq!(after("one  two", "lllcwx<Esc>"), "onextwo\n");
+        assert_eq!(after("a b c d", "c2wx<Esc>"), "x c d\n");
+        assert_eq!(after("  one\n  two\nthree", "cjx<Esc>"), "  x\nthree\n");
+        assert_eq!(after("one two", "ccx<Esc>"), "x\n");
+    }
+
+    #[test]
+    fn yank_fills_the_unnamed_register() {
+        let mut editor = editor_with("one two\nthree");
+        editor.type_keys("wye");
+        assert_eq!(editor.registers[&'"'], "two");
+        assert_eq!(editor.cursor_x, 4);
+        editor.type_keys("yj");
+        assert_eq!(editor.registers[&'"'], "one two\nthree\n");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (0, 4));
+        editor.type_keys("dw");
+        assert_eq!(editor.registers[&'"'], "two");
+        assert_eq!(editor.text(), "one \nthree\n");
+    }
+
+    #[test]
+    fn dot_repeats_operators() {
+        assert_eq!(after("a b c d e", "dw.."), "d e\n");
+        assert_eq!(after("a b c d e f", "d2w."), "e f\n");
+        assert_eq!(after("one two three", "cwx<Esc>w."), "x x three\n");
+        assert_eq!(after("1\n2\n3\n4\n5", "2dd."), "5\n");
+    }
+
+    #[test]
+    fn escape_cancels_an_operator() {
+        let mut editor = editor_with("one two");
+        editor.type_keys("d<Esc>w");
+        assert_eq!(editor.text(), "one two\n");
+        assert_eq!(editor.cursor_x, 4);
+    }
+}
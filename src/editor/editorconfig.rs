@@ -0,0 +1,489 @@
+use crate::*;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Charset {
+    Utf8,
+    Utf8Bom,
+    Latin1,
+    Utf16Be,
+    Utf16Le,
+}
+
+const BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+
+impl Charset {
+    /// Decodes `bytes`, handing them back if they aren't valid text.
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String, Vec<u8>> {
+        match self {
+            Charset::Utf8 => String::from_utf8(bytes).map_err(|e| e.into_bytes()),
+            Charset::Utf8Bom => match bytes.strip_prefix(BOM) {
+                Some(rest) => String::from_utf8(rest.to_vec()).map_err(|_| bytes),
+                None => String::from_utf8(bytes).map_err(|e| e.into_bytes()),
+            },
+            Charset::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+            Charset::Utf16Be | Charset::Utf16Le => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(bytes);
+                }
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|pair| match self {
+                        Charset::Utf16Be => u16::from_be_bytes([pair[0], pair[1]]),
+                        _ => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                let units = units.strip_prefix(&[0xFEFF]).unwrap_or(&units);
+                String::from_utf16(units).map_err(|_| bytes)
+            }
+        }
+    }
+
+    pub fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        Ok(match self {
+            Charset::Utf8 => text.as_bytes().to_vec(),
+            Charset::Utf8Bom => [BOM, text.as_bytes()].concat(),
+            Charset::Latin1 => text
+                .chars()
+                .map(|c| u8::try_from(c).ok())
+                .collect::<Option<Vec<u8>>>()
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "text is not valid latin1")
+                })?,
+            Charset::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Charset::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        })
+    }
+}
+
+/// The `.editorconfig` properties that apply to a file. Unset properties
+/// leave eep's own settings alone.
+#[derive(Clone, Default)]
+pub struct EditorConfig {
+    pub expandtab: Option<bool>,
+    /// Columns per indent level; 0 for `indent_size = tab`.
+    pub indent_size: Option<usize>,
+    pub tab_width: Option<usize>,
+    pub end_of_line: Option<LineEnding>,
+    pub charset: Option<Charset>,
+    pub trim_trailing_whitespace: Option<bool>,
+    pub insert_final_newline: Option<bool>,
+}
+
+impl EditorConfig {
+    /// Collects the sections matching `path` from `.editorconfig` files in
+    /// its directory and those above it, up to one marked `root = true`.
+    /// Nearer files and later sections win.
+    pub fn for_path(path: &Path) -> EditorConfig {
+        let Ok(path) = std::path::absolute(path) else {
+            return EditorConfig::default();
+        };
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            let Ok(text) = fs::read_to_string(dir.join(".editorconfig")) else {
+                continue;
+            };
+            let root = is_root(&text);
+            files.push((dir, text));
+            if root {
+                break;
+            }
+        }
+
+        let mut properties = HashMap::new();
+        for (dir, text) in files.iter().rev() {
+            let Ok(relative) = path.strip_prefix(dir) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            apply_sections(text, &relative, &mut properties);
+        }
+        EditorConfig::from_properties(&properties)
+    }
+
+    fn from_properties(properties: &HashMap<String, String>) -> EditorConfig {
+        let get = |name: &str| properties.get(name).map(String::as_str);
+        let flag = |name: &str| match get(name) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            _ => None,
+        };
+        let number = |name: &str| get(name)?.parse().ok().filter(|&n| n > 0);
+        EditorConfig {
+            expandtab: match get("indent_style") {
+                Some("space") => Some(true),
+                Some("tab") => Some(false),
+                _ => None,
+            },
+            indent_size: match get("indent_size") {
+                Some("tab") => Some(0),
+                _ => number("indent_size"),
+            },
+            tab_width: number("tab_width"),
+            end_of_line: match get("end_of_line") {
+                Some("lf") => Some(LineEnding::Lf),
+                Some("crlf") => Some(LineEnding::Crlf),
+                Some("cr") => Some(LineEnding::Cr),
+                _ => None,
+            },
+            charset: match get("charset") {
+                Some("utf-8") => Some(Charset::Utf8),
+                Some("utf-8-bom") => Some(Charset::Utf8Bom),
+                Some("latin1") => Some(Charset::Latin1),
+                Some("utf-16be") => Some(Charset::Utf16Be),
+                Some("utf-16le") => Some(Charset::Utf16Le),
+                _ => None,
+            },
+            trim_trailing_whitespace: flag("trim_trailing_whitespace"),
+            insert_final_newline: flag("insert_final_newline"),
+        }
+    }
+}
+
+/// Splits an INI line into a lowercased key and value.
+fn property(line: &str) -> Option<(String, String)> {
+    let (key, value) = line.split_once('=')?;
+    Some((key.trim().to_lowercase(), value.trim().to_lowercase()))
+}
+
+fn is_comment(line: &str) -> bool {
+    line.is_empty() || line.starts_with('#') || line.starts_with(';')
+}
+
+/// Whether the preamble before the first section says `root = true`.
+fn is_root(text: &str) -> bool {
+    text.lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !is_comment(line))
+        .filter_map(property)
+        .any(|(key, value)| key == "root" && value == "true")
+}
+
+/// Applies the properties of each section of `text` whose glob matches
+/// `relative`, the file's path from the directory `text` was found in.
+fn apply_sections(text: &str, relative: &str, properties: &mut HashMap<String, String>) {
+    let mut matched = false;
+    for line in text.lines().map(str::trim) {
+        if is_comment(line) {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            matched = SectionGlob::new(glob).is_some_and(|glob| glob.is_match(relative));
+            continue;
+        }
+        if !matched {
+            continue;
+        }
+        if let Some((key, value)) = property(line) {
+            match value.as_str() {
+                "unset" => properties.remove(&key),
+                _ => properties.insert(key, value),
+            };
+        }
+    }
+}
+
+/// A section name compiled for matching relative paths.
+struct SectionGlob {
+    regex: Regex,
+    /// The bounds of each `{low..high}` in the glob, which the regex
+    /// captures as plain numbers, in the order of its groups.
+    ranges: Vec<(i64, i64)>,
+}
+
+impl SectionGlob {
+    /// A glob without a `/` matches the file name in any directory.
+    fn new(glob: &str) -> Option<SectionGlob> {
+        let chars: Vec<char> = glob.chars().collect();
+        let mut ranges = Vec::new();
+        let pattern = match glob.strip_prefix('/') {
+            Some(_) => format!("^{}$", glob_pattern(&chars[1..], &mut ranges)),
+            None if glob.contains('/') => format!("^{}$", glob_pattern(&chars, &mut ranges)),
+            None => format!("^(?:.*/)?{}$", glob_pattern(&chars, &mut ranges)),
+        };
+        let regex = Regex::new(&pattern).ok()?;
+        Some(SectionGlob { regex, ranges })
+    }
+
+    fn is_match(&self, relative: &str) -> bool {
+        let Some(captures) = self.regex.captures(relative) else {
+            return false;
+        };
+        self.ranges.iter().enumerate().all(|(i, &(low, high))| {
+            captures.get(i + 1).is_none_or(|number| {
+                number
+                    .as_str()
+                    .parse::<i64>()
+                    .is_ok_and(|n| (low..=high).contains(&n))
+            })
+        })
+    }
+}
+
+/// Translates `*`, `**`, `?`, `[...]`, `{a,b}` and `{1..3}` to regex syntax,
+/// adding the bounds of numeric ranges to `ranges`.
+fn glob_pattern(chars: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let mut pattern = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // `**/` may match no directories at all
+            '*' if chars[i + 1..].starts_with(&['*', '/']) => {
+                pattern.push_str("(?:.*/)?");
+                i += 2;
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                pattern.push_str(".*");
+                i += 1;
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            '\\' if i + 1 < chars.len() => {
+                pattern.push_str(&regex::escape(&chars[i + 1].to_string()));
+                i += 1;
+            }
+            '[' if chars[i..].contains(&']') => {
+                let end = i + chars[i..].iter().position(|&c| c == ']').unwrap();
+                let class: String = chars[i + 1..end].iter().collect();
+                let (negate, class) = match class.strip_prefix('!') {
+                    Some(rest) => ("^", rest.to_string()),
+                    None => ("", class),
+                };
+                let class = class.replace('\\', "\\\\").replace('[', "\\[");
+                pattern.push_str(&format!("[{}{}]", negate, class));
+                i = end;
+            }
+            '{' => match closing_brace(chars, i) {
+                Some(end) => {
+                    pattern.push_str(&brace_pattern(&chars[i + 1..end], ranges));
+                    i = end;
+                }
+                None => pattern.push_str("\\{"),
+            },
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    pattern
+}
+
+/// The index of the `}` closing the `{` at `open`, allowing nesting.
+fn closing_brace(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(open) {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The contents of a `{...}` group: a numeric range, alternatives, or a
+/// literal when it is neither. A range matches any number and is checked
+/// against its bounds after the match, so a huge one costs no more than a
+/// small one.
+fn brace_pattern(inner: &[char], ranges: &mut Vec<(i64, i64)>) -> String {
+    let text: String = inner.iter().collect();
+    if let Some((low, high)) = text.split_once("..")
+        && let (Ok(low), Ok(high)) = (low.parse::<i64>(), high.parse::<i64>())
+    {
+        ranges.push((low.min(high), low.max(high)));
+        return "([+-]?[0-9]+)".to_string();
+    }
+
+    let mut alternatives = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, &c) in inner.iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                alternatives.push(glob_pattern(&inner[start..i], ranges));
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if alternatives.is_empty() {
+        return format!("\\{{{}\\}}", glob_pattern(inner, ranges));
+    }
+    alternatives.push(glob_pattern(&inner[start..], ranges));
+    format!("(?:{})", alternatives.join("|"))
+}
+
+impl Editor {
+    /// Applies the indentation properties of `self.editorconfig` on top of
+    /// the config and any detected indentation.
+    pub fn apply_editorconfig(&mut self) {
+        let editorconfig = &self.editorconfig;
+        if let Some(expandtab) = editorconfig.expandtab {
+            self.config.expandtab = expandtab;
+        }
+        // tab_width defaults to indent_size
+        if let Some(tab_width) = editorconfig
+            .tab_width
+            .or(editorconfig.indent_size)
+            .filter(|&n| n > 0)
+        {
+            self.config.tabstop = tab_width;
+        }
+        if let Some(size) = editorconfig.indent_size {
+            self.config.shiftwidth = size;
+        }
+        if editorconfig.expandtab.is_some() || editorconfig.indent_size.is_some() {
+            self.config.softtabstop = match self.config.expandtab {
+                true => self.config.shiftwidth(),
+                false => 0,
+            };
+        }
+    }
+
+    /// Removes trailing whitespace from every line, for
+    /// `trim_trailing_whitespace`.
+    pub fn trim_trailing_whitespace(&mut self) {
+        for y in 0..self.content.len() {
+            let line = &self.content[y];
+            let trimmed = line.trim_end();
+            if trimmed.len() != line.len() {
+                let trimmed = trimmed.to_string();
+                self.set_line(y, trimmed);
+            }
+        }
+        self.cursor_x = std::cmp::min(self.cursor_x, self.content[self.cursor_y].len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        SectionGlob::new(glob).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn stars_match_within_or_across_directories() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/editor/main.rs"));
+        assert!(!matches("src/*.rs", "src/editor/main.rs"));
+        assert!(matches("src/**.rs", "src/editor/main.rs"));
+        assert!(matches("src/**/*.rs", "src/editor/main.rs"));
+        assert!(matches("src/**/*.rs", "src/main.rs"));
+        assert!(!matches("src/**/*.rs", "tests/main.rs"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+    }
+
+    #[test]
+    fn a_slash_anchors_to_the_config_directory() {
+        assert!(matches("/top.txt", "top.txt"));
+        assert!(!matches("/top.txt", "sub/top.txt"));
+        assert!(matches("sub/*.txt", "sub/a.txt"));
+        assert!(!matches("sub/*.txt", "other/sub/a.txt"));
+    }
+
+    #[test]
+    fn brackets_and_braces() {
+        assert!(matches("[ab].c", "a.c"));
+        assert!(!matches("[ab].c", "c.c"));
+        assert!(matches("[!ab].c", "c.c"));
+        assert!(!matches("[!ab].c", "a.c"));
+        assert!(matches("*.{js,ts}", "index.ts"));
+        assert!(!matches("*.{js,ts}", "index.rs"));
+        assert!(matches("{a,{b,c}d}.txt", "cd.txt"));
+        assert!(matches("{a,{b,c}d}.txt", "a.txt"));
+        assert!(!matches("{a,{b,c}d}.txt", "b.txt"));
+        // a single word in braces is literal
+        assert!(matches("{a}.txt", "{a}.txt"));
+        assert!(matches("{a.txt", "{a.txt"));
+    }
+
+    #[test]
+    fn numeric_ranges_are_matched_by_value() {
+        assert!(matches("file{1..3}.txt", "file2.txt"));
+        assert!(!matches("file{1..3}.txt", "file4.txt"));
+        assert!(matches("{3..1}", "1"));
+        assert!(matches("{-3..3}", "-2"));
+        assert!(!matches("{-3..3}", "-4"));
+        assert!(matches("{a,{1..5}}.log", "4.log"));
+        assert!(!matches("{a,{1..5}}.log", "6.log"));
+        assert!(matches("{1..2}-{10..20}", "2-15"));
+        assert!(!matches("{1..2}-{10..20}", "2-25"));
+
+        let huge = "[{1..100000000}]\nindent_size = 2\n";
+        let mut properties = HashMap::new();
+        apply_sections(huge, "100000001", &mut properties);
+        assert!(properties.is_empty());
+        apply_sections(huge, "5000000", &mut properties);
+        assert_eq!(properties["indent_size"], "2");
+    }
+
+    #[test]
+    fn nearer_files_win_until_root() {
+        let dir = std::env::temp_dir().join(format!("eep-editorconfig-{}", std::process::id()));
+        let sub = dir.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(
+            dir.join(".editorconfig"),
+            "root = true\n\n[*]\nindent_style = tab\ntab_width = 8\n\n[*.md]\nindent_size = 2\n",
+        )
+        .unwrap();
+        fs::write(
+            sub.join(".editorconfig"),
+            "[*]\nindent_style = space\n# a comment\n[*.md]\ntab_width = unset\n",
+        )
+        .unwrap();
+
+        let top = EditorConfig::for_path(&dir.join("a.md"));
+        assert_eq!(top.expandtab, Some(false));
+        assert_eq!(top.tab_width, Some(8));
+        assert_eq!(top.indent_size, Some(2));
+
+        let nested = EditorConfig::for_path(&sub.join("b.md"));
+        assert_eq!(nested.expandtab, Some(true));
+        assert_eq!(nested.tab_width, None);
+        assert_eq!(nested.indent_size, Some(2));
+        assert_eq!(EditorConfig::for_path(&sub.join("b.rs")).tab_width, Some(8));
+
+        // a root file stops the search for ones further up
+        fs::write(
+            sub.join(".editorconfig"),
+            "root = true\n[*]\nindent_size = 3\n",
+        )
+        .unwrap();
+        let rooted = EditorConfig::for_path(&sub.join("b.md"));
+        assert_eq!(rooted.expandtab, None);
+        assert_eq!(rooted.indent_size, Some(3));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
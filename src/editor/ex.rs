@@ -0,0 +1,567 @@
+use crate::*;
+use regex::Regex;
+use std::fmt;
+use std::ops::Range;
+
+/// Why an ex command couldn't be parsed or run.
+#[derive(Debug)]
+pub enum ExError {
+    UnknownCommand(String),
+    InvalidRange,
+    NoRange,
+    NoBang,
+    MarkNotSet(char),
+    PatternNotFound(String),
+    InvalidPattern(String),
+    ArgumentRequired,
+    TrailingCharacters(String),
+    /// A shell command exited with an error, with the first line it wrote
+    /// to stderr.
+    ShellFailed {
+        status: std::process::ExitStatus,
+        stderr: String,
+    },
+    /// A command ran but failed, with its message.
+    Failed(String),
+}
+
+impl fmt::Display for ExError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExError::UnknownCommand(name) => write!(f, "Unknown command: {}", name),
+            ExError::InvalidRange => write!(f, "Invalid range"),
+            ExError::NoRange => write!(f, "No range allowed"),
+            ExError::NoBang => write!(f, "No ! allowed"),
+            ExError::MarkNotSet(c) => write!(f, "Mark not set: {}", c),
+            ExError::PatternNotFound(pattern) => write!(f, "Pattern not found: {}", pattern),
+            ExError::InvalidPattern(e) => write!(f, "Invalid pattern: {}", e),
+            ExError::ArgumentRequired => write!(f, "Argument required"),
+            ExError::TrailingCharacters(s) => write!(f, "Trailing characters: {}", s),
+            ExError::ShellFailed { status, stderr } => {
+                match status.code() {
+                    Some(code) => write!(f, "Shell returned {}", code)?,
+                    None => write!(f, "Shell command killed by a signal")?,
+                }
+                match stderr.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, ": {}", stderr),
+                }
+            }
+            ExError::Failed(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ExError {}
+
+impl From<String> for ExError {
+    fn from(message: String) -> Self {
+        ExError::Failed(message)
+    }
+}
+
+impl From<ConfigError> for ExError {
+    fn from(e: ConfigError) -> Self {
+        ExError::Failed(e.to_string())
+    }
+}
+
+/// What the argument of an ex command completes to on the command line.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExCompletion {
+    None,
+    File,
+    Option,
+    Colorscheme,
+    Syntax,
+}
+
+/// An entry in the ex command registry, [`EX_COMMANDS`].
+pub struct ExCommand {
+    pub name: &'static str,
+    /// How much of `name` has to be typed, like 1 for `:w`.
+    pub abbrev: usize,
+    /// Whether the command takes a line range.
+    pub range: bool,
+    /// Whether the command takes a `!` after its name.
+    pub bang: bool,
+    /// Whether `|` ends the command. Commands that take other commands or
+    /// keys as their argument get the `|` instead.
+    pub bar: bool,
+    pub complete: ExCompletion,
+    pub run: fn(&mut Editor, &ExCall) -> Result<(), ExError>,
+}
+
+/// An ex command as it is run, with its range resolved to lines.
+pub struct ExCall {
+    pub command: &'static ExCommand,
+    /// The lines the range covers, if one was given.
+    pub range: Option<Range<usize>>,
+    /// Whether the range ended at line 0, above the first line, which
+    /// commands that add lines like `:r` can put them after.
+    pub line_zero: bool,
+    pub bang: bool,
+    pub arg: String,
+}
+
+/// One piece of a command line, up to a `|`.
+enum ExLine {
+    Empty,
+    /// A range with no command, which goes to its last line.
+    Goto(usize),
+    Call(ExCall),
+}
+
+/// Where a line address starts counting from.
+enum AddressBase {
+    Number(usize),
+    Current,
+    Last,
+    Mark(char),
+    Search { pattern: String, forward: bool },
+}
+
+/// A line address like `'a+2` or `/fn main/`, not yet looked up.
+struct Address {
+    base: AddressBase,
+    offset: isize,
+    /// Whether it came after a `;`, which moves the cursor to the address
+    /// before it first.
+    semicolon: bool,
+}
+
+/// Finds the command `name` names, either in full or abbreviated.
+pub fn find_ex_command(name: &str) -> Option<&'static ExCommand> {
+    EX_COMMANDS.iter().find(|c| c.name == name).or_else(|| {
+        EX_COMMANDS
+            .iter()
+            .find(|c| name.len() >= c.abbrev && c.name.starts_with(name))
+    })
+}
+
+/// Reads the number at the start of `s`.
+fn parse_number(s: &str) -> (Option<usize>, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    (s[..end].parse().ok(), &s[end..])
+}
+
+/// Reads a pattern up to an unescaped `delimiter`, which may be left off
+/// at the end of the line.
+pub fn parse_pattern(s: &str, delimiter: char) -> (String, &str) {
+    let mut pattern = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if c == delimiter => return (pattern, &s[i + 1..]),
+            '\\' => match chars.next() {
+                Some((_, next)) if next == delimiter => pattern.push(next),
+                Some((_, next)) => {
+                    pattern.push('\\');
+                    pattern.push(next);
+                }
+                None => pattern.push('\\'),
+            },
+            _ => pattern.push(c),
+        }
+    }
+    (pattern, "")
+}
+
+/// Compiles a pattern from the command line. Regex errors point at the
+/// pattern over several lines, so only their last line is kept.
+pub fn compile_pattern(pattern: &str) -> Result<Regex, ExError> {
+    Regex::new(pattern).map_err(|e| {
+        let message = e.to_string();
+        let last = message.lines().last().unwrap_or_default();
+        ExError::InvalidPattern(last.trim_start_matches("error: ").to_string())
+    })
+}
+
+/// Reads a line address from the start of `s`, if there is one.
+fn parse_address(s: &str) -> Result<Option<(Address, &str)>, ExError> {
+    let s = s.trim_start();
+    let mut chars = s.chars();
+    let (base, mut rest) = match chars.next() {
+        Some('0'..='9') => {
+            let (n, rest) = parse_number(s);
+            (AddressBase::Number(n.ok_or(ExError::InvalidRange)?), rest)
+        }
+        Some('.') => (AddressBase::Current, &s[1..]),
+        Some('$') => (AddressBase::Last, &s[1..]),
+        Some('\'') => {
+            let c = chars.next().ok_or(ExError::InvalidRange)?;
+            (AddressBase::Mark(c), &s[1 + c.len_utf8()..])
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, rest) = parse_pattern(&s[1..], delimiter);
+            let forward = delimiter == '/';
+            (AddressBase::Search { pattern, forward }, rest)
+        }
+        Some('+' | '-') => (AddressBase::Current, s),
+        _ => return Ok(None),
+    };
+    let mut offset = 0;
+    while let Some(sign @ ('+' | '-')) = rest.chars().next() {
+        let (n, after) = parse_number(&rest[1..]);
+        let n = n.unwrap_or(1) as isize;
+        offset += if sign == '+' { n } else { -n };
+        rest = after;
+    }
+    let address = Address {
+        base,
+        offset,
+        semicolon: false,
+    };
+    Ok(Some((address, rest)))
+}
+
+/// Reads the addresses of the range in front of a command, and what
+/// follows them.
+fn parse_range(s: &str) -> Result<(Vec<Address>, &str), ExError> {
+    let s = s.trim_start();
+    let at = |base| Address {
+        base,
+        offset: 0,
+        semicolon: false,
+    };
+    if let Some(rest) = s.strip_prefix('%') {
+        return Ok((
+            vec![at(AddressBase::Number(1)), at(AddressBase::Last)],
+            rest,
+        ));
+    }
+    let mut addresses = Vec::new();
+    let mut rest = s;
+    let mut semicolon = false;
+    loop {
+        let address = match parse_address(rest)? {
+            Some((address, after)) => {
+                rest = after.trim_start();
+                Some(address)
+            }
+            None => None,
+        };
+        match rest.chars().next() {
+            // a missing address next to a separator is the cursor line
+            Some(separator @ (',' | ';')) => {
+                let address = address.unwrap_or_else(|| at(AddressBase::Current));
+                addresses.push(Address {
+                    semicolon,
+                    ..address
+                });
+                semicolon = separator == ';';
+                rest = &rest[1..];
+            }
+            _ => {
+                let address = match address {
+                    Some(address) => address,
+                    None if !addresses.is_empty() => at(AddressBase::Current),
+                    None => return Ok((addresses, rest)),
+                };
+                addresses.push(Address {
+                    semicolon,
+                    ..address
+                });
+                return Ok((addresses, rest));
+            }
+        }
+    }
+}
+
+/// The part of a command line after its range, for completion to find the
+/// command name in.
+pub fn skip_ex_range(s: &str) -> &str {
+    parse_range(s).map_or(s, |(_, rest)| rest)
+}
+
+/// Splits the argument of a command that `|` ends from the commands after
+/// it, turning `\|` into `|`.
+fn split_bar(s: &str) -> (String, Option<&str>) {
+    let mut arg = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '|' => return (arg, Some(&s[i + 1..])),
+            '\\' if s[i + 1..].starts_with('|') => {
+                chars.next();
+                arg.push('|');
+            }
+            _ => arg.push(c),
+        }
+    }
+    (arg, None)
+}
+
+impl Editor {
+    /// The 1-based line `address` refers to, searching from line `from`.
+    /// Line 0 is before the first line.
+    fn resolve_address(&self, address: &Address, from: usize) -> Result<usize, ExError> {
+        let line = match address.base {
+            AddressBase::Number(n) => n,
+            AddressBase::Current => from + 1,
+            AddressBase::Last => self.content.len(),
+            AddressBase::Mark(c) => self.mark_line(c).ok_or(ExError::MarkNotSet(c))? + 1,
+            AddressBase::Search {
+                ref pattern,
+                forward,
+            } => {
+                let regex = compile_pattern(pattern)?;
+                let len = self.content.len();
+                // the search starts on the next line and wraps around
+                (1..=len)
+                    .map(|i| match forward {
+                        true => (from + i) % len,
+                        false => (from + len - i) % len,
+                    })
+                    .find(|&y| regex.is_match(&self.content[y]))
+                    .ok_or_else(|| ExError::PatternNotFound(pattern.clone()))?
+                    + 1
+            }
+        };
+        line.checked_add_signed(address.offset)
+            .ok_or(ExError::InvalidRange)
+    }
+
+    /// Resolves a line address at the start of `s`, like the target of
+    /// `:m` or `:t`, returning the 1-based line and what follows it.
+    pub fn ex_address<'a>(&self, s: &'a str) -> Result<(usize, &'a str), ExError> {
+        let (address, rest) = parse_address(s)?.ok_or(ExError::InvalidRange)?;
+        let line = self.resolve_address(&address, self.cursor_y)?;
+        match line <= self.content.len() {
+            true => Ok((line, rest)),
+            false => Err(ExError::InvalidRange),
+        }
+    }
+
+    /// Parses and resolves the first command of `line`, returning it and
+    /// the rest of the line after a `|`.
+    fn parse_ex<'a>(&self, line: &'a str) -> Result<(ExLine, Option<&'a str>), ExError> {
+        let (addresses, rest) = parse_range(line.trim_start_matches([' ', ':']))?;
+        let mut lines = Vec::new();
+        let mut from = self.cursor_y;
+        for address in &addresses {
+            if address.semicolon
+                && let Some(&last) = lines.last()
+            {
+                from = std::cmp::max(last, 1) - 1;
+            }
+            lines.push(self.resolve_address(address, from)?);
+        }
+
+        let rest = rest.trim_start();
+        let name_len = match rest.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() => rest
+                .find(|c: char| !c.is_ascii_alphabetic())
+                .unwrap_or(rest.len()),
+            Some('|') | None => 0,
+            Some(c) => c.len_utf8(),
+        };
+        let (name, rest) = rest.split_at(name_len);
+        if name.is_empty() {
+            let next = rest.strip_prefix('|');
+            if next.is_none() && !rest.trim().is_empty() {
+                return Err(ExError::TrailingCharacters(rest.trim().to_string()));
+            }
+            let parsed = match lines.last() {
+                Some(&line) => ExLine::Goto(line.saturating_sub(1)),
+                None => ExLine::Empty,
+            };
+            return Ok((parsed, next));
+        }
+
+        let command =
+            find_ex_command(name).ok_or_else(|| ExError::UnknownCommand(name.to_string()))?;
+        let (bang, rest) = match rest.strip_prefix('!') {
+            Some(rest) if command.bang => (true, rest),
+            Some(_) => return Err(ExError::NoBang),
+            None => (false, rest),
+        };
+        let (arg, next) = match command.bar {
+            true => split_bar(rest),
+            false => (rest.to_string(), None),
+        };
+
+        let range = match lines.len() {
+            0 => None,
+            _ if !command.range => return Err(ExError::NoRange),
+            n => {
+                let (start, end) = match n {
+                    1 => (lines[0], lines[0]),
+                    _ => (lines[n - 2], lines[n - 1]),
+                };
+                let (start, end) = (std::cmp::min(start, end), std::cmp::max(start, end));
+                if end > self.content.len() {
+                    return Err(ExError::InvalidRange);
+                }
+                // line 0 is the first line for commands that don't insert
+                Some(start.saturating_sub(1)..std::cmp::max(end, 1))
+            }
+        };
+        let call = ExCall {
+            command,
+            range,
+            line_zero: lines.last() == Some(&0),
+            bang,
+            arg: arg.trim().to_string(),
+        };
+        Ok((ExLine::Call(call), next))
+    }
+
+    /// Runs a line of ex commands separated by `|`, stopping at the first
+    /// error. Each command is parsed after the one before it has run, so
+    /// its range sees the cursor where that one left it.
+    pub fn run_ex(&mut self, line: &str) -> Result<(), ExError> {
+        let mut rest = Some(line);
+        while let Some(line) = rest {
+            let (parsed, next) = self.parse_ex(line)?;
+            match parsed {
+                ExLine::Empty => {}
+                ExLine::Goto(y) => self.goto_line(y),
+                ExLine::Call(call) => (call.command.run)(self, &call)?,
+            }
+            rest = next;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    /// An editor on lines `1` to `10`, with the cursor on line 4.
+    fn ten_lines() -> Editor {
+        let text: String = (1..=10).map(|n| format!("{}\n", n)).collect();
+        let mut editor = editor_with(&text);
+        editor.cursor_y = 3;
+        editor
+    }
+
+    /// The lines a `:d` with `range` in front of it covers.
+    fn range(editor: &Editor, range: &str) -> Result<Option<Range<usize>>, ExError> {
+        match editor.parse_ex(&format!("{}d", range))?.0 {
+            ExLine::Call(call) => Ok(call.range),
+            _ => panic!("{}d is not a command", range),
+        }
+    }
+
+    #[test]
+    fn addresses_add_up_their_offsets() {
+        let editor = ten_lines();
+        assert_eq!(range(&editor, "").unwrap(), None);
+        assert_eq!(range(&editor, ".").unwrap(), Some(3..4));
+        assert_eq!(range(&editor, "+").unwrap(), Some(4..5));
+        assert_eq!(range(&editor, ".-1-1").unwrap(), Some(1..2));
+        assert_eq!(range(&editor, ".,.+2").unwrap(), Some(3..6));
+        assert_eq!(range(&editor, "$-2,$").unwrap(), Some(7..10));
+        assert_eq!(range(&editor, "%").unwrap(), Some(0..10));
+        assert_eq!(range(&editor, ",5").unwrap(), Some(3..5));
+        assert_eq!(range(&editor, "2;+2").unwrap(), Some(1..4));
+        assert_eq!(range(&editor, "2,+2").unwrap(), Some(1..6));
+        // line 0 is the first line for commands that don't insert
+        assert_eq!(range(&editor, "0").unwrap(), Some(0..1));
+    }
+
+    #[test]
+    fn addresses_outside_the_buffer_are_invalid() {
+        let editor = ten_lines();
+        assert!(matches!(range(&editor, "11"), Err(ExError::InvalidRange)));
+        assert!(matches!(range(&editor, "$+1"), Err(ExError::InvalidRange)));
+        assert!(matches!(range(&editor, ".-5"), Err(ExError::InvalidRange)));
+        assert!(matches!(
+            range(&editor, "'z"),
+            Err(ExError::MarkNotSet('z'))
+        ));
+    }
+
+    #[test]
+    fn reversed_ranges_are_swapped() {
+        let editor = ten_lines();
+        assert_eq!(range(&editor, "5,2").unwrap(), Some(1..5));
+        assert_eq!(range(&editor, "$,.").unwrap(), Some(3..10));
+    }
+
+    #[test]
+    fn pattern_addresses_search_from_the_cursor() {
+        let editor = ten_lines();
+        assert_eq!(range(&editor, "/1/").unwrap(), Some(9..10));
+        assert_eq!(range(&editor, "?2?").unwrap(), Some(1..2));
+        // the search wraps around the end of the buffer
+        assert_eq!(range(&editor, "/3/").unwrap(), Some(2..3));
+        assert_eq!(range(&editor, "/5/;/7/").unwrap(), Some(4..7));
+        assert!(matches!(
+            range(&editor, "/x/"),
+            Err(ExError::PatternNotFound(pattern)) if pattern == "x"
+        ));
+        assert!(matches!(
+            range(&editor, "/(/"),
+            Err(ExError::InvalidPattern(_))
+        ));
+    }
+
+    #[test]
+    fn bar_separates_commands_but_not_escaped_or_in_patterns() {
+        assert_eq!(split_bar("a b"), ("a b".to_string(), None));
+        assert_eq!(split_bar("a | b"), ("a ".to_string(), Some(" b")));
+        assert_eq!(split_bar(r"a \| b | c"), ("a | b ".to_string(), Some(" c")));
+
+        let mut editor = ten_lines();
+        editor.run_ex("/5|6/d | 1d").unwrap();
+        assert_eq!(editor.text(), "2\n3\n4\n6\n7\n8\n9\n10\n");
+
+        // :g takes the rest of the line, bars and all
+        let mut editor = ten_lines();
+        editor.run_ex("g/1|2/d").unwrap();
+        assert_eq!(editor.text(), "3\n4\n5\n6\n7\n8\n9\n");
+
+        let mut editor = ten_lines();
+        editor.run_ex("2|d").unwrap();
+        assert_eq!(editor.text(), "1\n3\n4\n5\n6\n7\n8\n9\n10\n");
+    }
+
+    #[test]
+    fn abbreviations_pick_the_command_that_owns_them() {
+        let name = |abbrev| find_ex_command(abbrev).map(|command| command.name);
+        assert_eq!(name("d"), Some("delete"));
+        assert_eq!(name("de"), Some("delete"));
+        assert_eq!(name("co"), Some("copy"));
+        assert_eq!(name("colo"), Some("colorscheme"));
+        assert_eq!(name("no"), Some("noremap"));
+        assert_eq!(name("norm"), Some("normal"));
+        assert_eq!(name("r"), Some("read"));
+        assert_eq!(name("reg"), Some("registers"));
+        assert_eq!(name("ren"), Some("rename"));
+        assert_eq!(name("se"), Some("set"));
+        assert_eq!(name("sor"), Some("sort"));
+        assert_eq!(name("sy"), Some("syntax"));
+        assert_eq!(name("t"), Some("t"));
+        assert_eq!(name("wq"), Some("wq"));
+        // too short to tell apart
+        assert_eq!(name("s"), None);
+        assert_eq!(name("l"), None);
+        assert_eq!(name("col"), None);
+        assert_eq!(name("deletes"), None);
+        assert!(matches!(
+            ten_lines().run_ex("frob"),
+            Err(ExError::UnknownCommand(name)) if name == "frob"
+        ));
+    }
+
+    #[test]
+    fn quit_refuses_to_drop_changes_without_bang() {
+        let mut editor = ten_lines();
+        editor.run_ex("q").unwrap();
+        assert!(editor.should_quit);
+
+        let mut editor = ten_lines();
+        editor.type_keys("x");
+        assert_eq!(
+            editor.run_ex("q").unwrap_err().to_string(),
+            "No write since last change (add ! to override)"
+        );
+        assert!(!editor.should_quit);
+        editor.run_ex("q!").unwrap();
+        assert!(editor.should_quit);
+    }
+}
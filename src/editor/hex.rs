@@ -0,0 +1,280 @@
+use crate::*;
+use crossterm::event::{KeyCode, KeyEvent};
+
+pub const BYTES_PER_ROW: usize = 16;
+const OFFSET_WIDTH: usize = 10; // "00000000: "
+
+pub struct HexView {
+    pub bytes: Vec<u8>,
+    pub cursor: usize,
+    pub low_nibble: bool,
+    pub offset_row: usize,
+}
+
+impl HexView {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        HexView {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+            offset_row: 0,
+        }
+    }
+
+    /// Overwrites the nibble under the cursor, appending a byte when the
+    /// cursor sits one past the end, and advances to the next nibble.
+    pub fn set_nibble(&mut self, value: u8) {
+        if self.cursor >= self.bytes.len() {
+            self.bytes.push(0);
+        }
+        let byte = &mut self.bytes[self.cursor];
+        if self.low_nibble {
+            *byte = (*byte & 0xf0) | value;
+            self.low_nibble = false;
+            self.cursor += 1;
+        } else {
+            *byte = (*byte & 0x0f) | (value << 4);
+            self.low_nibble = true;
+        }
+    }
+
+    /// Deletes the byte under the cursor, returning whether there was one.
+    pub fn delete_byte(&mut self) -> bool {
+        self.low_nibble = false;
+        if self.cursor < self.bytes.len() {
+            self.bytes.remove(self.cursor);
+            return true;
+        }
+        false
+    }
+
+    pub fn move_cursor(&mut self, direction: KeyCode) {
+        // one past the last byte is a valid position so bytes can be appended
+        let last = self.bytes.len();
+        match direction {
+            KeyCode::Left if self.low_nibble => self.low_nibble = false,
+            KeyCode::Left if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.low_nibble = true;
+            }
+            KeyCode::Right if !self.low_nibble && self.cursor < last => self.low_nibble = true,
+            KeyCode::Right if self.cursor < last => {
+                self.cursor += 1;
+                self.low_nibble = false;
+            }
+            KeyCode::Up if self.cursor >= BYTES_PER_ROW => self.cursor -= BYTES_PER_ROW,
+            KeyCode::Down => self.cursor = std::cmp::min(self.cursor + BYTES_PER_ROW, last),
+            KeyCode::Home => {
+                self.cursor -= self.cursor % BYTES_PER_ROW;
+                self.low_nibble = false;
+            }
+            KeyCode::End => {
+                let row_end = self.cursor - self.cursor % BYTES_PER_ROW + BYTES_PER_ROW - 1;
+                self.cursor = std::cmp::min(row_end, last);
+                self.low_nibble = false;
+            }
+            _ => {}
+        }
+        if self.cursor == last {
+            self.low_nibble = false;
+        }
+    }
+
+    pub fn scroll(&mut self, screen_rows: usize) {
+        let row = self.cursor / BYTES_PER_ROW;
+        if row < self.offset_row {
+            self.offset_row = row;
+        } else if row >= self.offset_row + screen_rows {
+            self.offset_row = row - screen_rows + 1;
+        }
+    }
+
+    /// Formats one `offset: hex bytes |ascii|` row, or `None` past the end.
+    pub fn format_row(&self, row: usize) -> Option<String> {
+        let start = row * BYTES_PER_ROW;
+        let len = self.bytes.len();
+        if start > len || (start == len && start != 0 && self.cursor != len) {
+            return None;
+        }
+        let end = std::cmp::min(start + BYTES_PER_ROW, self.bytes.len());
+        let chunk = &self.bytes[start..end];
+
+        let mut line = format!("{:08x}: ", start);
+        for i in 0..BYTES_PER_ROW {
+            if i == BYTES_PER_ROW / 2 {
+                line.push(' ');
+            }
+            match chunk.get(i) {
+                Some(b) => line.push_str(&format!("{:02x} ", b)),
+                None => line.push_str("   "),
+            }
+        }
+        line.push('|');
+        for &b in chunk {
+            line.push(if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            });
+        }
+        line.push('|');
+        Some(line)
+    }
+
+    /// Screen row (0-based, relative to the view) and 1-based column of the cursor.
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let row = self.cursor / BYTES_PER_ROW - self.offset_row;
+        let col_in_row = self.cursor % BYTES_PER_ROW;
+        let gap = if col_in_row >= BYTES_PER_ROW / 2 {
+            1
+        } else {
+            0
+        };
+        let col = OFFSET_WIDTH + col_in_row * 3 + gap + self.low_nibble as usize + 1;
+        (row, col)
+    }
+}
+
+impl Editor {
+    pub fn toggle_hex(&mut self) {
+        match self.hex.take() {
+            Some(view) => match String::from_utf8(view.bytes) {
+                Ok(text) => {
+                    self.set_content(&text);
+                    self.mode = Mode::Normal;
+                }
+                Err(e) => {
+                    self.hex = Some(HexView::new(e.into_bytes()));
+                    self.set_status("Buffer is not valid UTF-8, staying in hex mode");
+                }
+            },
+            None => {
+                let mut text = self.content.join("\n");
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                self.hex = Some(HexView::new(text.into_bytes()));
+                self.mode = Mode::Hex;
+            }
+        }
+    }
+
+    pub fn handle_hex_key(&mut self, key_event: KeyEvent) {
+        let Some(view) = self.hex.as_mut() else {
+            return;
+        };
+        match key_event.code {
+            KeyCode::Char(c) if c.is_ascii_hexdigit() => {
+                view.set_nibble(c.to_digit(16).unwrap() as u8);
+                self.changes += 1;
+            }
+            KeyCode::Char('x') | KeyCode::Delete => {
+                if view.delete_byte() {
+                    self.changes += 1;
+                }
+            }
+            KeyCode::Char('h') => view.move_cursor(KeyCode::Left),
+            KeyCode::Char('j') => view.move_cursor(KeyCode::Down),
+            KeyCode::Char('k') => view.move_cursor(KeyCode::Up),
+            KeyCode::Char('l') => view.move_cursor(KeyCode::Right),
+            KeyCode::Char('g') => {
+                view.cursor = 0;
+                view.low_nibble = false;
+            }
+            KeyCode::Char('G') => {
+                view.cursor = view.bytes.len().saturating_sub(1);
+                view.low_nibble = false;
+            }
+            code => view.move_cursor(code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+    use std::fs;
+
+    /// Opens a file that isn't valid UTF-8, so it comes up in hex mode.
+    fn binary_file(name: &str) -> (Editor, String) {
+        let path = std::env::temp_dir().join(format!("eep-hex-{}-{}", name, std::process::id()));
+        fs::write(&path, [0xff, 0x00, b'a']).unwrap();
+        let path = path.to_string_lossy().into_owned();
+        let mut editor = editor_with("");
+        editor.open_file(&path).unwrap();
+        (editor, path)
+    }
+
+    #[test]
+    fn toggling_converts_between_text_and_bytes() {
+        let mut editor = editor_with("hi\n");
+        editor.run_ex("hex").unwrap();
+        assert!(editor.mode == Mode::Hex);
+        assert_eq!(editor.hex.as_ref().unwrap().bytes, b"hi\n");
+        assert!(!editor.modified());
+
+        editor.type_keys("49");
+        assert!(editor.modified());
+        editor.run_ex("hex").unwrap();
+        assert!(editor.mode == Mode::Normal);
+        assert_eq!(editor.text(), "Ii\n");
+        assert!(editor.modified());
+    }
+
+    #[test]
+    fn invalid_utf8_stays_in_hex_mode() {
+        let (mut editor, path) = binary_file("invalid");
+        assert!(editor.mode == Mode::Hex);
+        editor.run_ex("hex").unwrap();
+        assert!(editor.hex.is_some());
+        assert_eq!(
+            editor.status_msg,
+            "Buffer is not valid UTF-8, staying in hex mode"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edits_are_changes_until_saved() {
+        let (mut editor, path) = binary_file("save");
+        assert!(!editor.modified());
+        editor.type_keys("41");
+        assert!(editor.modified());
+        // moving about is not an edit
+        editor.type_keys("lhG");
+        editor.type_keys("x");
+        assert_eq!(editor.hex.as_ref().unwrap().bytes, [0x41, 0x00]);
+
+        editor.save_file().unwrap();
+        assert!(!editor.modified());
+        assert_eq!(fs::read(&path).unwrap(), [0x41, 0x00]);
+        // deleting past the end changes nothing
+        editor.type_keys("lx");
+        assert!(!editor.modified());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn edit_refuses_to_drop_hex_changes() {
+        let (mut editor, path) = binary_file("refuse");
+        let other = format!("{}-other", path);
+        fs::write(&other, "text\n").unwrap();
+        editor.type_keys("x");
+        assert_eq!(
+            editor
+                .run_ex(&format!("e {}", other))
+                .unwrap_err()
+                .to_string(),
+            "No write since last change (add ! to override)"
+        );
+        assert!(editor.switch_file(&other).is_err());
+        assert_eq!(editor.hex.as_ref().unwrap().bytes, [0x00, b'a']);
+
+        editor.run_ex(&format!("e! {}", other)).unwrap();
+        assert!(editor.hex.is_none());
+        assert_eq!(editor.text(), "text\n");
+        fs::remove_file(path).unwrap();
+        fs::remove_file(other).unwrap();
+    }
+}
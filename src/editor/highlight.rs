@@ -0,0 +1,571 @@
+use crate::*;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const BUILTIN_GRAMMARS: &[(&str, &str)] = &[
+    ("rust", include_str!("grammars/rust.toml")),
+    ("toml", include_str!("grammars/toml.toml")),
+    ("markdown", include_str!("grammars/markdown.toml")),
+    ("json", include_str!("grammars/json.toml")),
+    ("shell", include_str!("grammars/shell.toml")),
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GrammarFile {
+    name: String,
+    #[serde(default)]
+    extensions: Vec<String>,
+    #[serde(default)]
+    filenames: Vec<String>,
+    #[serde(default)]
+    shebangs: Vec<String>,
+    pairs: Option<String>,
+    states: HashMap<String, StateFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StateFile {
+    scope: Option<String>,
+    #[serde(default)]
+    single_line: bool,
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(rename = "match")]
+    pattern: String,
+    scope: Option<String>,
+    push: Option<String>,
+    #[serde(default)]
+    pop: bool,
+}
+
+struct Rule {
+    regex: Regex,
+    scope: Option<&'static str>,
+    push: Option<usize>,
+    pop: bool,
+}
+
+struct State {
+    scope: Option<&'static str>,
+    single_line: bool,
+    rules: Vec<Rule>,
+}
+
+/// A compiled grammar: a set of states, each a list of regex rules. The
+/// earliest match on a line wins, ties going to the rule listed first. A
+/// rule's scope covers its first capture group if it has one, otherwise
+/// the whole match.
+pub struct Grammar {
+    pub name: String,
+    extensions: Vec<String>,
+    filenames: Vec<String>,
+    shebangs: Vec<String>,
+    /// Characters autopairs closes, as (opener, closer).
+    pub pairs: Vec<(char, char)>,
+    states: Vec<State>,
+}
+
+/// A highlighted byte range of a line, styled with the theme group `scope`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub scope: &'static str,
+}
+
+fn scope_group(name: &str) -> Result<&'static str, String> {
+    GROUPS
+        .iter()
+        .find(|group| **group == name)
+        .copied()
+        .ok_or_else(|| format!("unknown scope `{}`", name))
+}
+
+impl Grammar {
+    pub fn parse(source: &str, path: PathBuf) -> Result<Grammar, ConfigError> {
+        let file: GrammarFile = parse_toml(source, path.clone())?;
+        let error = |message: String| ConfigError {
+            path: Some(path.clone()),
+            line: None,
+            message,
+        };
+
+        // root is always state 0 so every line can start from it
+        let mut names: Vec<&String> = file.states.keys().collect();
+        names.sort_by_key(|name| (name.as_str() != "root", name.as_str()));
+        if names.first().is_none_or(|name| name.as_str() != "root") {
+            return Err(error("grammar has no `root` state".to_string()));
+        }
+        let index = |name: &str| names.iter().position(|n| n.as_str() == name);
+
+        let mut states = Vec::new();
+        for name in &names {
+            let state = &file.states[*name];
+            let mut rules = Vec::new();
+            for rule in &state.rules {
+                let regex = Regex::new(&rule.pattern).map_err(|e| {
+                    let detail = e.to_string();
+                    error(format!(
+                        "state `{}`: bad pattern '{}': {}",
+                        name,
+                        rule.pattern,
+                        detail.lines().last().unwrap_or_default().trim()
+                    ))
+                })?;
+                let push = match rule.push {
+                    Some(ref target) => Some(index(target).ok_or_else(|| {
+                        error(format!(
+                            "state `{}` pushes unknown state `{}`",
+                            name, target
+                        ))
+                    })?),
+                    None => None,
+                };
+                rules.push(Rule {
+                    regex,
+                    scope: rule
+                        .scope
+                        .as_deref()
+                        .map(scope_group)
+                        .transpose()
+                        .map_err(error)?,
+                    push,
+                    pop: rule.pop,
+                });
+            }
+            states.push(State {
+                scope: state
+                    .scope
+                    .as_deref()
+                    .map(scope_group)
+                    .transpose()
+                    .map_err(error)?,
+                single_line: state.single_line,
+                rules,
+            });
+        }
+
+        Ok(Grammar {
+            name: file.name,
+            extensions: file.extensions,
+            filenames: file.filenames,
+            shebangs: file.shebangs,
+            pairs: match file.pairs {
+                Some(ref pairs) => parse_pairs(pairs).map_err(error)?,
+                None => DEFAULT_PAIRS.to_vec(),
+            },
+            states,
+        })
+    }
+
+    pub fn builtin() -> Vec<Rc<Grammar>> {
+        BUILTIN_GRAMMARS
+            .iter()
+            .map(|(name, source)| {
+                let path = format!("<builtin>/{}.toml", name).into();
+                Rc::new(Grammar::parse(source, path).expect("built-in grammar is valid"))
+            })
+            .collect()
+    }
+
+    /// Whether this grammar applies to `path`, judging by its file name,
+    /// extension, or the interpreter named on a `#!` first line.
+    pub fn matches(&self, path: &Path, first_line: &str) -> bool {
+        let file_name = path.file_name().map(|n| n.to_string_lossy());
+        if file_name.is_some_and(|n| self.filenames.iter().any(|f| *f == n)) {
+            return true;
+        }
+        let extension = path.extension().map(|e| e.to_string_lossy());
+        if extension.is_some_and(|e| self.extensions.iter().any(|ext| *ext == e)) {
+            return true;
+        }
+        let Some(command) = first_line.strip_prefix("#!") else {
+            return false;
+        };
+        let mut words = command.split_whitespace();
+        let interpreter = match words.next().map(|w| w.rsplit('/').next().unwrap_or(w)) {
+            Some("env") => words.find(|w| !w.starts_with('-')),
+            other => other,
+        };
+        interpreter.is_some_and(|i| self.shebangs.iter().any(|s| s == i))
+    }
+
+    /// Highlights one line starting in the state on top of `stack`, leaving
+    /// the state the next line starts in.
+    pub fn highlight_line(&self, line: &str, stack: &mut Vec<usize>) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let mut push_span = |start: usize, end: usize, scope: Option<&'static str>| {
+            if let Some(scope) = scope
+                && start < end
+            {
+                spans.push(Span { start, end, scope });
+            }
+        };
+
+        let mut pos = 0;
+        while pos < line.len() {
+            let state = &self.states[*stack.last().unwrap_or(&0)];
+            let found = state
+                .rules
+                .iter()
+                .filter_map(|rule| rule.regex.find_at(line, pos).map(|m| (rule, m)))
+                .min_by_key(|(_, m)| m.start());
+
+            let Some((rule, m)) = found else {
+                push_span(pos, line.len(), state.scope);
+                break;
+            };
+            push_span(pos, m.start(), state.scope);
+
+            let scope = rule.scope.or(state.scope);
+            match rule
+                .regex
+                .captures_at(line, m.start())
+                .and_then(|caps| caps.get(1))
+            {
+                Some(group) => {
+                    push_span(m.start(), group.start(), state.scope);
+                    push_span(group.start(), group.end(), scope);
+                    push_span(group.end(), m.end(), state.scope);
+                }
+                None => push_span(m.start(), m.end(), scope),
+            }
+
+            if rule.pop && stack.len() > 1 {
+                stack.pop();
+            }
+            if let Some(target) = rule.push {
+                stack.push(target);
+            }
+
+            pos = if m.end() > pos {
+                m.end()
+            } else {
+                // an empty match must not stall the scan
+                let next = line.ceil_char_boundary(pos + 1);
+                push_span(pos, next, state.scope);
+                next
+            };
+        }
+
+        while stack.len() > 1 && self.states[*stack.last().unwrap()].single_line {
+            stack.pop();
+        }
+        spans
+    }
+}
+
+struct LineCache {
+    end: Vec<usize>,
+    spans: Vec<Span>,
+    dirty: bool,
+}
+
+impl LineCache {
+    fn dirty() -> Self {
+        LineCache {
+            end: Vec::new(),
+            spans: Vec::new(),
+            dirty: true,
+        }
+    }
+}
+
+/// Per-buffer highlighting state. Lines are only re-highlighted when they
+/// were edited, or when the state they start in changed because of an edit
+/// above them, and only once they are about to be drawn.
+pub struct Highlighter {
+    pub grammar: Rc<Grammar>,
+    lines: Vec<LineCache>,
+    first_dirty: usize,
+}
+
+impl Highlighter {
+    pub fn new(grammar: Rc<Grammar>, line_count: usize) -> Self {
+        Highlighter {
+            grammar,
+            lines: (0..line_count).map(|_| LineCache::dirty()).collect(),
+            first_dirty: 0,
+        }
+    }
+
+    /// Records that lines `start..start + removed` were replaced by
+    /// `inserted` new lines.
+    pub fn edit(&mut self, start: usize, removed: usize, inserted: usize) {
+        let end = std::cmp::min(start + removed, self.lines.len());
+        let start = std::cmp::min(start, end);
+        self.lines
+            .splice(start..end, (0..inserted).map(|_| LineCache::dirty()));
+        // the line after the edit may now start in a different state
+        if let Some(next) = self.lines.get_mut(start + inserted) {
+            next.dirty = true;
+        }
+        self.first_dirty = std::cmp::min(self.first_dirty, start);
+    }
+
+    /// Brings lines up to and including `last` up to date.
+    pub fn update(&mut self, content: &[String], last: usize) {
+        let last = std::cmp::min(last, content.len().saturating_sub(1));
+        let mut y = self.first_dirty;
+        while y <= last && y < self.lines.len() {
+            let mut stack = match y {
+                0 => vec![0],
+                _ => self.lines[y - 1].end.clone(),
+            };
+            let spans = self.grammar.highlight_line(&content[y], &mut stack);
+            let line = &mut self.lines[y];
+            let changed = line.end != stack;
+            *line = LineCache {
+                end: stack,
+                spans,
+                dirty: false,
+            };
+            if changed && let Some(next) = self.lines.get_mut(y + 1) {
+                next.dirty = true;
+            }
+            y += 1;
+            match self.lines[y.min(self.lines.len())..]
+                .iter()
+                .position(|line| line.dirty)
+            {
+                Some(offset) => y += offset,
+                None => y = self.lines.len(),
+            }
+        }
+        self.first_dirty = y;
+    }
+
+    pub fn spans(&self, y: usize) -> &[Span] {
+        match self.lines.get(y) {
+            Some(line) if !line.dirty => &line.spans,
+            _ => &[],
+        }
+    }
+
+    /// The innermost scope at byte `x` of line `y`, if it is highlighted.
+    pub fn scope_at(&self, y: usize, x: usize) -> Option<&'static str> {
+        self.spans(y)
+            .iter()
+            .rev()
+            .find(|span| span.start <= x && x < span.end)
+            .map(|span| span.scope)
+    }
+}
+
+impl Editor {
+    /// Loads grammars from the config directory's `grammars` folder. A user
+    /// grammar with the same name as a built-in one replaces it.
+    pub fn load_user_grammars(&mut self) -> Result<(), ConfigError> {
+        let Some(Ok(entries)) = config_dir().map(|dir| fs::read_dir(dir.join("grammars"))) else {
+            return Ok(());
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "toml") {
+                continue;
+            }
+            let source = fs::read_to_string(&path).map_err(|e| ConfigError {
+                path: Some(path.clone()),
+                line: None,
+                message: e.to_string(),
+            })?;
+            let grammar = Rc::new(Grammar::parse(&source, path)?);
+            self.grammars.retain(|g| g.name != grammar.name);
+            self.grammars.insert(0, grammar);
+        }
+        Ok(())
+    }
+
+    /// The scope byte `x` of line `y` is highlighted with, bringing the
+    /// highlighting up to date first.
+    pub fn scope_at(&mut self, y: usize, x: usize) -> Option<&'static str> {
+        let highlighter = self.highlighter.as_mut()?;
+        highlighter.update(&self.content, y);
+        highlighter.scope_at(y, x)
+    }
+
+    /// Picks a grammar for the current file and starts highlighting with it.
+    pub fn detect_syntax(&mut self) {
+        let first_line = self.content.first().map(String::as_str).unwrap_or("");
+        let grammar = self.filename.as_ref().and_then(|path| {
+            self.grammars
+                .iter()
+                .find(|g| g.matches(path, first_line))
+                .cloned()
+        });
+        self.highlighter = grammar.map(|g| Highlighter::new(g, self.content.len()));
+        #[cfg(feature = "treesitter")]
+        self.attach_syntax_tree();
+    }
+
+    /// `:syntax name` forces a grammar, `:syntax off` disables highlighting.
+    pub fn set_syntax(&mut self, name: &str) -> Result<(), String> {
+        if name == "off" {
+            self.highlighter = None;
+            #[cfg(feature = "treesitter")]
+            self.attach_syntax_tree();
+            return Ok(());
+        }
+        let grammar = self
+            .grammars
+            .iter()
+            .find(|g| g.name == name)
+            .cloned()
+            .ok_or_else(|| format!("No grammar named '{}'", name))?;
+        self.highlighter = Some(Highlighter::new(grammar, self.content.len()));
+        #[cfg(feature = "treesitter")]
+        self.attach_syntax_tree();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    const GRAMMAR: &str = r#"
+name = "test"
+extensions = ["t"]
+
+[states.root]
+rules = [
+    { match = '/\*', scope = "comment", push = "comment" },
+    { match = '"', scope = "string", push = "string" },
+    { match = '\b(fn)\(', scope = "keyword" },
+]
+
+[states.comment]
+scope = "comment"
+rules = [
+    { match = '/\*', push = "comment" },
+    { match = '\*/', pop = true },
+]
+
+[states.string]
+scope = "string"
+single_line = true
+rules = [{ match = '"', pop = true }]
+"#;
+
+    fn grammar() -> Rc<Grammar> {
+        Rc::new(Grammar::parse(GRAMMAR, PathBuf::from("test.toml")).unwrap())
+    }
+
+    fn span(start: usize, end: usize, scope: &'static str) -> Span {
+        Span { start, end, scope }
+    }
+
+    fn parse_error(source: &str) -> String {
+        match Grammar::parse(source, PathBuf::from("test.toml")) {
+            Ok(_) => panic!("grammar parsed"),
+            Err(e) => e.message,
+        }
+    }
+
+    #[test]
+    fn parse_checks_states_scopes_and_patterns() {
+        let rule = |rule: &str| format!("name = \"x\"\n[states.root]\nrules = [{}]\n", rule);
+        assert_eq!(
+            parse_error("name = \"x\"\n[states.main]\nrules = []\n"),
+            "grammar has no `root` state"
+        );
+        assert_eq!(
+            parse_error(&rule("{ match = 'a', push = 'nowhere' }")),
+            "state `root` pushes unknown state `nowhere`"
+        );
+        assert_eq!(
+            parse_error(&rule("{ match = 'a', scope = 'bogus' }")),
+            "unknown scope `bogus`"
+        );
+        assert!(parse_error(&rule("{ match = '(' }")).starts_with("state `root`: bad pattern '('"));
+    }
+
+    #[test]
+    fn rules_push_and_pop_states() {
+        let grammar = grammar();
+        let mut stack = vec![0];
+        let spans = grammar.highlight_line("a /* b /* c */ d */ fn(x)", &mut stack);
+        assert_eq!(
+            spans,
+            [
+                span(2, 4, "comment"),
+                span(4, 7, "comment"),
+                span(7, 9, "comment"),
+                span(9, 12, "comment"),
+                span(12, 14, "comment"),
+                span(14, 17, "comment"),
+                span(17, 19, "comment"),
+                // only the capture group of a rule is scoped
+                span(20, 22, "keyword"),
+            ]
+        );
+        assert_eq!(stack, [0]);
+
+        // an unclosed comment carries over to the next line
+        grammar.highlight_line("x /* y", &mut stack);
+        assert_eq!(stack.len(), 2);
+        let spans = grammar.highlight_line("z */ fn(", &mut stack);
+        assert_eq!(spans[0], span(0, 2, "comment"));
+        assert_eq!(spans.last(), Some(&span(5, 7, "keyword")));
+        assert_eq!(stack, [0]);
+    }
+
+    #[test]
+    fn single_line_states_end_with_the_line() {
+        let grammar = grammar();
+        let mut stack = vec![0];
+        let spans = grammar.highlight_line("s = \"open", &mut stack);
+        assert_eq!(spans, [span(4, 5, "string"), span(5, 9, "string")]);
+        assert_eq!(stack, [0]);
+        assert!(grammar.highlight_line("fn(", &mut stack)[0] == span(0, 2, "keyword"));
+    }
+
+    #[test]
+    fn edits_rehighlight_the_lines_after_them() {
+        let mut content: Vec<String> = ["fn(a)", "b", "fn(c)"].map(String::from).to_vec();
+        let mut highlighter = Highlighter::new(grammar(), content.len());
+        assert!(highlighter.spans(2).is_empty());
+        highlighter.update(&content, 2);
+        assert_eq!(highlighter.scope_at(2, 0), Some("keyword"));
+
+        // opening a comment changes the state every later line starts in
+        content[0] = "/* fn(a)".to_string();
+        highlighter.edit(0, 1, 1);
+        highlighter.update(&content, 0);
+        assert_eq!(highlighter.scope_at(0, 3), Some("comment"));
+        assert!(highlighter.spans(1).is_empty());
+        highlighter.update(&content, 2);
+        assert_eq!(highlighter.scope_at(1, 0), Some("comment"));
+        assert_eq!(highlighter.spans(2), [span(0, 5, "comment")]);
+
+        // closing it again, on an inserted line, brings the keyword back
+        content.insert(2, "*/".to_string());
+        highlighter.edit(2, 0, 1);
+        highlighter.update(&content, 3);
+        assert_eq!(highlighter.scope_at(3, 0), Some("keyword"));
+        assert_eq!(highlighter.scope_at(1, 0), Some("comment"));
+    }
+
+    #[test]
+    fn typing_a_comment_opener_rehighlights_the_buffer() {
+        let mut editor = editor_with("fn main() {}\nlet x = 1;\n");
+        editor.set_syntax("rust").unwrap();
+        assert_eq!(editor.scope_at(1, 0), Some("keyword"));
+        editor.type_keys("O/*<Esc>");
+        assert_eq!(editor.scope_at(2, 0), Some("comment"));
+        editor.type_keys("hx");
+        assert_eq!(editor.text(), "/\nfn main() {}\nlet x = 1;\n");
+        assert_eq!(editor.scope_at(2, 0), Some("keyword"));
+    }
+}
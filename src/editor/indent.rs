@@ -0,0 +1,281 @@
+use crate::*;
+use std::ops::Range;
+
+/// Characters that open a block when they end a line, for smartindent.
+pub const OPENERS: &[char] = &['{', '(', '[', ':'];
+pub const CLOSERS: &[char] = &['}', ')', ']'];
+
+/// The leading whitespace of `line`.
+pub fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+impl Editor {
+    /// How many columns `indent` spans, with tabs reaching the next tab stop.
+    pub fn indent_width(&self, indent: &str) -> usize {
+        let tabstop = self.config.tabstop;
+        indent.chars().fold(0, |col, c| match c {
+            '\t' => col + tabstop - col % tabstop,
+            _ => col + 1,
+        })
+    }
+
+    /// Indentation spanning `width` columns: spaces with expandtab,
+    /// otherwise as many tabs as fit followed by spaces.
+    pub fn indent_string(&self, width: usize) -> String {
+        if self.config.expandtab {
+            return " ".repeat(width);
+        }
+        let tabstop = self.config.tabstop;
+        "\t".repeat(width / tabstop) + &" ".repeat(width % tabstop)
+    }
+
+    /// `indent` with one shiftwidth added.
+    pub fn indented(&self, indent: &str) -> String {
+        self.indent_string(self.indent_width(indent) + self.config.shiftwidth())
+    }
+
+    /// `indent` with one shiftwidth removed.
+    pub fn dedented(&self, indent: &str) -> String {
+        let width = self.indent_width(indent);
+        self.indent_string(width.saturating_sub(self.config.shiftwidth()))
+    }
+
+    /// Guesses the buffer's indentation style: tabs if more lines start
+    /// with a tab than with spaces, otherwise spaces in steps of the most
+    /// common change in indentation between neighbouring lines.
+    pub fn detect_indent(&mut self) {
+        let (mut tabs, mut spaces) = (0, 0);
+        let mut steps = [0; 9];
+        let mut previous = 0;
+        for line in &self.content {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tabs += 1;
+                continue;
+            }
+            let width = line.len() - line.trim_start_matches(' ').len();
+            if width > 0 {
+                spaces += 1;
+            }
+            if let step @ 2..=8 = width.abs_diff(previous) {
+                steps[step] += 1;
+            }
+            previous = width;
+        }
+
+        if tabs > spaces {
+            self.config.expandtab = false;
+            self.config.shiftwidth = 0;
+            self.config.softtabstop = 0;
+        } else if spaces > 0 {
+            let step = (2..=8).max_by_key(|&s| (steps[s], std::cmp::Reverse(s)));
+            if let Some(step) = step.filter(|&s| steps[s] > 0) {
+                self.config.expandtab = true;
+                self.config.shiftwidth = step;
+                self.config.softtabstop = step;
+            }
+        }
+    }
+
+    /// The indentation a new line gets after `previous`: its indent, plus a
+    /// level with smartindent if it ends by opening a block.
+    pub fn indent_after(&self, previous: &str) -> String {
+        let indent = leading_whitespace(previous);
+        if self.config.smartindent && previous.trim_end().ends_with(OPENERS) {
+            return self.indented(indent);
+        }
+        indent.to_string()
+    }
+
+    /// With smartindent, typing a closing bracket as the first thing on a
+    /// line takes a level of indentation off it.
+    pub fn dedent_for_closer(&mut self, c: char) {
+        let line = &self.content[self.cursor_y];
+        if !self.config.smartindent
+            || !CLOSERS.contains(&c)
+            || !line[..self.cursor_x].trim().is_empty()
+            || self.cursor_x == 0
+        {
+            return;
+        }
+        let indent = self.dedented(&line[..self.cursor_x]);
+        let rest = line[self.cursor_x..].to_string();
+        self.cursor_x = indent.len();
+        self.set_line(self.cursor_y, indent + &rest);
+    }
+
+    /// The lines a linewise command applies to: the selection in visual
+    /// mode, which it also ends, otherwise the cursor line and those below
+    /// it for a count.
+    pub fn target_lines(&mut self) -> Range<usize> {
+        if self.mode == Mode::Visual {
+            let ((start, _), (end, _)) = self.selection();
+            self.mode = self.resting_mode();
+            return start..end + 1;
+        }
+        let count = self.take_count().unwrap_or(1);
+        self.cursor_y..std::cmp::min(self.cursor_y + count, self.content.len())
+    }
+
+    fn set_indent(&mut self, y: usize, indent: &str) {
+        let line = &self.content[y];
+        let text = line.trim_start();
+        if text.is_empty() {
+            return;
+        }
+        let new = format!("{}{}", indent, text);
+        if new != *line {
+            self.set_line(y, new);
+        }
+    }
+
+    /// Moves the cursor to the first non-blank character of its line.
+    pub fn cursor_to_indent(&mut self) {
+        let line = &self.content[self.cursor_y];
+        self.cursor_x = leading_whitespace(line).len();
+    }
+
+    /// `>>` and `<<`: shifts lines right or left by one level.
+    pub fn shift_lines(&mut self, right: bool) {
+        let lines = self.target_lines();
+        self.shift_range(lines.clone(), right, 1);
+        self.cursor_y = lines.start;
+        self.cursor_to_indent();
+    }
+
+    /// Shifts `lines` right or left by `levels` levels.
+    pub fn shift_range(&mut self, lines: Range<usize>, right: bool, levels: usize) {
+        for y in lines {
+            let mut indent = leading_whitespace(&self.content[y]).to_string();
+            for _ in 0..levels {
+                indent = if right {
+                    self.indented(&indent)
+                } else {
+                    self.dedented(&indent)
+                };
+            }
+            self.set_indent(y, &indent);
+        }
+    }
+
+    /// `=`: reindents lines from the line above them, a level deeper after a
+    /// line opening a block and a level shallower for a closing bracket.
+    pub fn reindent_lines(&mut self) {
+        let lines = self.target_lines();
+        self.reindent_range(lines);
+    }
+
+    /// Reindents `lines` as `=` does, leaving the cursor on the first.
+    pub fn reindent_range(&mut self, lines: Range<usize>) {
+        for y in lines.clone() {
+            let Some(previous) = (0..y).rev().find(|&p| !self.content[p].trim().is_empty()) else {
+                self.set_indent(y, "");
+                continue;
+            };
+            let mut indent = leading_whitespace(&self.content[previous]).to_string();
+            if self.content[previous].trim_end().ends_with(OPENERS) {
+                indent = self.indented(&indent);
+            }
+            if self.content[y].trim_start().starts_with(CLOSERS) {
+                indent = self.dedented(&indent);
+            }
+            self.set_indent(y, &indent);
+        }
+        self.cursor_y = lines.start;
+        self.cursor_to_indent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn smartindent_after_opener() {
+        let mut editor = editor_with("fn f() {");
+        editor.config.smartindent = true;
+        editor.type_keys("A<CR>x<Esc>");
+        assert_eq!(editor.text(), "fn f() {\n    x\n");
+        // autoindent alone keeps the previous line's indentation
+        editor.config.smartindent = false;
+        editor.type_keys("A<CR>y<Esc>");
+        assert_eq!(editor.text(), "fn f() {\n    x\n    y\n");
+    }
+
+    #[test]
+    fn closer_dedents_with_smartindent() {
+        let mut editor = editor_with("fn f() {");
+        editor.config.smartindent = true;
+        editor.type_keys("A<CR>x<CR>}<Esc>");
+        assert_eq!(editor.text(), "fn f() {\n    x\n}\n");
+        // only as the first thing on the line
+        editor.type_keys("kA)<Esc>");
+        assert_eq!(editor.text(), "fn f() {\n    x)\n}\n");
+    }
+
+    #[test]
+    fn shift_with_count() {
+        let mut editor = editor_with("a\nb\nc\nd");
+        editor.type_keys("3>>");
+        assert_eq!(editor.text(), "    a\n    b\n    c\nd\n");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (0, 4));
+        editor.type_keys("j2<lt><lt>");
+        assert_eq!(editor.text(), "    a\nb\nc\nd\n");
+    }
+
+    #[test]
+    fn reindent_block() {
+        let mut editor = editor_with("fn f() {\n        x;\n  if y {\nz;\n      }\n   }");
+        editor.type_keys("=G");
+        assert_eq!(
+            editor.text(),
+            "fn f() {\n    x;\n    if y {\n        z;\n    }\n}\n"
+        );
+        assert_eq!((editor.cursor_y, editor.cursor_x), (0, 0));
+    }
+
+    #[test]
+    fn detect_tabs_or_space_step() {
+        let mut editor = editor_with("a {\n\tb\n\tc {\n\t\td\n\t}\n    e\n}");
+        editor.detect_indent();
+        assert!(!editor.config.expandtab);
+        assert_eq!(editor.config.shiftwidth(), editor.config.tabstop);
+
+        let mut editor = editor_with("a\n  b\n    c\n  d\n      e\ng\n");
+        editor.config.expandtab = false;
+        editor.detect_indent();
+        assert!(editor.config.expandtab);
+        assert_eq!(editor.config.shiftwidth, 2);
+        assert_eq!(editor.config.softtabstop, 2);
+
+        // the smaller step wins a tie
+        let mut editor = editor_with("a\n      b\n   c\n");
+        editor.detect_indent();
+        assert_eq!(editor.config.shiftwidth, 3);
+
+        // nothing indented leaves the settings alone
+        let mut editor = editor_with("a\nb\n");
+        editor.config.shiftwidth = 5;
+        editor.detect_indent();
+        assert_eq!(editor.config.shiftwidth, 5);
+    }
+
+    #[test]
+    fn mixed_tabs_and_spaces() {
+        let mut editor = editor_with("");
+        assert_eq!(editor.indent_width(" \t"), 4);
+        assert_eq!(editor.indent_width("\t  "), 6);
+        assert_eq!(editor.indent_width("  \t \t"), 8);
+        assert_eq!(editor.indent_string(10), " ".repeat(10));
+        editor.config.expandtab = false;
+        assert_eq!(editor.indent_string(10), "\t\t  ");
+        assert_eq!(editor.dedented("\t \t"), "\t");
+        editor.config.tabstop = 8;
+        assert_eq!(editor.indent_width(" \t  "), 10);
+        assert_eq!(editor.indent_string(10), "\t  ");
+        assert_eq!(editor.indented("  "), "\t  ");
+    }
+}
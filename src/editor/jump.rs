@@ -0,0 +1,80 @@
+use crate::*;
+
+/// Most positions the jump list remembers.
+const JUMP_LIST_SIZE: usize = 100;
+
+impl Editor {
+    /// Records the cursor position in the jump list before a big move. Any
+    /// older entry for the same line is dropped so each line appears once.
+    pub fn push_jump(&mut self) {
+        let cursor = (self.cursor_y, self.cursor_x);
+        self.set_mark('\'');
+        self.jumps.retain(|&(y, _)| y != cursor.0);
+        self.jumps.push(cursor);
+        if self.jumps.len() > JUMP_LIST_SIZE {
+            self.jumps.remove(0);
+        }
+        self.jump_index = self.jumps.len();
+    }
+
+    /// Moves to `pos`, clamped to the buffer.
+    pub fn goto_pos(&mut self, (y, x): Pos) {
+        self.cursor_y = std::cmp::min(y, self.content.len() - 1);
+        let line = &self.content[self.cursor_y];
+        self.cursor_x = line.floor_char_boundary(std::cmp::min(x, line.len()));
+    }
+
+    /// `Ctrl-o`: goes back to the previous position in the jump list.
+    pub fn jump_back(&mut self) {
+        if self.jump_index == 0 {
+            self.fail("At start of jump list");
+            return;
+        }
+        // remember where we left from so `Ctrl-i` can come back
+        if self.jump_index == self.jumps.len() {
+            self.push_jump();
+            self.jump_index = self.jumps.len() - 1;
+            if self.jump_index == 0 {
+                return;
+            }
+        }
+        self.jump_index -= 1;
+        self.goto_pos(self.jumps[self.jump_index]);
+    }
+
+    /// `Ctrl-i`: goes forward again after `Ctrl-o`.
+    pub fn jump_forward(&mut self) {
+        if self.jump_index + 1 >= self.jumps.len() {
+            self.fail("At end of jump list");
+            return;
+        }
+        self.jump_index += 1;
+        self.goto_pos(self.jumps[self.jump_index]);
+    }
+
+    /// Jumps to the first non-blank character of line `y`, clamped to the
+    /// buffer, recording the jump.
+    pub fn goto_line(&mut self, y: usize) {
+        self.push_jump();
+        self.cursor_y = std::cmp::min(y, self.content.len() - 1);
+        self.cursor_to_indent();
+    }
+
+    /// Goes to 1-based `line` and, if given, character `column`, as asked
+    /// for on the command line.
+    pub fn goto_position(&mut self, line: usize, column: Option<usize>) {
+        self.goto_line(line.saturating_sub(1));
+        if let Some(column) = column {
+            let line = &self.content[self.cursor_y];
+            self.cursor_x = line
+                .char_indices()
+                .nth(column.saturating_sub(1))
+                .map_or(line.len(), |(x, _)| x);
+        }
+    }
+
+    /// Takes the count typed before the current command.
+    pub fn take_count(&mut self) -> Option<usize> {
+        self.count.take()
+    }
+}
@@ -0,0 +1,714 @@
+use crate::*;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Expansion depth after which a mapping is assumed to recurse forever.
+pub const MAX_MAP_DEPTH: usize = 1000;
+
+/// A key press with modifiers normalized, so `G` and `Shift-G` compare equal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl Key {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = match code {
+            KeyCode::Char(_) | KeyCode::BackTab => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        Key { code, modifiers }
+    }
+
+    pub fn char(c: char) -> Self {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    pub fn ctrl(c: char) -> Self {
+        Key::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    /// The character this key types, if it is a plain printable key.
+    pub fn printable(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(c)
+                if !self
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        Key::new(event.code, event.modifiers)
+    }
+}
+
+/// A key waiting to be handled. Keys produced by a `noremap` mapping are not
+/// looked up in the user's mappings again.
+#[derive(Clone, Copy)]
+pub struct QueuedKey {
+    pub key: Key,
+    pub remap: bool,
+}
+
+const KEY_NAMES: &[(&str, KeyCode)] = &[
+    ("Esc", KeyCode::Esc),
+    ("CR", KeyCode::Enter),
+    ("Enter", KeyCode::Enter),
+    ("Return", KeyCode::Enter),
+    ("BS", KeyCode::Backspace),
+    ("Tab", KeyCode::Tab),
+    ("Space", KeyCode::Char(' ')),
+    ("lt", KeyCode::Char('<')),
+    ("Bar", KeyCode::Char('|')),
+    ("Bslash", KeyCode::Char('\\')),
+    ("Del", KeyCode::Delete),
+    ("Insert", KeyCode::Insert),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+];
+
+/// Parses vi key notation such as `jk`, `<Esc>`, `<C-w>` or `<lt>leader`.
+pub fn parse_keys(notation: &str) -> Result<Vec<Key>, String> {
+    let mut keys = Vec::new();
+    let mut rest = notation;
+    while let Some(c) = rest.chars().next() {
+        let special = rest
+            .strip_prefix('<')
+            .and_then(|inner| inner.split_once('>'))
+            .and_then(|(name, after)| parse_special(name).map(|key| (key, after)));
+        match special {
+            Some((key, after)) => {
+                keys.push(key);
+                rest = after;
+            }
+            None => {
+                keys.push(Key::char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    if keys.is_empty() {
+        return Err("Empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+fn parse_special(name: &str) -> Option<Key> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut base = name;
+    while base.len() > 2 && base.as_bytes()[1] == b'-' {
+        modifiers |= match base.as_bytes()[0].to_ascii_uppercase() {
+            b'C' => KeyModifiers::CONTROL,
+            b'S' => KeyModifiers::SHIFT,
+            b'A' | b'M' => KeyModifiers::ALT,
+            _ => return None,
+        };
+        base = &base[2..];
+    }
+
+    let mut chars = base.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) if modifiers != KeyModifiers::NONE => {
+            KeyCode::Char(if modifiers.contains(KeyModifiers::CONTROL) {
+                c.to_ascii_lowercase()
+            } else {
+                c
+            })
+        }
+        _ => match base.strip_prefix(['F', 'f']).map(str::parse::<u8>) {
+            Some(Ok(n)) if (1..=12).contains(&n) => KeyCode::F(n),
+            _ => {
+                KEY_NAMES
+                    .iter()
+                    .find(|(key_name, _)| key_name.eq_ignore_ascii_case(base))?
+                    .1
+            }
+        },
+    };
+    let code = match (code, modifiers.contains(KeyModifiers::SHIFT)) {
+        (KeyCode::Tab, true) => KeyCode::BackTab,
+        _ => code,
+    };
+    Some(Key::new(code, modifiers))
+}
+
+/// The inverse of [`parse_keys`], used when listing mappings.
+pub fn format_keys(keys: &[Key]) -> String {
+    keys.iter().map(format_key).collect()
+}
+
+fn format_key(key: &Key) -> String {
+    let mut prefix = String::new();
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        prefix.push_str("C-");
+    }
+    if key.modifiers.contains(KeyModifiers::ALT) {
+        prefix.push_str("A-");
+    }
+    if key.modifiers.contains(KeyModifiers::SHIFT) {
+        prefix.push_str("S-");
+    }
+    let name = match key.code {
+        KeyCode::Char('<') => "lt".to_string(),
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) if prefix.is_empty() => return c.to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        code => KEY_NAMES
+            .iter()
+            .find(|(_, key_code)| *key_code == code)
+            .map(|(name, _)| name.to_string())
+            .unwrap_or_else(|| "?".to_string()),
+    };
+    format!("<{}{}>", prefix, name)
+}
+
+#[derive(Clone)]
+pub enum Binding {
+    Action(&'static Action),
+    Keys { keys: Vec<Key>, remap: bool },
+}
+
+impl Binding {
+    /// Parses the right-hand side of a mapping: key notation, or
+    /// `<Action>name` to call a registered action directly.
+    pub fn parse(rhs: &str, remap: bool) -> Result<Binding, String> {
+        if let Some(name) = rhs.strip_prefix("<Action>") {
+            return find_action(name)
+                .map(Binding::Action)
+                .ok_or_else(|| format!("Unknown action: {}", name));
+        }
+        Ok(Binding::Keys {
+            keys: parse_keys(rhs)?,
+            remap,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Binding::Action(action) => format!("<Action>{}", action.name),
+            Binding::Keys { keys, remap: true } => format_keys(keys),
+            Binding::Keys { keys, remap: false } => format!("* {}", format_keys(keys)),
+        }
+    }
+}
+
+pub enum Lookup<'a> {
+    /// No mapping starts with these keys.
+    None,
+    /// Longer mappings start with these keys, but none matches exactly.
+    Prefix,
+    /// These keys are mapped and nothing longer starts with them.
+    Exact(&'a Binding),
+    /// These keys are mapped, but so is something longer that starts with
+    /// them; resolved by the next key or by the timeout.
+    Ambiguous(&'a Binding),
+}
+
+/// Mappings for one mode. The built-in table holds eep's default bindings;
+/// user mappings shadow it, except for keys coming from a `noremap`.
+#[derive(Default)]
+pub struct Keymap {
+    builtin: HashMap<Vec<Key>, Binding>,
+    user: HashMap<Vec<Key>, Binding>,
+}
+
+impl Keymap {
+    fn bind(&mut self, lhs: &str, action: &str) {
+        let keys = parse_keys(lhs).expect("built-in key notation is valid");
+        let action = find_action(action).expect("built-in binding names a registered action");
+        self.builtin.insert(keys, Binding::Action(action));
+    }
+
+    pub fn exact(&self, keys: &[Key], remap: bool) -> Option<&Binding> {
+        let user = if remap { self.user.get(keys) } else { None };
+        user.or_else(|| self.builtin.get(keys))
+    }
+
+    pub fn lookup(&self, keys: &[Key], remap: bool) -> Lookup<'_> {
+        let tables = if remap {
+            vec![&self.user, &self.builtin]
+        } else {
+            vec![&self.builtin]
+        };
+        let longer = tables.iter().any(|table| {
+            table
+                .keys()
+                .any(|lhs| lhs.len() > keys.len() && lhs.starts_with(keys))
+        });
+        match (self.exact(keys, remap), longer) {
+            (Some(binding), false) => Lookup::Exact(binding),
+            (Some(binding), true) => Lookup::Ambiguous(binding),
+            (None, true) => Lookup::Prefix,
+            (None, false) => Lookup::None,
+        }
+    }
+
+    pub fn map(&mut self, lhs: Vec<Key>, binding: Binding) {
+        self.user.insert(lhs, binding);
+    }
+
+    pub fn unmap(&mut self, lhs: &[Key]) -> bool {
+        self.user.remove(lhs).is_some()
+    }
+
+    /// Every active binding, user mappings first, sorted by key notation.
+    pub fn bindings(&self) -> Vec<(String, String)> {
+        let mut user: Vec<_> = self
+            .user
+            .iter()
+            .map(|(lhs, binding)| (format_keys(lhs), binding.describe()))
+            .collect();
+        let mut builtin: Vec<_> = self
+            .builtin
+            .iter()
+            .filter(|(lhs, _)| !self.user.contains_key(*lhs))
+            .map(|(lhs, binding)| (format_keys(lhs), binding.describe()))
+            .collect();
+        user.sort();
+        builtin.sort();
+        user.extend(builtin);
+        user
+    }
+}
+
+pub struct Keymaps {
+    maps: HashMap<Mode, Keymap>,
+    /// Text objects, looked up before the normal keymap while an operator
+    /// waits for its motion, so `daf` doesn't read `a` as append.
+    operator_pending: Keymap,
+}
+
+impl Default for Keymaps {
+    fn default() -> Self {
+        let mut normal = Keymap::default();
+        normal.bind("ZQ", "quit");
+        normal.bind("ZZ", "write_quit");
+        normal.bind("q", "record_macro");
+        normal.bind("@", "play_macro");
+        normal.bind(".", "repeat_change");
+        normal.bind(":", "command_mode");
+        normal.bind("i", "insert_mode");
+        normal.bind("a", "append");
+        normal.bind("A", "append_line_end");
+        normal.bind("I", "insert_line_start");
+        normal.bind("o", "open_line_below");
+        normal.bind("O", "open_line_above");
+        normal.bind("r", "replace_char");
+        normal.bind("R", "replace_mode");
+        normal.bind("J", "join_lines");
+        normal.bind("~", "toggle_case");
+        normal.bind("d", "delete_operator");
+        normal.bind("c", "change_operator");
+        normal.bind("y", "yank_operator");
+        normal.bind("g~", "toggle_case_operator");
+        normal.bind("gu", "lowercase_operator");
+        normal.bind("gU", "uppercase_operator");
+        normal.bind("g~~", "toggle_case_line");
+        normal.bind("g~g~", "toggle_case_line");
+        normal.bind("guu", "lowercase_line");
+        normal.bind("gugu", "lowercase_line");
+        normal.bind("gUU", "uppercase_line");
+        normal.bind("gUgU", "uppercase_line");
+        normal.bind("s", "substitute_char");
+        normal.bind("S", "substitute_line");
+        normal.bind("C", "change_to_line_end");
+        normal.bind("D", "delete_to_line_end");
+        normal.bind("<Esc>", "normal_mode");
+        normal.bind("h", "move_left");
+        normal.bind("j", "move_down");
+        normal.bind("k", "move_up");
+        normal.bind("l", "move_right");
+        normal.bind("<Left>", "move_left");
+        normal.bind("<Down>", "move_down");
+        normal.bind("<Up>", "move_up");
+        normal.bind("<Right>", "move_right");
+        normal.bind("<Home>", "line_start");
+        normal.bind("<End>", "line_end");
+        normal.bind("<PageUp>", "page_up");
+        normal.bind("<PageDown>", "page_down");
+        normal.bind("<Del>", "delete_char_forward");
+        normal.bind("0", "line_start");
+        normal.bind("$", "line_end");
+        normal.bind("gg", "goto_first_line");
+        normal.bind("G", "goto_last_line");
+        normal.bind("w", "word_forward");
+        normal.bind("W", "big_word_forward");
+        normal.bind("b", "word_backward");
+        normal.bind("B", "big_word_backward");
+        normal.bind("e", "word_end");
+        normal.bind("E", "big_word_end");
+        normal.bind("ge", "word_end_backward");
+        normal.bind("gE", "big_word_end_backward");
+        normal.bind("^", "first_non_blank");
+        normal.bind("_", "line_down_first_non_blank");
+        normal.bind("}", "paragraph_forward");
+        normal.bind("{", "paragraph_backward");
+        normal.bind(")", "sentence_forward");
+        normal.bind("(", "sentence_backward");
+        normal.bind("H", "screen_top");
+        normal.bind("M", "screen_middle");
+        normal.bind("L", "screen_bottom");
+        normal.bind("f", "find_char");
+        normal.bind("F", "find_char_backward");
+        normal.bind("t", "till_char");
+        normal.bind("T", "till_char_backward");
+        normal.bind(";", "repeat_find");
+        normal.bind(",", "repeat_find_reverse");
+        normal.bind("<C-d>", "half_page_down");
+        normal.bind("<C-u>", "half_page_up");
+        normal.bind("<C-f>", "page_down");
+        normal.bind("<C-b>", "page_up");
+        normal.bind("m", "set_mark");
+        normal.bind("`", "goto_mark");
+        normal.bind("'", "goto_mark_line");
+        normal.bind("<C-o>", "jump_back");
+        normal.bind("<C-i>", "jump_forward");
+        // terminals send Ctrl-i as a tab
+        normal.bind("<Tab>", "jump_forward");
+        normal.bind("x", "delete_char_forward");
+        normal.bind("u", "undo");
+        normal.bind("/", "search");
+        normal.bind("v", "visual_mode");
+        normal.bind("gd", "goto_definition");
+        normal.bind("K", "hover");
+        normal.bind(">", "shift_right_operator");
+        normal.bind("<lt>", "shift_left_operator");
+        normal.bind("=", "reindent_operator");
+        normal.bind(">>", "shift_right");
+        normal.bind("<lt><lt>", "shift_left");
+        normal.bind("==", "reindent");
+        normal.bind("%", "match_bracket");
+        #[cfg(feature = "treesitter")]
+        {
+            normal.bind("<A-o>", "expand_selection");
+            normal.bind("]f", "next_function");
+            normal.bind("[f", "previous_function");
+        }
+
+        let mut insert = Keymap::default();
+        insert.bind("<Esc>", "normal_mode");
+        insert.bind("<BS>", "delete_char");
+        insert.bind("<CR>", "accept_or_newline");
+        insert.bind("<Tab>", "complete_or_tab");
+        insert.bind("<C-n>", "complete_next");
+        insert.bind("<C-p>", "complete_previous");
+        insert.bind("<Left>", "move_left");
+        insert.bind("<Down>", "move_down");
+        insert.bind("<Up>", "move_up");
+        insert.bind("<Right>", "move_right");
+        insert.bind("<C-w>", "delete_word_before");
+        insert.bind("<C-u>", "delete_to_line_start");
+        insert.bind("<C-r>", "insert_register");
+        insert.bind("<C-o>", "insert_normal_command");
+        insert.bind("<Del>", "delete_forward");
+        insert.bind("<Home>", "line_start");
+        insert.bind("<End>", "line_end");
+        insert.bind("<PageUp>", "page_up");
+        insert.bind("<PageDown>", "page_down");
+
+        let mut command = Keymap::default();
+        command.bind("<Esc>", "normal_mode");
+        command.bind("<BS>", "command_backspace");
+        command.bind("<CR>", "process_command");
+        command.bind("<Del>", "command_delete");
+        command.bind("<C-w>", "command_delete_word");
+        command.bind("<C-u>", "command_delete_to_start");
+        command.bind("<Left>", "command_left");
+        command.bind("<Right>", "command_right");
+        command.bind("<S-Left>", "command_word_left");
+        command.bind("<S-Right>", "command_word_right");
+        command.bind("<C-Left>", "command_word_left");
+        command.bind("<C-Right>", "command_word_right");
+        command.bind("<Home>", "command_home");
+        command.bind("<C-b>", "command_home");
+        command.bind("<End>", "command_end");
+        command.bind("<C-e>", "command_end");
+        command.bind("<Up>", "command_history_older");
+        command.bind("<Down>", "command_history_newer");
+        command.bind("<C-p>", "command_history_older");
+        command.bind("<C-n>", "command_history_newer");
+        command.bind("<Tab>", "command_complete_next");
+        command.bind("<S-Tab>", "command_complete_previous");
+
+        let mut visual = Keymap::default();
+        visual.bind("<Esc>", "normal_mode");
+        visual.bind(":", "visual_command_mode");
+        visual.bind("v", "normal_mode");
+        visual.bind("h", "move_left");
+        visual.bind("j", "move_down");
+        visual.bind("k", "move_up");
+        visual.bind("l", "move_right");
+        visual.bind("<Left>", "move_left");
+        visual.bind("<Down>", "move_down");
+        visual.bind("<Up>", "move_up");
+        visual.bind("<Right>", "move_right");
+        visual.bind("<Home>", "line_start");
+        visual.bind("<End>", "line_end");
+        visual.bind("<PageUp>", "page_up");
+        visual.bind("<PageDown>", "page_down");
+        visual.bind("<Del>", "delete_selection");
+        visual.bind("0", "line_start");
+        visual.bind("$", "line_end");
+        visual.bind("gg", "goto_first_line");
+        visual.bind("G", "goto_last_line");
+        visual.bind("w", "word_forward");
+        visual.bind("W", "big_word_forward");
+        visual.bind("b", "word_backward");
+        visual.bind("B", "big_word_backward");
+        visual.bind("e", "word_end");
+        visual.bind("E", "big_word_end");
+        visual.bind("ge", "word_end_backward");
+        visual.bind("gE", "big_word_end_backward");
+        visual.bind("^", "first_non_blank");
+        visual.bind("_", "line_down_first_non_blank");
+        visual.bind("}", "paragraph_forward");
+        visual.bind("{", "paragraph_backward");
+        visual.bind(")", "sentence_forward");
+        visual.bind("(", "sentence_backward");
+        visual.bind("H", "screen_top");
+        visual.bind("M", "screen_middle");
+        visual.bind("L", "screen_bottom");
+        visual.bind("f", "find_char");
+        visual.bind("F", "find_char_backward");
+        visual.bind("t", "till_char");
+        visual.bind("T", "till_char_backward");
+        visual.bind(";", "repeat_find");
+        visual.bind(",", "repeat_find_reverse");
+        visual.bind("<C-d>", "half_page_down");
+        visual.bind("<C-u>", "half_page_up");
+        visual.bind("<C-f>", "page_down");
+        visual.bind("<C-b>", "page_up");
+        visual.bind("%", "match_bracket");
+        visual.bind("`", "goto_mark");
+        visual.bind("'", "goto_mark_line");
+        visual.bind("d", "delete_selection");
+        visual.bind("x", "delete_selection");
+        visual.bind(">", "shift_right");
+        visual.bind("<lt>", "shift_left");
+        visual.bind("=", "reindent");
+        visual.bind("J", "join_lines");
+        visual.bind("~", "toggle_case_selection");
+        visual.bind("u", "lowercase_selection");
+        visual.bind("U", "uppercase_selection");
+        #[cfg(feature = "treesitter")]
+        {
+            visual.bind("<A-o>", "expand_selection");
+            visual.bind("]f", "next_function");
+            visual.bind("[f", "previous_function");
+            visual.bind("af", "select_function");
+            visual.bind("if", "select_function_body");
+            visual.bind("ac", "select_class");
+            visual.bind("ic", "select_class_body");
+        }
+
+        let mut hex = Keymap::default();
+        hex.bind(":", "command_mode");
+
+        #[allow(unused_mut)]
+        let mut operator_pending = Keymap::default();
+        #[cfg(feature = "treesitter")]
+        {
+            operator_pending.bind("af", "select_function");
+            operator_pending.bind("if", "select_function_body");
+            operator_pending.bind("ac", "select_class");
+            operator_pending.bind("ic", "select_class_body");
+        }
+
+        Keymaps {
+            maps: HashMap::from([
+                (Mode::Normal, normal),
+                (Mode::Insert, insert),
+                (Mode::Command, command),
+                (Mode::Hex, hex),
+                (Mode::Visual, visual),
+            ]),
+            operator_pending,
+        }
+    }
+}
+
+/// The mode whose keymap `mode` uses: replace mode shares insert mode's.
+fn keymap_mode(mode: Mode) -> Mode {
+    match mode {
+        Mode::Replace => Mode::Insert,
+        mode => mode,
+    }
+}
+
+impl Keymaps {
+    pub fn get(&self, mode: Mode) -> &Keymap {
+        &self.maps[&keymap_mode(mode)]
+    }
+
+    pub fn get_mut(&mut self, mode: Mode) -> &mut Keymap {
+        self.maps
+            .get_mut(&keymap_mode(mode))
+            .expect("every mode has a keymap")
+    }
+
+    /// Looks `keys` up in `mode`'s keymap, trying the text objects first
+    /// when `operator_pending`.
+    pub fn lookup(
+        &self,
+        mode: Mode,
+        operator_pending: bool,
+        keys: &[Key],
+        remap: bool,
+    ) -> Lookup<'_> {
+        if operator_pending && mode == Mode::Normal {
+            match self.operator_pending.lookup(keys, remap) {
+                Lookup::None => {}
+                found => return found,
+            }
+        }
+        self.get(mode).lookup(keys, remap)
+    }
+}
+
+/// The `[keymap]` section of the config file, one table per mode mapping
+/// key notation to keys or to `{ action = "name" }`.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeymapConfig {
+    pub normal: HashMap<String, MappingConfig>,
+    pub insert: HashMap<String, MappingConfig>,
+    pub command: HashMap<String, MappingConfig>,
+    pub hex: HashMap<String, MappingConfig>,
+    pub visual: HashMap<String, MappingConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum MappingConfig {
+    Keys(String),
+    Action { action: String },
+}
+
+/// The modes a `:map` family command applies to, keyed by its prefix letter.
+pub fn map_command_modes(prefix: &str) -> Option<&'static [Mode]> {
+    match prefix {
+        "" | "n" => Some(&[Mode::Normal]),
+        "i" => Some(&[Mode::Insert]),
+        "c" => Some(&[Mode::Command]),
+        "v" | "x" => Some(&[Mode::Visual]),
+        _ => None,
+    }
+}
+
+fn mode_letter(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Normal => "n",
+        Mode::Insert | Mode::Replace => "i",
+        Mode::Command => "c",
+        Mode::Hex => "hex",
+        Mode::Visual => "v",
+    }
+}
+
+impl Editor {
+    /// Applies the `[keymap]` section of the config file.
+    pub fn apply_keymap_config(&mut self) -> Result<(), String> {
+        let sections = [
+            (Mode::Normal, std::mem::take(&mut self.config.keymap.normal)),
+            (Mode::Insert, std::mem::take(&mut self.config.keymap.insert)),
+            (
+                Mode::Command,
+                std::mem::take(&mut self.config.keymap.command),
+            ),
+            (Mode::Hex, std::mem::take(&mut self.config.keymap.hex)),
+            (Mode::Visual, std::mem::take(&mut self.config.keymap.visual)),
+        ];
+        for (mode, mappings) in sections {
+            for (lhs, mapping) in mappings {
+                let binding = match mapping {
+                    MappingConfig::Keys(rhs) => Binding::parse(&rhs, true),
+                    MappingConfig::Action { action } => find_action(&action)
+                        .map(Binding::Action)
+                        .ok_or_else(|| format!("Unknown action: {}", action)),
+                }
+                .map_err(|e| format!("keymap.{} \"{}\": {}", mode_letter_name(mode), lhs, e))?;
+                let lhs = parse_keys(&lhs)
+                    .map_err(|e| format!("keymap.{} \"{}\": {}", mode_letter_name(mode), lhs, e))?;
+                self.keymaps.get_mut(mode).map(lhs, binding);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs a `:map` family command. `prefix` is the mode letter before
+    /// `map`/`noremap`/`unmap` and `args` is everything after the name.
+    pub fn map_command(&mut self, prefix: &str, kind: &str, args: &str) -> Result<(), String> {
+        let modes = map_command_modes(prefix)
+            .ok_or_else(|| format!("Unknown command: {}{}", prefix, kind))?;
+        let mut args = args.trim().splitn(2, char::is_whitespace);
+        let lhs = args.next().filter(|lhs| !lhs.is_empty());
+        let rhs = args.next().map(str::trim).filter(|rhs| !rhs.is_empty());
+
+        match (kind, lhs, rhs) {
+            ("unmap", Some(lhs), _) => {
+                let lhs = parse_keys(lhs)?;
+                let mut removed = false;
+                for &mode in modes {
+                    removed |= self.keymaps.get_mut(mode).unmap(&lhs);
+                }
+                if !removed {
+                    return Err(format!("No such mapping: {}", format_keys(&lhs)));
+                }
+            }
+            ("unmap", None, _) => return Err("Argument required".to_string()),
+            (_, Some(lhs), Some(rhs)) => {
+                let lhs = parse_keys(lhs)?;
+                let binding = Binding::parse(rhs, kind == "map")?;
+                for &mode in modes {
+                    self.keymaps.get_mut(mode).map(lhs.clone(), binding.clone());
+                }
+            }
+            (_, lhs, _) => {
+                let filter = lhs
+                    .map(parse_keys)
+                    .transpose()?
+                    .map(|keys| format_keys(&keys));
+                let mut lines = Vec::new();
+                for &mode in modes {
+                    for (keys, binding) in self.keymaps.get(mode).bindings() {
+                        if filter.as_ref().is_none_or(|f| keys.starts_with(f.as_str())) {
+                            lines.push(format!("{}  {:<12} {}", mode_letter(mode), keys, binding));
+                        }
+                    }
+                }
+                if lines.is_empty() {
+                    lines.push("No mapping found".to_string());
+                }
+                self.show_lines(lines);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mode_letter_name(mode: Mode) -> &'static str {
+    match mode {
+        Mode::Normal => "normal",
+        Mode::Insert | Mode::Replace => "insert",
+        Mode::Command => "command",
+        Mode::Hex => "hex",
+        Mode::Visual => "visual",
+    }
+}
@@ -0,0 +1,375 @@
+use crate::*;
+use crossterm::event::{KeyCode, KeyModifiers};
+use std::ops::Range;
+
+/// Reads the `[x] [count]` argument of `:d` and `:y`.
+fn register_and_count(arg: &str) -> Result<(char, Option<usize>), ExError> {
+    let (register, rest) = match arg.chars().next() {
+        Some(c) if !c.is_ascii_digit() => {
+            let name =
+                register_name(c).ok_or_else(|| ExError::Failed("Invalid register".into()))?;
+            (name, arg[c.len_utf8()..].trim_start())
+        }
+        _ => ('"', arg),
+    };
+    Ok((register, parse_count(rest)?))
+}
+
+/// Reads the count after a command like `:> 3`.
+fn parse_count(arg: &str) -> Result<Option<usize>, ExError> {
+    if arg.is_empty() {
+        return Ok(None);
+    }
+    match arg.parse() {
+        Ok(0) => Err(ExError::Failed("Positive count required".into())),
+        Ok(n) => Ok(Some(n)),
+        Err(_) => Err(ExError::TrailingCharacters(arg.to_string())),
+    }
+}
+
+/// The first number in `line`, which `:sort n` sorts by.
+fn first_number(line: &str) -> Option<i64> {
+    let start = line.find(|c: char| c.is_ascii_digit())?;
+    let negative = line[..start].ends_with('-');
+    let digits = &line[start..];
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    let n: i64 = digits[..end].parse().unwrap_or(i64::MAX);
+    Some(if negative { -n } else { n })
+}
+
+impl ExCall {
+    /// The lines the command covers: its range, or the cursor line.
+    pub fn lines(&self, editor: &Editor) -> Range<usize> {
+        self.range
+            .clone()
+            .unwrap_or(editor.cursor_y..editor.cursor_y + 1)
+    }
+
+    /// The lines a command like `:d 3` covers: count lines from the last
+    /// line of its range.
+    fn counted_lines(&self, editor: &Editor, count: Option<usize>) -> Range<usize> {
+        let lines = self.lines(editor);
+        match count {
+            Some(n) => lines.end - 1..std::cmp::min(lines.end - 1 + n, editor.content.len()),
+            None => lines,
+        }
+    }
+}
+
+impl Editor {
+    /// The text of `lines` as a register holds it, ending in a newline.
+    pub fn lines_text(&self, lines: Range<usize>) -> String {
+        self.content[lines]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// `:[range]d [x] [count]`: deletes lines into a register.
+    pub fn ex_delete(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let (register, count) = register_and_count(&call.arg)?;
+        let lines = call.counted_lines(self, count);
+        self.registers
+            .insert(register, self.lines_text(lines.clone()));
+        // the buffer always keeps one line
+        let keep = match lines.len() == self.content.len() {
+            true => vec![String::new()],
+            false => Vec::new(),
+        };
+        self.replace_lines(lines.start, lines.end, keep);
+        self.cursor_y = std::cmp::min(lines.start, self.content.len() - 1);
+        self.cursor_to_indent();
+        Ok(())
+    }
+
+    /// `:[range]y [x] [count]`: copies lines into a register.
+    pub fn ex_yank(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let (register, count) = register_and_count(&call.arg)?;
+        let lines = call.counted_lines(self, count);
+        self.registers.insert(register, self.lines_text(lines));
+        Ok(())
+    }
+
+    /// `:[range]m {address}`: moves lines below the address, 0 being above
+    /// the first line.
+    pub fn ex_move(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let lines = call.lines(self);
+        let (target, rest) = self.ex_address(&call.arg)?;
+        if !rest.trim().is_empty() {
+            return Err(ExError::TrailingCharacters(rest.trim().to_string()));
+        }
+        if target > lines.start && target < lines.end {
+            return Err(ExError::Failed(
+                "Cannot move a range of lines into itself".into(),
+            ));
+        }
+        let moved = self.content[lines.clone()].to_vec();
+        let len = moved.len();
+        // moving down, the target is above the lines once they're gone
+        let target = match target >= lines.end {
+            true => target - len,
+            false => target,
+        };
+        self.replace_lines(lines.start, lines.end, Vec::new());
+        self.replace_lines(target, target, moved);
+        self.cursor_y = target + len - 1;
+        self.cursor_to_indent();
+        Ok(())
+    }
+
+    /// `:[range]t {address}` and `:co`: copies lines below the address.
+    pub fn ex_copy(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let lines = call.lines(self);
+        let (target, rest) = self.ex_address(&call.arg)?;
+        if !rest.trim().is_empty() {
+            return Err(ExError::TrailingCharacters(rest.trim().to_string()));
+        }
+        let copied = self.content[lines].to_vec();
+        let len = copied.len();
+        self.replace_lines(target, target, copied);
+        self.cursor_y = target + len - 1;
+        self.cursor_to_indent();
+        Ok(())
+    }
+
+    /// `:[range]> [count]` and `:<`: shifts lines a level right or left,
+    /// or a level for each extra `>` or `<`, as in `:>>>`.
+    pub fn ex_shift(&mut self, call: &ExCall, right: bool) -> Result<(), ExError> {
+        let shift = if right { '>' } else { '<' };
+        let extra = call.arg.chars().take_while(|&c| c == shift).count();
+        let count = parse_count(call.arg[extra..].trim_start())?;
+        let lines = call.counted_lines(self, count);
+        self.shift_range(lines.clone(), right, extra + 1);
+        self.cursor_y = lines.end - 1;
+        self.cursor_to_indent();
+        Ok(())
+    }
+
+    /// `:[range]normal[!] {keys}`: runs normal mode keys on each line, with
+    /// the cursor at its start. An unfinished command is ended as though
+    /// `<Esc>` were typed. With `!` mappings are not used.
+    pub fn ex_normal(&mut self, call: &ExCall) -> Result<(), ExError> {
+        if call.arg.is_empty() {
+            return Err(ExError::ArgumentRequired);
+        }
+        let keys = parse_keys(&call.arg)?;
+        // keys queued by a macro or mapping that ran this come after it
+        let queued = std::mem::take(&mut self.input_queue);
+        let result = self.run_normal_keys(&keys, call.lines(self), !call.bang);
+        self.input_queue.extend(queued);
+        result.map_err(|e| ExError::Failed(e.to_string()))
+    }
+
+    fn run_normal_keys(
+        &mut self,
+        keys: &[Key],
+        lines: Range<usize>,
+        remap: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.mode = Mode::Normal;
+        for y in lines {
+            if y >= self.content.len() {
+                break;
+            }
+            (self.cursor_y, self.cursor_x) = (y, 0);
+            let keys = keys.iter().map(|&key| QueuedKey { key, remap });
+            self.input_queue.extend(keys);
+            self.handle_queued_keys()?;
+            if !self.between_commands() || self.mode != Mode::Normal {
+                let esc = Key::new(KeyCode::Esc, KeyModifiers::NONE);
+                self.input_queue.push_back(QueuedKey {
+                    key: esc,
+                    remap: false,
+                });
+                self.handle_queued_keys()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles every queued key, then any left waiting for more.
+    fn handle_queued_keys(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        while let Some(key) = self.input_queue.pop_front() {
+            self.handle_key(key)?;
+            if self.input_queue.is_empty() {
+                self.flush_pending_keys()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `:[range]g/{pattern}/{command}` runs an ex command on each line that
+    /// matches, and `:v` or `:g!` on each one that doesn't. Without a
+    /// command the lines are listed. Lines are picked before any command
+    /// runs, and ones that get deleted are skipped.
+    pub fn ex_global(&mut self, call: &ExCall, invert: bool) -> Result<(), ExError> {
+        if self.global_lines.is_some() {
+            return Err(ExError::Failed("Cannot nest :global".into()));
+        }
+        let invert = invert != call.bang;
+        let mut chars = call.arg.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && c != '"' && c != '|' && c != '\\' => c,
+            _ => return Err(ExError::Failed("Usage: g/{pattern}/{command}".into())),
+        };
+        let (pattern, command) = parse_pattern(chars.as_str(), delimiter);
+        let regex = compile_pattern(&pattern)?;
+        let lines = call.range.clone().unwrap_or(0..self.content.len());
+        let matches: Vec<usize> = lines
+            .filter(|&y| regex.is_match(&self.content[y]) != invert)
+            .collect();
+        if matches.is_empty() {
+            return Err(ExError::PatternNotFound(pattern));
+        }
+        let command = command.trim();
+        if command.is_empty() {
+            let listed = matches
+                .iter()
+                .map(|&y| format!("{:>6} {}", y + 1, self.content[y]))
+                .collect();
+            self.show_lines(listed);
+            return Ok(());
+        }
+
+        self.global_lines = Some(matches);
+        let mut result = Ok(());
+        // commands move the lines still to come, so take them one at a time
+        while let Some(y) = self
+            .global_lines
+            .as_mut()
+            .and_then(|lines| (!lines.is_empty()).then(|| lines.remove(0)))
+        {
+            (self.cursor_y, self.cursor_x) = (y, 0);
+            result = self.run_ex(command);
+            if result.is_err() {
+                break;
+            }
+        }
+        self.global_lines = None;
+        result
+    }
+
+    /// `:[range]sort[!] [n][u][i][r]`: sorts lines, the whole buffer without
+    /// a range. `n` sorts by the first number in each line, with lines that
+    /// have none first, `u` drops repeated lines, `i` ignores case, and `r`
+    /// or `!` reverses the order.
+    pub fn ex_sort(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let (mut numeric, mut unique, mut ignore_case) = (false, false, false);
+        let mut reverse = call.bang;
+        for c in call.arg.chars().filter(|c| !c.is_whitespace()) {
+            match c {
+                'n' => numeric = true,
+                'u' => unique = true,
+                'i' => ignore_case = true,
+                'r' => reverse = true,
+                _ => return Err(ExError::Failed(format!("Invalid sort option: {}", c))),
+            }
+        }
+        let lines = call.range.clone().unwrap_or(0..self.content.len());
+        let key = |line: &String| match ignore_case {
+            true => line.to_lowercase(),
+            false => line.clone(),
+        };
+        let mut sorted = self.content[lines.clone()].to_vec();
+        match numeric {
+            true => sorted.sort_by_key(|line| first_number(line)),
+            false => sorted.sort_by_key(key),
+        }
+        if reverse {
+            sorted.reverse();
+        }
+        if unique {
+            sorted.dedup_by(|a, b| match numeric {
+                true => first_number(a) == first_number(b),
+                false => key(a) == key(b),
+            });
+        }
+        if sorted[..] != self.content[lines.clone()] {
+            self.replace_lines(lines.start, lines.end, sorted);
+        }
+        self.cursor_y = lines.start;
+        self.cursor_to_indent();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    fn ex(text: &str, command: &str) -> String {
+        let mut editor = editor_with(text);
+        editor.run_ex(command).unwrap();
+        editor.text()
+    }
+
+    fn ex_error(text: &str, command: &str) -> String {
+        let mut editor = editor_with(text);
+        let error = editor.run_ex(command).unwrap_err().to_string();
+        assert_eq!(editor.text(), text);
+        error
+    }
+
+    const ABCDE: &str = "a\nb\nc\nd\ne\n";
+
+    #[test]
+    fn move_puts_lines_below_the_address() {
+        assert_eq!(ex(ABCDE, "2m4"), "a\nc\nd\nb\ne\n");
+        assert_eq!(ex(ABCDE, "4m0"), "d\na\nb\nc\ne\n");
+        assert_eq!(ex(ABCDE, "1,2m$"), "c\nd\ne\na\nb\n");
+        assert_eq!(ex(ABCDE, "2,3m1"), ABCDE);
+        assert_eq!(
+            ex_error(ABCDE, "1,3m2"),
+            "Cannot move a range of lines into itself"
+        );
+        assert_eq!(ex_error(ABCDE, "m"), "Invalid range");
+        assert_eq!(ex_error(ABCDE, "m 2 x"), "Trailing characters: x");
+
+        let mut editor = editor_with(ABCDE);
+        editor.run_ex("1,2m4").unwrap();
+        assert_eq!(editor.cursor_y, 3);
+    }
+
+    #[test]
+    fn copy_puts_a_copy_below_the_address() {
+        assert_eq!(ex(ABCDE, "1t$"), "a\nb\nc\nd\ne\na\n");
+        assert_eq!(ex(ABCDE, "2,3t0"), "b\nc\na\nb\nc\nd\ne\n");
+        assert_eq!(ex(ABCDE, "2,3co3"), "a\nb\nc\nb\nc\nd\ne\n");
+        assert_eq!(ex(ABCDE, "t."), "a\na\nb\nc\nd\ne\n");
+        assert_eq!(ex_error(ABCDE, "t9"), "Invalid range");
+    }
+
+    #[test]
+    fn global_runs_a_command_on_matching_lines() {
+        assert_eq!(ex(ABCDE, "g/[bd]/d"), "a\nc\ne\n");
+        assert_eq!(ex(ABCDE, "v/[bd]/d"), "b\nd\n");
+        assert_eq!(ex(ABCDE, "g!/[bd]/d"), "b\nd\n");
+        assert_eq!(ex(ABCDE, "2,4g/./d"), "a\ne\n");
+        // lines are picked first, so moving each to the top reverses them
+        assert_eq!(ex(ABCDE, "g/^/m0"), "e\nd\nc\nb\na\n");
+        // a line deleted by an earlier match is skipped
+        assert_eq!(ex(ABCDE, "g/[abc]/.,+1d"), "e\n");
+        assert_eq!(ex_error(ABCDE, "g/x/d"), "Pattern not found: x");
+        assert_eq!(ex_error(ABCDE, "g/a/g/b/d"), "Cannot nest :global");
+        assert_eq!(ex_error(ABCDE, "g a"), "Usage: g/{pattern}/{command}");
+    }
+
+    #[test]
+    fn sort_orders_by_text_number_or_case() {
+        let text = "b10\nB2\na1\nc\n";
+        assert_eq!(ex(text, "sort"), "B2\na1\nb10\nc\n");
+        assert_eq!(ex(text, "sort i"), "a1\nb10\nB2\nc\n");
+        assert_eq!(ex(text, "sort n"), "c\na1\nB2\nb10\n");
+        assert_eq!(ex(text, "sort!"), "c\nb10\na1\nB2\n");
+        assert_eq!(ex(text, "sort r"), "c\nb10\na1\nB2\n");
+        assert_eq!(ex(text, "sort nr"), "b10\nB2\na1\nc\n");
+        assert_eq!(ex(text, "2,3sort"), "b10\nB2\na1\nc\n");
+        assert_eq!(ex(text, "2,3sort i"), "b10\na1\nB2\nc\n");
+        assert_eq!(ex("b\na\nB\nb\n", "sort u"), "B\na\nb\n");
+        assert_eq!(ex("b\na\nB\nb\n", "sort ui"), "a\nb\n");
+        assert_eq!(ex("x-3\nx2\nx-10\n", "sort n"), "x-10\nx-3\nx2\n");
+        assert_eq!(ex_error(text, "sort x"), "Invalid sort option: x");
+    }
+}
@@ -0,0 +1,1086 @@
+use crate::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often the main loop wakes up to handle server messages while it
+/// waits for a key.
+pub const LSP_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A `[lsp.<grammar>]` table in the config file.
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LspServerConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    fn from_lsp(value: &Value) -> Severity {
+        match value.as_u64() {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Info,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn group(self) -> &'static str {
+        match self {
+            Severity::Error => "diagnostic_error",
+            Severity::Warning => "diagnostic_warning",
+            Severity::Info => "diagnostic_info",
+            Severity::Hint => "diagnostic_hint",
+        }
+    }
+
+    pub fn sign(self) -> char {
+        match self {
+            Severity::Error => 'E',
+            Severity::Warning => 'W',
+            Severity::Info => 'I',
+            Severity::Hint => 'H',
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// The diagnostic as the server sent it, handed back with code actions.
+    raw: Value,
+}
+
+/// What a request in flight was for, so its response can be handled.
+enum Request {
+    Initialize,
+    Definition,
+    Hover,
+    Rename,
+    CodeAction,
+    ResolveCodeAction,
+    Completion,
+    Other,
+}
+
+/// A language server running as a child process, talking JSON-RPC over its
+/// stdin and stdout. Messages are read on a separate thread and handled by
+/// the main loop between keys.
+pub struct LspClient {
+    pub language: String,
+    pub command: String,
+    /// The workspace the server was started for.
+    root: PathBuf,
+    child: Child,
+    stdin: ChildStdin,
+    messages: Receiver<Value>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    uri: String,
+    version: i64,
+    initialized: bool,
+    /// Whether positions count bytes rather than UTF-16 code units.
+    utf8: bool,
+    incremental: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    code_actions: Vec<Value>,
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    });
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < encoded.len() {
+        let hex = encoded
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok());
+        match (
+            encoded[i],
+            hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+        ) {
+            (b'%', Some(byte)) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                bytes.push(byte);
+                i += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// Byte offset in `line` of the LSP `character` column.
+fn byte_col(line: &str, character: usize, utf8: bool) -> usize {
+    if utf8 {
+        return line.floor_char_boundary(character.min(line.len()));
+    }
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+/// The LSP `character` column of byte `x` in `line`.
+fn lsp_col(line: &str, x: usize, utf8: bool) -> usize {
+    let x = std::cmp::min(x, line.len());
+    if utf8 {
+        x
+    } else {
+        line[..x].encode_utf16().count()
+    }
+}
+
+fn position_of(value: &Value) -> Option<(usize, usize)> {
+    Some((
+        value.get("line")?.as_u64()? as usize,
+        value.get("character")?.as_u64()? as usize,
+    ))
+}
+
+/// Turns a `TextEdit` into a splice of whole lines of `lines`: the first
+/// line, the line after the last one, and what replaces them. Lines are
+/// taken to end with a newline, as they are sent to the server.
+fn text_edit_splice(
+    lines: &[String],
+    edit: &Value,
+    utf8: bool,
+) -> Option<(usize, usize, Vec<String>)> {
+    let range = edit.get("range")?;
+    let (start_y, start_c) = position_of(range.get("start")?)?;
+    let (end_y, end_c) = position_of(range.get("end")?)?;
+    let text = edit.get("newText")?.as_str()?;
+    let start_y = std::cmp::min(start_y, lines.len());
+    let end_y = std::cmp::min(std::cmp::max(end_y, start_y), lines.len());
+
+    let empty = String::new();
+    let (first, last) = (
+        lines.get(start_y).unwrap_or(&empty),
+        lines.get(end_y).unwrap_or(&empty),
+    );
+    let prefix = &first[..byte_col(first, start_c, utf8)];
+    let suffix = &last[byte_col(last, end_c, utf8)..];
+    let mut new_lines: Vec<String> = format!("{}{}{}", prefix, text, suffix)
+        .split('\n')
+        .map(String::from)
+        .collect();
+    let end = std::cmp::min(end_y + 1, lines.len());
+    if end_y == lines.len() && new_lines.len() > 1 && new_lines.last().is_some_and(String::is_empty)
+    {
+        // the newline at the end of the buffer is implied
+        new_lines.pop();
+    }
+    Some((start_y, std::cmp::max(end, start_y), new_lines))
+}
+
+/// Edits sorted so that applying them one after another never shifts the
+/// position of one still to come.
+fn edits_last_first(edits: &[Value]) -> Vec<&Value> {
+    let mut edits: Vec<&Value> = edits.iter().collect();
+    edits.sort_by_key(|edit| {
+        std::cmp::Reverse(
+            edit.get("range")
+                .and_then(|range| range.get("start"))
+                .and_then(position_of),
+        )
+    });
+    edits
+}
+
+/// The text of hover `contents`, which may be a string, a marked string,
+/// markup, or a list of those.
+fn hover_text(contents: &Value) -> String {
+    match contents {
+        Value::String(text) => text.clone(),
+        Value::Array(items) => items
+            .iter()
+            .map(hover_text)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        Value::Object(object) => object
+            .get("value")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+fn completion_item(item: &Value) -> Option<CompletionItem> {
+    let label = item["label"].as_str()?.trim().to_string();
+    let insert = item["textEdit"]["newText"]
+        .as_str()
+        .or(item["insertText"].as_str())
+        .unwrap_or(&label);
+    // servers may send snippets anyway; keep just their placeholder text
+    let insert = match item["insertTextFormat"].as_u64() {
+        Some(2) => strip_snippet(insert),
+        _ => insert.to_string(),
+    };
+    Some(CompletionItem {
+        detail: item["detail"].as_str().map(String::from),
+        label,
+        insert,
+    })
+}
+
+fn strip_snippet(snippet: &str) -> String {
+    let mut text = String::new();
+    let mut chars = snippet.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(_)) => text.extend(chars.next()),
+            ('$', Some('{')) => {
+                chars.next();
+                let inner: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                text.push_str(inner.split_once(':').map_or("", |(_, default)| default));
+            }
+            ('$', Some(c)) if c.is_ascii_digit() => {
+                while chars.peek().is_some_and(char::is_ascii_digit) {
+                    chars.next();
+                }
+            }
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+fn read_messages(stdout: ChildStdout, sender: Sender<Value>) {
+    let mut reader = BufReader::new(stdout);
+    loop {
+        let mut length = None;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap_or(0) == 0 {
+                return;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length:") {
+                length = value.trim().parse().ok();
+            }
+        }
+        let Some(length) = length else {
+            continue;
+        };
+        let mut body = vec![0; length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        if let Ok(message) = serde_json::from_slice(&body)
+            && sender.send(message).is_err()
+        {
+            return;
+        }
+    }
+}
+
+impl LspClient {
+    /// Launches the server for `path` and sends it `initialize`. The file
+    /// is opened once the server has answered.
+    pub fn start(language: &str, config: &LspServerConfig, path: &Path) -> io::Result<LspClient> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || read_messages(stdout, sender));
+
+        let root = std::env::current_dir()?;
+        let mut client = LspClient {
+            language: language.to_string(),
+            command: config.command.clone(),
+            root: root.clone(),
+            child,
+            stdin,
+            messages,
+            next_id: 1,
+            pending: HashMap::new(),
+            uri: path_to_uri(path),
+            version: 0,
+            initialized: false,
+            utf8: false,
+            incremental: false,
+            diagnostics: Vec::new(),
+            code_actions: Vec::new(),
+        };
+        client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": path_to_uri(&root),
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-8", "utf-16"] },
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "publishDiagnostics": {},
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "definition": { "linkSupport": true },
+                        "rename": {},
+                        "completion": { "completionItem": { "snippetSupport": false } },
+                        "codeAction": {
+                            "codeActionLiteralSupport": {
+                                "codeActionKind": {
+                                    "valueSet": ["", "quickfix", "refactor", "source"]
+                                }
+                            },
+                            "resolveSupport": { "properties": ["edit"] }
+                        }
+                    },
+                    "workspace": {
+                        "applyEdit": true,
+                        "configuration": true,
+                        "workspaceEdit": { "documentChanges": true }
+                    }
+                }
+            }),
+            Request::Initialize,
+        )?;
+        Ok(client)
+    }
+
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.stdin.flush()
+    }
+
+    fn request(&mut self, method: &str, params: Value, kind: Request) -> io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id, kind);
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn respond(&mut self, id: Value, result: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn did_open(&mut self, content: &[String]) -> io::Result<()> {
+        self.version = 1;
+        let params = json!({
+            "textDocument": {
+                "uri": self.uri,
+                "languageId": self.language,
+                "version": self.version,
+                "text": content.join("\n") + "\n",
+            }
+        });
+        self.notify("textDocument/didOpen", params)
+    }
+
+    /// Switches the client to the file at `path`, closing the one it had
+    /// open. Before the server has answered `initialize` there is nothing
+    /// to close, and the new file is opened once it does.
+    fn open_document(&mut self, path: &Path, content: &[String]) -> io::Result<()> {
+        let uri = path_to_uri(path);
+        if uri == self.uri {
+            return Ok(());
+        }
+        self.diagnostics.clear();
+        self.code_actions.clear();
+        if !self.initialized {
+            self.uri = uri;
+            return Ok(());
+        }
+        let params = json!({ "textDocument": { "uri": self.uri } });
+        self.notify("textDocument/didClose", params)?;
+        self.uri = uri;
+        self.did_open(content)
+    }
+
+    /// Tells the server that lines `start..end` were replaced by
+    /// `inserted` lines, which are now in `content`.
+    pub fn did_change(&mut self, content: &[String], start: usize, end: usize, inserted: usize) {
+        let removed = end - start;
+        for diagnostic in &mut self.diagnostics {
+            if diagnostic.line >= end {
+                diagnostic.line = diagnostic.line + inserted - removed;
+            } else if diagnostic.line >= start {
+                diagnostic.line = start;
+            }
+        }
+        if !self.initialized {
+            return;
+        }
+        self.version += 1;
+        let change = if self.incremental {
+            let text: String = content[start..start + inserted]
+                .iter()
+                .map(|line| format!("{}\n", line))
+                .collect();
+            json!({
+                "range": {
+                    "start": { "line": start, "character": 0 },
+                    "end": { "line": end, "character": 0 },
+                },
+                "text": text,
+            })
+        } else {
+            json!({ "text": content.join("\n") + "\n" })
+        };
+        let params = json!({
+            "textDocument": { "uri": self.uri, "version": self.version },
+            "contentChanges": [change],
+        });
+        let _ = self.notify("textDocument/didChange", params);
+    }
+
+    pub fn did_save(&mut self) {
+        if self.initialized {
+            let params = json!({ "textDocument": { "uri": self.uri } });
+            let _ = self.notify("textDocument/didSave", params);
+        }
+    }
+
+    fn position(&self, content: &[String], y: usize, x: usize) -> Value {
+        json!({
+            "textDocument": { "uri": self.uri },
+            "position": { "line": y, "character": lsp_col(&content[y], x, self.utf8) },
+        })
+    }
+
+    /// Asks the server to shut down, killing it if it takes too long.
+    pub fn stop(mut self) {
+        if self.initialized {
+            let _ = self.request("shutdown", Value::Null, Request::Other);
+            let _ = self.notify("exit", Value::Null);
+        }
+        let deadline = Instant::now() + Duration::from_millis(200);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Editor {
+    /// Starts the language server configured for the buffer's grammar, if
+    /// there is one. A server already running for the same language and
+    /// workspace is kept and told about the new file instead.
+    pub fn start_lsp(&mut self) {
+        let language = self
+            .highlighter
+            .as_ref()
+            .map(|highlighter| highlighter.grammar.name.clone());
+        let config = language
+            .as_ref()
+            .and_then(|language| self.config.lsp.get(language).cloned());
+        let (Some(path), Some(language), Some(config)) = (self.filename.clone(), language, config)
+        else {
+            self.stop_lsp();
+            return;
+        };
+        let root = std::env::current_dir().ok();
+        if let Some(ref mut client) = self.lsp
+            && client.language == language
+            && client.command == config.command
+            && Some(&client.root) == root.as_ref()
+        {
+            if let Err(e) = client.open_document(&path, &self.content) {
+                self.set_status(&format!("LSP error: {}", e));
+            }
+            return;
+        }
+        self.stop_lsp();
+        match LspClient::start(&language, &config, &path) {
+            Ok(client) => self.lsp = Some(client),
+            Err(e) => self.set_status(&format!("Cannot start {}: {}", config.command, e)),
+        }
+    }
+
+    pub fn stop_lsp(&mut self) {
+        if let Some(client) = self.lsp.take() {
+            client.stop();
+        }
+    }
+
+    /// Handles every message the server has sent since the last call.
+    /// Returns whether there were any, so the screen can be redrawn.
+    pub fn poll_lsp(&mut self) -> bool {
+        let mut handled = false;
+        loop {
+            let Some(ref client) = self.lsp else {
+                return handled;
+            };
+            match client.messages.try_recv() {
+                Ok(message) => {
+                    self.handle_lsp_message(message);
+                    handled = true;
+                }
+                Err(TryRecvError::Empty) => return handled,
+                Err(TryRecvError::Disconnected) => {
+                    let client = self.lsp.take().expect("checked above");
+                    self.set_status(&format!("{} exited", client.command));
+                    client.stop();
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn handle_lsp_message(&mut self, message: Value) {
+        let method = message.get("method").and_then(Value::as_str);
+        match (message.get("id"), method) {
+            (Some(id), Some(method)) => {
+                let result = self.handle_lsp_request(method, &message["params"]);
+                if let Some(ref mut client) = self.lsp {
+                    let _ = client.respond(id.clone(), result);
+                }
+            }
+            (Some(id), None) => {
+                let Some(ref mut client) = self.lsp else {
+                    return;
+                };
+                let Some(kind) = id.as_u64().and_then(|id| client.pending.remove(&id)) else {
+                    return;
+                };
+                match message.get("error") {
+                    Some(error) => {
+                        let text = error["message"].as_str().unwrap_or("request failed");
+                        self.set_status(&format!("LSP error: {}", text));
+                    }
+                    None => self.handle_lsp_response(kind, &message["result"]),
+                }
+            }
+            (None, Some(method)) => self.handle_lsp_notification(method, &message["params"]),
+            (None, None) => {}
+        }
+    }
+
+    fn handle_lsp_request(&mut self, method: &str, params: &Value) -> Value {
+        match method {
+            "workspace/configuration" => {
+                let items = params["items"].as_array().map_or(0, Vec::len);
+                Value::Array(vec![Value::Null; items])
+            }
+            "workspace/applyEdit" => match self.apply_workspace_edit(&params["edit"]) {
+                Ok(_) => json!({ "applied": true }),
+                Err(e) => json!({ "applied": false, "failureReason": e }),
+            },
+            _ => Value::Null,
+        }
+    }
+
+    fn handle_lsp_notification(&mut self, method: &str, params: &Value) {
+        match method {
+            "textDocument/publishDiagnostics" => {
+                let Some(ref mut client) = self.lsp else {
+                    return;
+                };
+                if params["uri"].as_str() != Some(client.uri.as_str()) {
+                    return;
+                }
+                let empty = Vec::new();
+                client.diagnostics = params["diagnostics"]
+                    .as_array()
+                    .unwrap_or(&empty)
+                    .iter()
+                    .filter_map(|raw| {
+                        Some(Diagnostic {
+                            line: position_of(&raw["range"]["start"])?.0,
+                            severity: Severity::from_lsp(&raw["severity"]),
+                            message: raw["message"].as_str()?.to_string(),
+                            raw: raw.clone(),
+                        })
+                    })
+                    .collect();
+                client.diagnostics.sort_by_key(|d| (d.line, d.severity));
+            }
+            "window/showMessage" => {
+                if let Some(text) = params["message"].as_str() {
+                    self.set_status(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_lsp_response(&mut self, kind: Request, result: &Value) {
+        match kind {
+            Request::Initialize => {
+                let Some(ref mut client) = self.lsp else {
+                    return;
+                };
+                let capabilities = &result["capabilities"];
+                client.utf8 = capabilities["positionEncoding"].as_str() == Some("utf-8");
+                let sync = &capabilities["textDocumentSync"];
+                client.incremental = sync.as_u64().or_else(|| sync["change"].as_u64()) == Some(2);
+                client.initialized = true;
+                let opened = client
+                    .notify("initialized", json!({}))
+                    .and_then(|_| client.did_open(&self.content));
+                if let Err(e) = opened {
+                    self.set_status(&format!("LSP error: {}", e));
+                }
+            }
+            Request::Definition => {
+                let location = match result {
+                    Value::Array(locations) => locations.first(),
+                    Value::Null => None,
+                    location => Some(location),
+                };
+                let target = location.and_then(|location| {
+                    let uri = location.get("targetUri").or(location.get("uri"))?;
+                    let range = location
+                        .get("targetSelectionRange")
+                        .or(location.get("range"))?;
+                    Some((uri.as_str()?.to_string(), position_of(&range["start"])?))
+                });
+                match target {
+                    Some((uri, position)) => self.jump_to_location(&uri, position),
+                    None => self.set_status("No definition found"),
+                }
+            }
+            Request::Hover => {
+                let text = hover_text(&result["contents"]);
+                if text.trim().is_empty() {
+                    self.set_status("No hover information");
+                } else {
+                    self.show_lines(text.trim().lines().map(String::from).collect());
+                }
+            }
+            Request::Rename => match self.apply_workspace_edit(result) {
+                Ok(files) => self.set_status(&format!("Renamed in {} file(s)", files)),
+                Err(e) => self.set_status(&e),
+            },
+            Request::CodeAction => {
+                let actions = result.as_array().cloned().unwrap_or_default();
+                if actions.is_empty() {
+                    self.set_status("No code actions available");
+                    return;
+                }
+                let mut lines: Vec<String> = actions
+                    .iter()
+                    .enumerate()
+                    .map(|(i, action)| {
+                        format!("{}. {}", i + 1, action["title"].as_str().unwrap_or("?"))
+                    })
+                    .collect();
+                lines.push("Apply one with :codeaction <number>".to_string());
+                if let Some(ref mut client) = self.lsp {
+                    client.code_actions = actions;
+                }
+                self.show_lines(lines);
+            }
+            Request::ResolveCodeAction => self.run_code_action(result.clone(), false),
+            Request::Completion => {
+                let items = result
+                    .get("items")
+                    .unwrap_or(result)
+                    .as_array()
+                    .map_or(Vec::new(), |items| {
+                        items.iter().filter_map(completion_item).collect()
+                    });
+                self.add_completion_items(items);
+            }
+            Request::Other => {}
+        }
+    }
+
+    fn jump_to_location(&mut self, uri: &str, (line, character): (usize, usize)) {
+        let utf8 = self.lsp.as_ref().is_some_and(|client| client.utf8);
+        let current = self.lsp.as_ref().map(|client| client.uri.as_str());
+        if current != Some(uri) {
+            let Some(path) = uri_to_path(uri) else {
+                self.set_status(&format!("Cannot open {}", uri));
+                return;
+            };
+            if let Err(e) = self.switch_file(&path.to_string_lossy()) {
+                self.set_status(&e);
+                return;
+            }
+        } else {
+            self.push_jump();
+        }
+        self.cursor_y = std::cmp::min(line, self.content.len() - 1);
+        self.cursor_x = byte_col(&self.content[self.cursor_y], character, utf8);
+    }
+
+    /// Applies a `WorkspaceEdit`. Edits to the open file change the buffer;
+    /// other files are changed on disk. Returns how many files changed.
+    pub fn apply_workspace_edit(&mut self, edit: &Value) -> Result<usize, String> {
+        let mut files: Vec<(String, Vec<Value>)> = Vec::new();
+        if let Some(changes) = edit["documentChanges"].as_array() {
+            for change in changes {
+                let Some(uri) = change["textDocument"]["uri"].as_str() else {
+                    return Err("Resource operations are not supported".to_string());
+                };
+                let edits = change["edits"].as_array().cloned().unwrap_or_default();
+                files.push((uri.to_string(), edits));
+            }
+        } else if let Some(changes) = edit["changes"].as_object() {
+            for (uri, edits) in changes {
+                files.push((uri.clone(), edits.as_array().cloned().unwrap_or_default()));
+            }
+        } else if !edit.is_null() {
+            return Err("Unsupported workspace edit".to_string());
+        }
+
+        let Some(ref client) = self.lsp else {
+            return Err("No language server".to_string());
+        };
+        let (current, utf8) = (client.uri.clone(), client.utf8);
+        for (uri, edits) in &files {
+            if *uri == current {
+                for edit in edits_last_first(edits) {
+                    if let Some((start, end, lines)) = text_edit_splice(&self.content, edit, utf8) {
+                        self.replace_lines(start, end, lines);
+                    }
+                }
+                self.cursor_y = std::cmp::min(self.cursor_y, self.content.len() - 1);
+                self.cursor_x = self.content[self.cursor_y].floor_char_boundary(self.cursor_x);
+                continue;
+            }
+            let path = uri_to_path(uri).ok_or_else(|| format!("Cannot edit {}", uri))?;
+            let source = fs::read_to_string(&path)
+                .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+            let mut lines: Vec<String> = source.lines().map(String::from).collect();
+            for edit in edits_last_first(edits) {
+                if let Some((start, end, new_lines)) = text_edit_splice(&lines, edit, utf8) {
+                    lines.splice(start..end, new_lines);
+                }
+            }
+            fs::write(&path, lines.join("\n") + "\n")
+                .map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        }
+        Ok(files.len())
+    }
+
+    /// Sends a request about the cursor position, or says why it can't.
+    fn lsp_request(&mut self, method: &str, extra: Value, kind: Request) {
+        let Some(ref mut client) = self.lsp else {
+            self.set_status("No language server for this buffer");
+            return;
+        };
+        if !client.initialized {
+            let msg = format!("{} is still starting", client.command);
+            self.set_status(&msg);
+            return;
+        }
+        let mut params = client.position(&self.content, self.cursor_y, self.cursor_x);
+        if let (Some(params), Some(extra)) = (params.as_object_mut(), extra.as_object()) {
+            params.extend(extra.clone());
+        }
+        if let Err(e) = client.request(method, params, kind) {
+            self.set_status(&format!("LSP error: {}", e));
+        }
+    }
+
+    pub fn lsp_definition(&mut self) {
+        self.lsp_request("textDocument/definition", json!({}), Request::Definition);
+    }
+
+    /// Asks the server for completions at the cursor, if one is running.
+    pub fn lsp_completion(&mut self) {
+        if self.lsp.as_ref().is_some_and(|client| client.initialized) {
+            self.lsp_request("textDocument/completion", json!({}), Request::Completion);
+        }
+    }
+
+    pub fn lsp_hover(&mut self) {
+        self.lsp_request("textDocument/hover", json!({}), Request::Hover);
+    }
+
+    pub fn lsp_rename(&mut self, new_name: &str) {
+        if new_name.is_empty() {
+            self.set_status("Usage: :rename <new name>");
+            return;
+        }
+        self.lsp_request(
+            "textDocument/rename",
+            json!({ "newName": new_name }),
+            Request::Rename,
+        );
+    }
+
+    /// `:codeaction` lists the actions for the cursor line, `:codeaction n`
+    /// applies the n-th one listed.
+    pub fn lsp_code_action(&mut self, arg: &str) {
+        if !arg.is_empty() {
+            let action = match arg.parse::<usize>() {
+                Ok(n) if n > 0 => self
+                    .lsp
+                    .as_ref()
+                    .and_then(|client| client.code_actions.get(n - 1))
+                    .cloned(),
+                _ => None,
+            };
+            match action {
+                Some(action) => self.run_code_action(action, true),
+                None => self.set_status(&format!("No code action {}", arg)),
+            }
+            return;
+        }
+
+        let y = self.cursor_y;
+        let utf8 = self.lsp.as_ref().is_some_and(|client| client.utf8);
+        let end = lsp_col(&self.content[y], self.content[y].len(), utf8);
+        let diagnostics: Vec<Value> = self
+            .lsp
+            .iter()
+            .flat_map(|client| &client.diagnostics)
+            .filter(|d| d.line == y)
+            .map(|d| d.raw.clone())
+            .collect();
+        self.lsp_request(
+            "textDocument/codeAction",
+            json!({
+                "range": {
+                    "start": { "line": y, "character": 0 },
+                    "end": { "line": y, "character": end },
+                },
+                "context": { "diagnostics": diagnostics },
+            }),
+            Request::CodeAction,
+        );
+    }
+
+    /// Applies a code action's edit and runs its command, asking the server
+    /// to fill in the edit first if the action came without one.
+    fn run_code_action(&mut self, action: Value, resolve: bool) {
+        // a bare `Command` has a string where a code action has an object
+        if action["command"].is_string() {
+            return self.execute_lsp_command(&action);
+        }
+        if action.get("edit").is_none() && action["command"].is_null() && resolve {
+            if let Some(ref mut client) = self.lsp
+                && let Err(e) =
+                    client.request("codeAction/resolve", action, Request::ResolveCodeAction)
+            {
+                self.set_status(&format!("LSP error: {}", e));
+            }
+            return;
+        }
+        if let Some(edit) = action.get("edit")
+            && let Err(e) = self.apply_workspace_edit(edit)
+        {
+            self.set_status(&e);
+            return;
+        }
+        if action["command"].is_object() {
+            self.execute_lsp_command(&action["command"]);
+        }
+    }
+
+    fn execute_lsp_command(&mut self, command: &Value) {
+        let params = json!({
+            "command": command["command"],
+            "arguments": command.get("arguments").cloned().unwrap_or(json!([])),
+        });
+        if let Some(ref mut client) = self.lsp
+            && let Err(e) = client.request("workspace/executeCommand", params, Request::Other)
+        {
+            self.set_status(&format!("LSP error: {}", e));
+        }
+    }
+
+    /// The most severe diagnostic on line `y`.
+    pub fn line_diagnostic(&self, y: usize) -> Option<&Diagnostic> {
+        self.lsp
+            .as_ref()?
+            .diagnostics
+            .iter()
+            .filter(|d| d.line == y)
+            .min_by_key(|d| d.severity)
+    }
+
+    /// `:diagnostics` lists every diagnostic in the buffer.
+    pub fn list_diagnostics(&mut self) {
+        let lines: Vec<String> = self
+            .lsp
+            .iter()
+            .flat_map(|client| &client.diagnostics)
+            .map(|d| {
+                let message = d.message.replace('\n', " ");
+                format!("{}: {} {}", d.line + 1, d.severity.sign(), message)
+            })
+            .collect();
+        match lines.is_empty() {
+            true => self.set_status("No diagnostics"),
+            false => self.show_lines(lines),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    /// Writes `text` to a temporary Rust file and opens it in an editor
+    /// whose Rust server is `examples/mock_lsp.rs`, built by `cargo test`
+    /// next to the test binary.
+    fn editor_with_server(name: &str, text: &str) -> (Editor, PathBuf) {
+        let exe = std::env::current_exe().unwrap();
+        let mock = exe
+            .parent()
+            .unwrap()
+            .with_file_name("examples")
+            .join("mock_lsp");
+        let path = std::env::temp_dir().join(format!("eep-lsp-{}-{}.rs", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let mut editor = editor_with("");
+        let config = LspServerConfig {
+            command: mock.to_string_lossy().into_owned(),
+            args: Vec::new(),
+        };
+        editor.config.lsp.insert("rust".to_string(), config);
+        editor.open_file(&path.to_string_lossy()).unwrap();
+        wait_for(&mut editor, |editor| {
+            editor.lsp.as_ref().unwrap().initialized
+        });
+        (editor, path)
+    }
+
+    /// Handles server messages until `done`, failing after a few seconds.
+    fn wait_for(editor: &mut Editor, done: impl Fn(&Editor) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(editor) {
+            assert!(
+                Instant::now() < deadline,
+                "timed out waiting for the server"
+            );
+            editor.poll_lsp();
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn diagnostic_lines(editor: &Editor) -> Vec<usize> {
+        let client = editor.lsp.as_ref().unwrap();
+        client.diagnostics.iter().map(|d| d.line).collect()
+    }
+
+    #[test]
+    fn initialize_negotiates_encoding_and_sync() {
+        let (mut editor, path) = editor_with_server("init", "fn main() {}\n");
+        let client = editor.lsp.as_ref().unwrap();
+        assert!(client.utf8);
+        assert!(client.incremental);
+        assert_eq!(client.uri, path_to_uri(&path));
+        editor.stop_lsp();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn diagnostics_follow_edits() {
+        let (mut editor, path) = editor_with_server("diag", "fn main() {}\n// TODO\n");
+        wait_for(&mut editor, |editor| !diagnostic_lines(editor).is_empty());
+        assert_eq!(diagnostic_lines(&editor), [1]);
+        assert!(editor.line_diagnostic(1).unwrap().severity == Severity::Warning);
+
+        editor.type_keys("O// TODO: é<Esc>");
+        wait_for(&mut editor, |editor| diagnostic_lines(editor).len() == 2);
+        assert_eq!(diagnostic_lines(&editor), [0, 2]);
+        editor.stop_lsp();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn definition_jumps_within_the_file() {
+        let (mut editor, path) = editor_with_server("def", "fn foo() {}\nfn main() { foo(); }\n");
+        editor.type_keys("jff");
+        assert_eq!(editor.cursor_x, 12);
+        editor.lsp_definition();
+        wait_for(&mut editor, |editor| editor.cursor_y == 0);
+        assert_eq!(editor.cursor_x, 3);
+        editor.stop_lsp();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn rename_edits_every_occurrence() {
+        let (mut editor, path) =
+            editor_with_server("rename", "fn foo() {}\nfn main() { foo(); foobar(); }\n");
+        editor.type_keys("w");
+        editor.lsp_rename("bar");
+        wait_for(&mut editor, |editor| editor.content[0].contains("bar"));
+        assert_eq!(
+            editor.text(),
+            "fn bar() {}\nfn main() { bar(); foobar(); }\n"
+        );
+        assert_eq!(editor.status_msg, "Renamed in 1 file(s)");
+        editor.stop_lsp();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn switching_files_keeps_the_server() {
+        let (mut editor, a) = editor_with_server("switch-a", "fn a() {}\n// TODO\n");
+        wait_for(&mut editor, |editor| !diagnostic_lines(editor).is_empty());
+        let b = a.with_file_name(format!("eep-lsp-switch-b-{}.rs", std::process::id()));
+        fs::write(&b, "// TODO b\nfn b() {}\n").unwrap();
+        let server = editor.lsp.as_ref().unwrap().child.id();
+
+        editor.switch_file(&b.to_string_lossy()).unwrap();
+        let client = editor.lsp.as_ref().unwrap();
+        assert_eq!(client.child.id(), server);
+        assert_eq!(client.uri, path_to_uri(&b));
+        wait_for(&mut editor, |editor| !diagnostic_lines(editor).is_empty());
+        assert_eq!(diagnostic_lines(&editor), [0]);
+
+        // the server answers from the new file's text
+        editor.type_keys("jw");
+        editor.lsp_definition();
+        wait_for(&mut editor, |editor| editor.cursor_y == 0);
+        assert_eq!(editor.cursor_x, 8);
+        editor.stop_lsp();
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+}
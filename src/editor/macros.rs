@@ -0,0 +1,180 @@
+use crate::*;
+
+/// Macros started from within macros after which one is assumed to call
+/// itself forever.
+pub const MAX_MACRO_DEPTH: usize = 100;
+
+/// The register an uppercase name appends to, or `None` if `c` doesn't
+/// name a register. `"` is the unnamed register `:d` and `:y` use by
+/// default.
+pub fn register_name(c: char) -> Option<char> {
+    match c {
+        '"' => Some(c),
+        _ => c.is_ascii_alphabetic().then(|| c.to_ascii_lowercase()),
+    }
+}
+
+impl Editor {
+    /// `q{reg}`: starts recording typed keys into a register. An uppercase
+    /// name appends to the register instead. `q:` lists the command history.
+    pub fn start_recording(&mut self, c: char) {
+        if c == ':' {
+            self.list_command_history();
+            return;
+        }
+        if register_name(c).is_none() {
+            self.fail("Invalid register");
+            return;
+        }
+        self.recording = Some((c, Vec::new()));
+    }
+
+    /// Records a key typed while a macro is being recorded.
+    pub fn record_key(&mut self, key: Key) {
+        if let Some((_, ref mut keys)) = self.recording {
+            keys.push(key);
+        }
+    }
+
+    /// `q` while recording: stores the keys typed since `q{reg}`, leaving
+    /// out the `q` that ended it.
+    pub fn stop_recording(&mut self) {
+        let Some((c, mut keys)) = self.recording.take() else {
+            return;
+        };
+        keys.pop();
+        let Some(name) = register_name(c) else {
+            return;
+        };
+        let text = format_keys(&keys);
+        match c.is_ascii_uppercase() {
+            true => self.registers.entry(name).or_default().push_str(&text),
+            false => {
+                self.registers.insert(name, text);
+            }
+        }
+    }
+
+    /// `@{reg}` replays a register's keys, count times; `@@` replays the
+    /// last register played.
+    pub fn play_macro(&mut self, c: char) {
+        let name = match c {
+            '@' => match self.last_macro {
+                Some(name) => name,
+                None => {
+                    self.fail("No previous macro");
+                    return;
+                }
+            },
+            c => match register_name(c) {
+                Some(name) => name,
+                None => {
+                    self.fail("Invalid register");
+                    return;
+                }
+            },
+        };
+        let Some(text) = self.registers.get(&name) else {
+            self.fail(&format!("Register {} is empty", name));
+            return;
+        };
+        let keys = match parse_keys(text) {
+            Ok(keys) => keys,
+            Err(e) => {
+                self.fail(&format!("Register {}: {}", name, e));
+                return;
+            }
+        };
+
+        self.macro_depth += 1;
+        if self.macro_depth > MAX_MACRO_DEPTH {
+            self.input_queue.clear();
+            self.set_status("Recursive macro");
+            return;
+        }
+        self.last_macro = Some(name);
+        for _ in 0..self.take_count().unwrap_or(1) {
+            for key in keys.iter().rev() {
+                self.input_queue.push_front(QueuedKey {
+                    key: *key,
+                    remap: true,
+                });
+            }
+        }
+    }
+
+    /// `Ctrl-r{reg}` in insert mode: inserts a register's text.
+    pub fn insert_register(&mut self, c: char) {
+        let text = register_name(c).and_then(|name| self.registers.get(&name));
+        match text.cloned() {
+            Some(text) => self.insert_text(&text),
+            None => self.set_status(&format!("Register {} is empty", c)),
+        }
+    }
+
+    /// `:let @a = text` sets a register, so a recorded macro can be edited
+    /// as key notation.
+    pub fn set_register(&mut self, arg: &str) -> Result<(), String> {
+        let usage = || "Usage: let @{reg} = {keys}".to_string();
+        let (name, value) = arg.split_once('=').ok_or_else(usage)?;
+        let name = name.trim().strip_prefix('@').ok_or_else(usage)?;
+        let mut chars = name.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else {
+            return Err(usage());
+        };
+        let name = register_name(c).ok_or_else(|| "Invalid register".to_string())?;
+        let value = value.trim();
+        let value = ['"', '\'']
+            .iter()
+            .find_map(|&q| value.strip_prefix(q)?.strip_suffix(q))
+            .unwrap_or(value);
+        parse_keys(value)?;
+        self.registers.insert(name, value.to_string());
+        Ok(())
+    }
+
+    /// `:registers`: lists the non-empty registers.
+    pub fn list_registers(&mut self) {
+        let mut names: Vec<&char> = self.registers.keys().collect();
+        names.sort();
+        let lines: Vec<String> = names
+            .into_iter()
+            .map(|name| format!("\"{}   {}", name, self.registers[name].replace('\n', "^J")))
+            .collect();
+        match lines.is_empty() {
+            true => self.set_status("No registers set"),
+            false => self.show_lines(lines),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+
+    #[test]
+    fn recursive_macro_stops_at_the_last_line() {
+        let mut editor = editor_with("a\nb\nc");
+        editor.type_keys("qaA!<Esc>j@aq");
+        editor.type_keys("@a");
+        assert_eq!(editor.text(), "a!\nb!\nc!\n");
+        assert!(editor.input_queue.is_empty());
+    }
+
+    #[test]
+    fn failing_motion_stops_a_counted_macro() {
+        let mut editor = editor_with("one\ntwo\nthree");
+        editor.type_keys("qbI-<Esc>jq");
+        editor.type_keys("10@b");
+        assert_eq!(editor.text(), "-one\n-two\n-three\n");
+    }
+
+    #[test]
+    fn failing_find_stops_a_macro() {
+        let mut editor = editor_with("a,b,c\nd");
+        editor.type_keys("qcf,a-<Esc>q");
+        editor.type_keys("5@c");
+        assert_eq!(editor.text(), "a,-b,-c\nd\n");
+        assert_eq!(editor.cursor_y, 0);
+    }
+}
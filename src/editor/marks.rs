@@ -0,0 +1,252 @@
+use crate::*;
+use std::path::PathBuf;
+
+/// The mark `'` and `` ` `` both name: where the cursor was before the
+/// latest jump.
+const CONTEXT_MARK: char = '\'';
+
+fn mark_name(c: char) -> char {
+    match c {
+        '`' => CONTEXT_MARK,
+        _ => c,
+    }
+}
+
+/// Where line `y` ends up after lines `start..end` are replaced by
+/// `inserted` lines, or `None` if it was deleted.
+fn shifted_line(y: usize, start: usize, end: usize, inserted: usize) -> Option<usize> {
+    if y < start {
+        Some(y)
+    } else if y < end {
+        (y - start < inserted).then_some(y)
+    } else {
+        Some(y - (end - start) + inserted)
+    }
+}
+
+impl Editor {
+    /// The current file as an absolute path, which global marks are stored
+    /// under.
+    fn mark_path(&self) -> Option<PathBuf> {
+        self.filename
+            .as_ref()
+            .and_then(|path| std::path::absolute(path).ok())
+    }
+
+    /// Puts the current file's marks away while another file is open.
+    pub fn stash_marks(&mut self) {
+        let marks = std::mem::take(&mut self.marks);
+        if let Some(path) = self.mark_path()
+            && !marks.is_empty()
+        {
+            self.file_marks.insert(path, marks);
+        }
+    }
+
+    /// Brings back the marks of a file opened before.
+    pub fn restore_marks(&mut self) {
+        self.marks = self
+            .mark_path()
+            .and_then(|path| self.file_marks.remove(&path))
+            .unwrap_or_default();
+    }
+
+    /// `m{a-zA-Z}`: marks the cursor position. Uppercase marks remember the
+    /// file as well.
+    pub fn set_mark(&mut self, c: char) {
+        let cursor = (self.cursor_y, self.cursor_x);
+        match mark_name(c) {
+            c @ ('a'..='z' | CONTEXT_MARK) => {
+                self.marks.insert(c, cursor);
+            }
+            c @ 'A'..='Z' => match self.mark_path() {
+                Some(path) => {
+                    self.global_marks.insert(c, (path, cursor));
+                }
+                None => self.set_status("Uppercase marks need a file name"),
+            },
+            _ => self.fail("Invalid mark"),
+        }
+    }
+
+    /// `` `{mark} `` jumps to a mark, and `'{mark}` to the first non-blank
+    /// character of its line. An uppercase mark opens its file if needed.
+    pub fn goto_mark(&mut self, c: char, linewise: bool) {
+        let c = mark_name(c);
+        let target = match c {
+            'A'..='Z' => {
+                let Some((path, pos)) = self.global_marks.get(&c).cloned() else {
+                    self.fail("Mark not set");
+                    return;
+                };
+                if self.mark_path().as_ref() != Some(&path)
+                    && let Err(e) = self.switch_file(&path.to_string_lossy())
+                {
+                    self.fail(&e);
+                    return;
+                }
+                pos
+            }
+            _ => match self.marks.get(&c) {
+                Some(&pos) => pos,
+                None => {
+                    self.fail("Mark not set");
+                    return;
+                }
+            },
+        };
+        self.push_jump();
+        self.goto_pos(target);
+        if linewise {
+            self.cursor_to_indent();
+        }
+    }
+
+    /// The line of mark `c` in the current file, for ex line addresses.
+    pub fn mark_line(&self, c: char) -> Option<usize> {
+        match mark_name(c) {
+            c @ 'A'..='Z' => {
+                let (path, (y, _)) = self.global_marks.get(&c)?;
+                (Some(path) == self.mark_path().as_ref()).then_some(*y)
+            }
+            c => self.marks.get(&c).map(|&(y, _)| y),
+        }
+    }
+
+    /// Calls `f` on every mark in the current file, dropping those it
+    /// returns false for.
+    fn retain_marks(&mut self, mut f: impl FnMut(&mut Pos) -> bool) {
+        self.marks.retain(|_, pos| f(pos));
+        let path = self.mark_path();
+        self.global_marks
+            .retain(|_, (file, pos)| Some(&*file) != path.as_ref() || f(pos));
+    }
+
+    /// Moves marks, and the lines `:global` has left, to follow lines
+    /// `start..end` being replaced by `inserted` lines. Marks on deleted
+    /// lines are dropped.
+    pub fn adjust_marks(&mut self, start: usize, end: usize, inserted: usize) {
+        if end - start == inserted {
+            return;
+        }
+        if let Some(ref mut lines) = self.global_lines {
+            lines.retain_mut(|y| match shifted_line(*y, start, end, inserted) {
+                Some(line) => {
+                    *y = line;
+                    true
+                }
+                None => false,
+            });
+        }
+        self.retain_marks(|(y, _)| match shifted_line(*y, start, end, inserted) {
+            Some(line) => {
+                *y = line;
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Moves marks at or after `split` to follow the text after it to `to`,
+    /// for a line split in two. The first `skipped` bytes after the split
+    /// were dropped, so marks on them land on `to` itself.
+    pub fn split_marks(&mut self, split: Pos, skipped: usize, to: Pos) {
+        self.retain_marks(|pos| {
+            if pos.0 == split.0 && pos.1 >= split.1 {
+                *pos = (to.0, to.1 + (pos.1 - split.1).saturating_sub(skipped));
+            }
+            true
+        });
+    }
+
+    /// `:marks`: lists every mark with the text it is on.
+    pub fn list_marks(&mut self) {
+        let path = self.mark_path();
+        let mut marks: Vec<(char, Pos, String)> = self
+            .marks
+            .iter()
+            .map(|(&c, &(y, x))| {
+                let text = self.content.get(y).map_or("", |line| line.trim());
+                (c, (y, x), text.to_string())
+            })
+            .collect();
+        for (&c, (file, (y, x))) in &self.global_marks {
+            let text = match Some(file) == path.as_ref() {
+                true => self
+                    .content
+                    .get(*y)
+                    .map_or("", |line| line.trim())
+                    .to_string(),
+                false => file.to_string_lossy().into_owned(),
+            };
+            marks.push((c, (*y, *x), text));
+        }
+        // letters first, then the special marks
+        marks.sort_by_key(|(c, _, _)| (!c.is_ascii_alphabetic(), *c));
+
+        let mut lines = vec!["mark  line   col text".to_string()];
+        lines.extend(
+            marks
+                .into_iter()
+                .map(|(c, (y, x), text)| format!(" {}  {:>6} {:>5} {}", c, y + 1, x, text)),
+        );
+        match lines.len() {
+            1 => self.set_status("No marks set"),
+            _ => self.show_lines(lines),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::testing::*;
+    use std::fs;
+
+    fn files(name: &str) -> (String, String) {
+        let dir = std::env::temp_dir();
+        let a = dir.join(format!("eep-marks-{}-{}-a", name, std::process::id()));
+        let b = dir.join(format!("eep-marks-{}-{}-b", name, std::process::id()));
+        fs::write(&a, "a1\na2\na3\n").unwrap();
+        fs::write(&b, "b1\nb2\n").unwrap();
+        (
+            a.to_string_lossy().into_owned(),
+            b.to_string_lossy().into_owned(),
+        )
+    }
+
+    #[test]
+    fn global_mark_refuses_to_drop_unsaved_changes() {
+        let (a, b) = files("unsaved");
+        let mut editor = editor_with("");
+        editor.open_file(&b).unwrap();
+        editor.type_keys("jmB");
+        editor.open_file(&a).unwrap();
+        editor.type_keys("x'B");
+        assert_eq!(editor.status_msg, "No write since last change");
+        assert_eq!(editor.text(), "1\na2\na3\n");
+
+        editor.save_file().unwrap();
+        editor.type_keys("'B");
+        assert_eq!(editor.text(), "b1\nb2\n");
+        assert_eq!(editor.cursor_y, 1);
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn local_marks_are_kept_per_file() {
+        let (a, b) = files("local");
+        let mut editor = editor_with("");
+        editor.open_file(&a).unwrap();
+        editor.type_keys("jjma");
+        editor.open_file(&b).unwrap();
+        assert_eq!(editor.mark_line('a'), None);
+        editor.type_keys("jma");
+        editor.open_file(&a).unwrap();
+        assert_eq!(editor.mark_line('a'), Some(2));
+        editor.open_file(&b).unwrap();
+        assert_eq!(editor.mark_line('a'), Some(1));
+        fs::remove_file(a).unwrap();
+        fs::remove_file(b).unwrap();
+    }
+}
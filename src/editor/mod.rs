@@ -1,5 +1,56 @@
+pub mod action;
+pub use action::*;
 pub mod core;
 pub use core::*;
+pub mod autopair;
+pub use autopair::*;
+pub mod bracket;
+pub use bracket::*;
+pub mod cmdline;
+pub use cmdline::*;
 pub mod command;
+pub use command::*;
+pub mod completion;
+pub use completion::*;
+pub mod config;
+pub use config::*;
 pub mod cursor;
+pub mod edit;
+pub use edit::*;
+pub mod editorconfig;
+pub use editorconfig::*;
+pub mod ex;
+pub use ex::*;
+pub mod hex;
+pub use hex::*;
+pub mod highlight;
+pub use highlight::*;
+pub mod indent;
+pub use indent::*;
+pub mod jump;
+pub mod keymap;
+pub mod linewise;
+pub mod macros;
+pub use macros::*;
+pub mod marks;
+pub mod motion;
+pub use keymap::*;
+pub use motion::*;
+pub mod lsp;
+pub use lsp::*;
+pub mod pager;
+pub use pager::*;
 pub mod render;
+pub mod repeat;
+pub use repeat::*;
+pub mod selection;
+pub use selection::*;
+pub mod shell;
+#[cfg(test)]
+pub mod testing;
+pub mod theme;
+pub use theme::*;
+#[cfg(feature = "treesitter")]
+pub mod treesitter;
+#[cfg(feature = "treesitter")]
+pub use treesitter::*;
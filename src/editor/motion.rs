@@ -0,0 +1,536 @@
+use crate::*;
+
+/// What `iskeyword` means by default: letters, digits, `_` and the
+/// accented Latin-1 letters.
+pub const DEFAULT_ISKEYWORD: &str = "@,48-57,_,192-255";
+
+/// The characters `iskeyword` makes word characters, from a list like
+/// `@,48-57,_,-` of `@` for letters, characters, character codes and
+/// ranges of either.
+pub struct Keywords {
+    letters: bool,
+    ranges: Vec<(u32, u32)>,
+}
+
+impl Keywords {
+    pub fn parse(iskeyword: &str) -> Result<Keywords, String> {
+        let invalid = || format!("Invalid iskeyword: '{}'", iskeyword);
+        let code = |s: &str| -> Option<u32> {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(c as u32),
+                _ => s.parse().ok(),
+            }
+        };
+        let mut keywords = Keywords {
+            letters: false,
+            ranges: Vec::new(),
+        };
+        for part in iskeyword.split(',').filter(|part| !part.is_empty()) {
+            if part == "@" {
+                keywords.letters = true;
+                continue;
+            }
+            // a lone `-` is the character itself, not a range
+            let range = match part.split_once('-') {
+                Some((from, to)) if !from.is_empty() && !to.is_empty() => (
+                    code(from).ok_or_else(invalid)?,
+                    code(to).ok_or_else(invalid)?,
+                ),
+                _ => {
+                    let c = code(part).ok_or_else(invalid)?;
+                    (c, c)
+                }
+            };
+            keywords.ranges.push(range);
+        }
+        Ok(keywords)
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        (self.letters && c.is_alphabetic())
+            || self
+                .ranges
+                .iter()
+                .any(|&(from, to)| (from..=to).contains(&(c as u32)))
+            // past Latin-1, letters and digits are always word characters
+            || (c as u32 > 255 && c.is_alphanumeric())
+    }
+}
+
+/// Kinds of character word motions stop between.
+#[derive(PartialEq, Clone, Copy)]
+enum CharClass {
+    Blank,
+    Punctuation,
+    Word,
+}
+
+/// How a motion marks out the text an operator works on: up to the
+/// target, through it, or the whole lines between.
+#[derive(Clone, Copy, PartialEq)]
+pub enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+    /// A text object like `af`, which names a range around the cursor
+    /// rather than moving it. The range's end is exclusive.
+    TextObject,
+}
+
+/// Which way `f`, `F`, `t` and `T` look, and whether they stop before the
+/// character.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Find {
+    pub forward: bool,
+    pub till: bool,
+}
+
+impl Editor {
+    /// How the motion bound to the action called `name` works with an
+    /// operator, or `None` if it isn't a motion.
+    pub fn motion_kind(&self, name: &str) -> Option<MotionKind> {
+        let kind = match name {
+            "move_left" | "move_right" | "line_start" | "first_non_blank" | "word_forward"
+            | "big_word_forward" | "word_backward" | "big_word_backward" | "paragraph_forward"
+            | "paragraph_backward" | "sentence_forward" | "sentence_backward" | "goto_mark"
+            | "find_char_backward" | "till_char_backward" | "next_function"
+            | "previous_function" => MotionKind::Exclusive,
+            "line_end"
+            | "word_end"
+            | "big_word_end"
+            | "word_end_backward"
+            | "big_word_end_backward"
+            | "find_char"
+            | "till_char"
+            | "match_bracket" => MotionKind::Inclusive,
+            "move_up"
+            | "move_down"
+            | "goto_first_line"
+            | "goto_last_line"
+            | "line_down_first_non_blank"
+            | "screen_top"
+            | "screen_middle"
+            | "screen_bottom"
+            | "goto_mark_line" => MotionKind::Linewise,
+            "select_function" | "select_function_body" | "select_class" | "select_class_body" => {
+                MotionKind::TextObject
+            }
+            // `;` and `,` work like the `f` or `F` they repeat
+            "repeat_find" | "repeat_find_reverse" => {
+                let (_, find) = self.last_find?;
+                match find.forward == (name == "repeat_find") {
+                    true => MotionKind::Inclusive,
+                    false => MotionKind::Exclusive,
+                }
+            }
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// Whether the motion bound to the action called `name` failed, having
+    /// left the cursor at `before`. Motions like `j` fail when there is
+    /// nowhere to go, unlike `0` or `gg` which may already be there.
+    pub fn motion_failed(&self, name: &str, before: Pos) -> bool {
+        before == (self.cursor_y, self.cursor_x)
+            && self.awaiting_char.is_none()
+            && matches!(
+                name,
+                "move_left"
+                    | "move_right"
+                    | "move_up"
+                    | "move_down"
+                    | "word_forward"
+                    | "big_word_forward"
+                    | "word_backward"
+                    | "big_word_backward"
+                    | "word_end"
+                    | "big_word_end"
+                    | "word_end_backward"
+                    | "big_word_end_backward"
+                    | "paragraph_forward"
+                    | "paragraph_backward"
+                    | "sentence_forward"
+                    | "sentence_backward"
+            )
+    }
+
+    /// The character at `pos`, with the end of a line read as a newline.
+    fn char_at(&self, (y, x): Pos) -> char {
+        self.content[y][x..].chars().next().unwrap_or('\n')
+    }
+
+    /// The position after `pos`, counting each line end as a character.
+    fn step_forward(&self, (y, x): Pos) -> Option<Pos> {
+        let line = &self.content[y];
+        match line[x..].chars().next() {
+            Some(c) => Some((y, x + c.len_utf8())),
+            None if y + 1 < self.content.len() => Some((y + 1, 0)),
+            None => None,
+        }
+    }
+
+    fn step_backward(&self, (y, x): Pos) -> Option<Pos> {
+        match x {
+            0 if y == 0 => None,
+            0 => Some((y - 1, self.content[y - 1].len())),
+            _ => Some((y, self.content[y].floor_char_boundary(x - 1))),
+        }
+    }
+
+    fn char_class(&self, pos: Pos, big: bool, keywords: &Keywords) -> CharClass {
+        let c = self.char_at(pos);
+        if c.is_whitespace() {
+            CharClass::Blank
+        } else if big || keywords.contains(c) {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+
+    fn keywords(&self) -> Keywords {
+        Keywords::parse(&self.config.iskeyword)
+            .or_else(|_| Keywords::parse(DEFAULT_ISKEYWORD))
+            .unwrap_or(Keywords {
+                letters: true,
+                ranges: Vec::new(),
+            })
+    }
+
+    fn is_empty_line(&self, (y, _): Pos) -> bool {
+        self.content[y].is_empty()
+    }
+
+    /// `w` and `W`: the start of the next word. Empty lines count as words.
+    pub fn next_word_start(&self, pos: Pos, big: bool) -> Pos {
+        let keywords = self.keywords();
+        let class = |p| self.char_class(p, big, &keywords);
+        let start = class(pos);
+        let mut p = pos;
+        while start != CharClass::Blank && class(p) == start {
+            match self.step_forward(p) {
+                Some(next) => p = next,
+                None => return p,
+            }
+        }
+        while class(p) == CharClass::Blank && (p == pos || !self.is_empty_line(p)) {
+            match self.step_forward(p) {
+                Some(next) => p = next,
+                None => return p,
+            }
+        }
+        p
+    }
+
+    /// `e` and `E`: the end of this word, or of the next one when already
+    /// there.
+    pub fn next_word_end(&self, pos: Pos, big: bool) -> Pos {
+        let keywords = self.keywords();
+        let class = |p| self.char_class(p, big, &keywords);
+        let Some(mut p) = self.step_forward(pos) else {
+            return pos;
+        };
+        while class(p) == CharClass::Blank {
+            match self.step_forward(p) {
+                Some(next) => p = next,
+                None => return p,
+            }
+        }
+        let word = class(p);
+        while let Some(next) = self.step_forward(p)
+            && class(next) == word
+        {
+            p = next;
+        }
+        p
+    }
+
+    /// The end of the word at `pos`, which unlike `e` may be `pos` itself,
+    /// for `cw`.
+    pub fn current_word_end(&self, pos: Pos, big: bool) -> Pos {
+        let keywords = self.keywords();
+        let class = |p| self.char_class(p, big, &keywords);
+        let word = class(pos);
+        let mut p = pos;
+        while let Some(next) = self.step_forward(p)
+            && class(next) == word
+        {
+            p = next;
+        }
+        p
+    }
+
+    /// `b` and `B`: the start of this word, or of the previous one when
+    /// already there.
+    pub fn previous_word_start(&self, pos: Pos, big: bool) -> Pos {
+        let keywords = self.keywords();
+        let class = |p| self.char_class(p, big, &keywords);
+        let Some(mut p) = self.step_backward(pos) else {
+            return pos;
+        };
+        while class(p) == CharClass::Blank {
+            if self.is_empty_line(p) {
+                return p;
+            }
+            match self.step_backward(p) {
+                Some(previous) => p = previous,
+                None => return p,
+            }
+        }
+        let word = class(p);
+        while let Some(previous) = self.step_backward(p)
+            && class(previous) == word
+        {
+            p = previous;
+        }
+        p
+    }
+
+    /// `ge` and `gE`: the end of the previous word.
+    pub fn previous_word_end(&self, pos: Pos, big: bool) -> Pos {
+        let keywords = self.keywords();
+        let class = |p| self.char_class(p, big, &keywords);
+        let start = class(pos);
+        let mut p = pos;
+        loop {
+            match self.step_backward(p) {
+                Some(previous) => p = previous,
+                None => return pos,
+            }
+            if start == CharClass::Blank || class(p) != start {
+                break;
+            }
+        }
+        while class(p) == CharClass::Blank && !self.is_empty_line(p) {
+            match self.step_backward(p) {
+                Some(previous) => p = previous,
+                None => return p,
+            }
+        }
+        p
+    }
+
+    /// `^`: the first non-blank character of line `y`.
+    pub fn first_non_blank(&self, y: usize) -> Pos {
+        let line = &self.content[y];
+        (y, line.len() - line.trim_start().len())
+    }
+
+    /// `}`: the next empty line after a paragraph, or the end of the buffer.
+    pub fn next_paragraph(&self, (y, _): Pos) -> Pos {
+        let last = self.content.len() - 1;
+        let mut y = y;
+        while y < last && self.content[y].is_empty() {
+            y += 1;
+        }
+        while y < last && !self.content[y].is_empty() {
+            y += 1;
+        }
+        match self.content[y].is_empty() {
+            true => (y, 0),
+            false => (y, self.content[y].len()),
+        }
+    }
+
+    /// `{`: the previous empty line before a paragraph, or the start of the
+    /// buffer.
+    pub fn previous_paragraph(&self, (y, _): Pos) -> Pos {
+        let mut y = y;
+        while y > 0 && self.content[y].is_empty() {
+            y -= 1;
+        }
+        while y > 0 && !self.content[y].is_empty() {
+            y -= 1;
+        }
+        (y, 0)
+    }
+
+    /// Whether a sentence starts at `pos`: the first character after a `.`,
+    /// `!` or `?` (and any closing brackets and quotes) followed by blanks,
+    /// or an empty line.
+    fn is_sentence_start(&self, pos: Pos) -> bool {
+        if self.is_empty_line(pos) {
+            return true;
+        }
+        if self.char_at(pos).is_whitespace() {
+            return false;
+        }
+        let mut p = pos;
+        let mut blanks = false;
+        loop {
+            match self.step_backward(p) {
+                Some(previous) => p = previous,
+                None => return true,
+            }
+            if !self.char_at(p).is_whitespace() {
+                break;
+            }
+            if self.is_empty_line(p) {
+                return true;
+            }
+            blanks = true;
+        }
+        if !blanks {
+            return false;
+        }
+        while matches!(self.char_at(p), ')' | ']' | '"' | '\'') {
+            match self.step_backward(p) {
+                Some(previous) => p = previous,
+                None => return false,
+            }
+        }
+        matches!(self.char_at(p), '.' | '!' | '?')
+    }
+
+    /// `)`: the start of the next sentence.
+    pub fn next_sentence(&self, pos: Pos) -> Pos {
+        let mut p = pos;
+        while let Some(next) = self.step_forward(p) {
+            p = next;
+            if self.is_sentence_start(p) {
+                break;
+            }
+        }
+        p
+    }
+
+    /// `(`: the start of this sentence, or of the previous one when already
+    /// there.
+    pub fn previous_sentence(&self, pos: Pos) -> Pos {
+        let mut p = pos;
+        while let Some(previous) = self.step_backward(p) {
+            p = previous;
+            if self.is_sentence_start(p) {
+                break;
+            }
+        }
+        p
+    }
+
+    /// `H`, `M` and `L`: the first non-blank character of the line `count`
+    /// lines from the top of the screen, in its middle, or `count` lines
+    /// from its bottom.
+    pub fn screen_line(&self, place: char, count: usize) -> Pos {
+        let last = self.content.len() - 1;
+        let bottom = std::cmp::min(self.offset_y + self.screen_rows.saturating_sub(1), last);
+        let y = match place {
+            'H' => std::cmp::min(self.offset_y + count - 1, bottom),
+            'M' => self.offset_y + (bottom - self.offset_y) / 2,
+            _ => std::cmp::max(bottom.saturating_sub(count - 1), self.offset_y),
+        };
+        self.first_non_blank(y)
+    }
+
+    /// `f`, `F`, `t` and `T`: the next `c` on the line in the direction of
+    /// `find`, or the character before it.
+    pub fn find_in_line(&self, (y, x): Pos, c: char, find: Find) -> Option<Pos> {
+        let line = &self.content[y];
+        let x = match find.forward {
+            true => {
+                let from = x + line[x..].chars().next().map_or(0, char::len_utf8);
+                let found = from + line.get(from..)?.find(c)?;
+                match find.till {
+                    true => line.floor_char_boundary(found - 1),
+                    false => found,
+                }
+            }
+            false => {
+                let found = line[..x].rfind(c)?;
+                match find.till {
+                    true => found + c.len_utf8(),
+                    false => found,
+                }
+            }
+        };
+        Some((y, x))
+    }
+
+    /// Moves the cursor by `motion` once for each of the count, recording a
+    /// jump first for the motions that are jumps.
+    pub fn move_by(&mut self, motion: fn(&Editor, Pos) -> Pos, jump: bool) {
+        let count = self.take_count().unwrap_or(1);
+        let mut pos = (self.cursor_y, self.cursor_x);
+        for _ in 0..count {
+            let next = motion(self, pos);
+            if next == pos {
+                break;
+            }
+            pos = next;
+        }
+        if jump && pos != (self.cursor_y, self.cursor_x) {
+            self.push_jump();
+        }
+        (self.cursor_y, self.cursor_x) = pos;
+    }
+
+    /// Goes to the line `screen_line` picks, with the count, recording a
+    /// jump.
+    pub fn goto_screen_line(&mut self, place: char) {
+        let count = self.take_count().unwrap_or(1);
+        let target = self.screen_line(place, count);
+        self.push_jump();
+        (self.cursor_y, self.cursor_x) = target;
+    }
+
+    /// `f{char}` and friends, remembered for `;` and `,`.
+    pub fn find_char(&mut self, c: char, find: Find) {
+        self.last_find = Some((c, find));
+        self.repeat_find(false, false);
+    }
+
+    /// Goes to the last `f`, `F`, `t` or `T` target again; `;` repeats it
+    /// and `,` repeats it the other way.
+    pub fn repeat_find(&mut self, reverse: bool, repeat: bool) {
+        let Some((c, mut find)) = self.last_find else {
+            return;
+        };
+        find.forward ^= reverse;
+        let mut pos = (self.cursor_y, self.cursor_x);
+        for i in 0..self.take_count().unwrap_or(1) {
+            let mut next = self.find_in_line(pos, c, find);
+            // repeating `t` right before its character would stay put, so
+            // it looks past that one
+            if (repeat || i > 0) && next == Some(pos) {
+                let skipped = match find.forward {
+                    true => self.step_forward(pos),
+                    false => self.step_backward(pos),
+                };
+                next = skipped
+                    .filter(|&(y, _)| y == pos.0)
+                    .and_then(|skipped| self.find_in_line(skipped, c, find));
+            }
+            match next {
+                Some(next) => pos = next,
+                None => break,
+            }
+        }
+        if pos == (self.cursor_y, self.cursor_x) {
+            self.fail("");
+        }
+        (self.cursor_y, self.cursor_x) = pos;
+    }
+
+    /// `Ctrl-d`, `Ctrl-u`, `Ctrl-f` and `Ctrl-b`: scrolls the view by
+    /// `rows` and moves the cursor along with it.
+    pub fn scroll_by(&mut self, rows: usize, down: bool) {
+        let last = self.content.len() - 1;
+        let last_top = self.content.len().saturating_sub(self.screen_rows);
+        if down {
+            if self.cursor_y == last {
+                return;
+            }
+            self.offset_y = std::cmp::min(self.offset_y + rows, last_top);
+            self.cursor_y = std::cmp::min(std::cmp::max(self.cursor_y + rows, self.offset_y), last);
+        } else {
+            if self.cursor_y == 0 {
+                return;
+            }
+            self.offset_y = self.offset_y.saturating_sub(rows);
+            let bottom = self.offset_y + self.screen_rows.saturating_sub(1);
+            self.cursor_y = std::cmp::min(self.cursor_y.saturating_sub(rows), bottom);
+        }
+        self.cursor_to_indent();
+    }
+}
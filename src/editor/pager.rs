@@ -0,0 +1,68 @@
+use crate::*;
+use crossterm::event::KeyCode;
+
+/// Output too long for the status bar, shown over the text area until
+/// dismissed.
+pub struct Pager {
+    pub lines: Vec<String>,
+    pub offset: usize,
+}
+
+impl Editor {
+    /// Shows `lines` in the pager, or in the status bar if there is just one.
+    pub fn show_lines(&mut self, lines: Vec<String>) {
+        match lines.len() {
+            0 => {}
+            1 => self.set_status(&lines[0]),
+            _ => self.pager = Some(Pager { lines, offset: 0 }),
+        }
+    }
+
+    pub fn handle_pager_key(&mut self, key: Key) {
+        let page = self.screen_rows.max(1);
+        let Some(pager) = self.pager.as_mut() else {
+            return;
+        };
+        let last_top = pager.lines.len().saturating_sub(page);
+        let at_end = pager.offset >= last_top;
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => {
+                pager.offset = std::cmp::min(pager.offset + 1, last_top);
+            }
+            KeyCode::Enter if !at_end => {
+                pager.offset = std::cmp::min(pager.offset + 1, last_top);
+            }
+            KeyCode::Char('k') | KeyCode::Up => pager.offset = pager.offset.saturating_sub(1),
+            KeyCode::Char(' ') | KeyCode::PageDown if !at_end => {
+                pager.offset = std::cmp::min(pager.offset + page, last_top);
+            }
+            KeyCode::Char('b') | KeyCode::PageUp => {
+                pager.offset = pager.offset.saturating_sub(page);
+            }
+            KeyCode::Char('g') => pager.offset = 0,
+            KeyCode::Char('G') => pager.offset = last_top,
+            _ => self.pager = None,
+        }
+    }
+
+    /// Draws the pager over the text area, returning the status line prompt.
+    pub fn render_pager(&self, output: &mut String) -> Option<String> {
+        let pager = self.pager.as_ref()?;
+        for (row, line) in pager
+            .lines
+            .iter()
+            .skip(pager.offset)
+            .take(self.screen_rows)
+            .enumerate()
+        {
+            let end = line.floor_char_boundary(self.screen_cols);
+            output.push_str(&format!("\x1B[{};1H{}", row + 1, &line[..end]));
+        }
+        let prompt = if pager.offset + self.screen_rows >= pager.lines.len() {
+            "Press ENTER or type any key to continue"
+        } else {
+            "-- More -- (j/k scroll, SPACE next page, q quit)"
+        };
+        Some(prompt.to_string())
+    }
+}
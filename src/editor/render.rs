@@ -6,19 +6,7 @@ use crossterm::{
     terminal::{size, Clear, ClearType, EnterAlternateScreen},
 };
 use std::io::Write;
-
-const STATUS_FILENAME_FG: &str = "\x1B[38;5;231m"; // White text
-const STATUS_FILENAME_BG: &str = "\x1B[48;5;240m"; // Dark gray background
-const STATUS_MODE_FG: &str = "\x1B[35;5;213m";
-const STATUS_MODE_BG: &str = "\x1B[48;5;236m"; // Darker gray background
-const STATUS_MSG_FG: &str = "\x1B[38;5;220m"; // Yellow text
-const STATUS_MSG_BG: &str = "\x1B[48;5;236m"; // Darker gray background
-const STATUS_CMD_FG: &str = "\x1B[38;5;117m"; // Light blue text
-const STATUS_CMD_BG: &str = "\x1B[48;5;236m"; // Darker gray background
-const STATUS_INFO_FG: &str = "\x1B[38;5;255m"; // Light gray text
-const STATUS_INFO_BG: &str = "\x1B[48;5;236m"; // Darker gray background
-const STATUS_TRANSPARENT_BG: &str = "\x1B[49m"; // Transparent background
-const RESET: &str = "\x1B[0m";
+use std::ops::Range;
 
 impl Editor {
     pub fn render(&mut self) -> Result<(), Box<dyn std::error::Error>> {
@@ -29,65 +17,199 @@ impl Editor {
         self.screen_cols = cols as usize;
         self.screen_rows = rows as usize - 2;
 
-        let line_num_width = if self.show_line_numbers {
-            (self.content.len() as f32).log10().floor() as usize + 1
-        } else {
-            0
-        };
-
         let mut output = String::new();
 
-        if self.cursor_y >= self.offset_y + self.screen_rows - 1 {
-            self.offset_y = self.cursor_y.saturating_sub(self.screen_rows - 1) + 1;
+        if let Some(view) = self.hex.as_mut() {
+            view.scroll(self.screen_rows);
+            for row in 0..self.screen_rows {
+                if let Some(line) = view.format_row(row + view.offset_row) {
+                    output.push_str(&format!("\x1B[{};{}H{}", row + 1, 1, line));
+                }
+            }
+            let (cursor_row, cursor_col) = view.cursor_position();
+            output.push_str(&self.build_status_bar());
+            output.push_str(&format!("\x1B[{};{}H", cursor_row + 1, cursor_col));
+            execute!(self.stdout, SetCursorStyle::SteadyUnderScore)?;
+            execute!(self.stdout, Print(output))?;
+            self.stdout.flush()?;
+            return Ok(());
         }
-        if self.cursor_y < self.offset_y {
-            self.offset_y = self.cursor_y;
+
+        if let Some(prompt) = self.render_pager(&mut output) {
+            output.push_str(&format!(
+                "\x1B[{};1H{}{}{}",
+                self.screen_rows + 1,
+                self.theme.sgr("status_msg"),
+                prompt,
+                RESET
+            ));
+            execute!(self.stdout, Print(output))?;
+            self.stdout.flush()?;
+            return Ok(());
         }
 
-        for row in 0..self.screen_rows {
-            let content_row = row + self.offset_y;
-            if content_row < self.content.len() {
-                if self.show_line_numbers {
+        self.scroll();
+        let line_num_width = self.line_num_width();
+        let gutter_width = self.gutter_width();
+        let text_width = self.text_width();
+
+        if let Some(ref mut highlighter) = self.highlighter {
+            highlighter.update(&self.content, self.offset_y + self.screen_rows);
+        }
+        #[cfg(feature = "treesitter")]
+        let tree_spans = self.syntax_tree.as_mut().map(|tree| {
+            tree.highlight_lines(
+                &self.content,
+                self.offset_y,
+                self.offset_y + self.screen_rows,
+            )
+        });
+
+        let bracket = self.highlighted_bracket();
+        let glyphs = match self.config.list {
+            true => tab_glyphs(&self.config.listchars).unwrap_or((' ', ' ')),
+            false => (' ', ' '),
+        };
+
+        let mut row = 0;
+        let mut content_row = self.offset_y;
+        while row < self.screen_rows && content_row < self.content.len() {
+            let (line, offsets) =
+                expand_tabs(&self.content[content_row], self.config.tabstop, glyphs);
+            let line = &line;
+            let segments = if self.config.wrap {
+                wrap_line(line, text_width)
+            } else {
+                vec![visible_range(line, self.offset_x, text_width)]
+            };
+            let mut spans = match self.highlighter {
+                Some(ref highlighter) => highlighter.spans(content_row).to_vec(),
+                None => Vec::new(),
+            };
+            #[cfg(feature = "treesitter")]
+            if let Some(ref lines) = tree_spans {
+                spans = lines[content_row - self.offset_y].clone();
+            }
+            if let Some(range) = self.selection_range(content_row) {
+                spans = overlay_span(&spans, range, "selection");
+            }
+            if let Some((y, x)) = bracket
+                && y == content_row
+            {
+                spans = overlay_span(&spans, x..x + 1, "matching_bracket");
+            }
+            for span in &mut spans {
+                span.start = offsets[span.start];
+                span.end = offsets[span.end];
+            }
+
+            let last_segment = segments.len() - 1;
+            let diagnostic = self.line_diagnostic(content_row);
+
+            for (i, segment) in segments.into_iter().enumerate() {
+                if row >= self.screen_rows {
+                    break;
+                }
+                if self.lsp.is_some() {
+                    let sign = match diagnostic {
+                        Some(d) if i == 0 => format!(
+                            "{}{}{} ",
+                            self.theme.sgr(d.severity.group()),
+                            d.severity.sign(),
+                            RESET
+                        ),
+                        _ => "  ".to_string(),
+                    };
+                    output.push_str(&format!("\x1B[{};1H{}", row + 1, sign));
+                }
+                if self.config.line_numbers && i == 0 {
+                    let group = if content_row == self.cursor_y {
+                        "cursor_line_nr"
+                    } else {
+                        "line_nr"
+                    };
                     let line_num = format!(
-                        "{:>width$} \x1B[90m\x1B[39m ",
+                        "{}{:>width$}{}  ",
+                        self.theme.sgr(group),
                         content_row + 1,
+                        RESET,
                         width = line_num_width
                     );
-                    output.push_str(&format!("\x1B[{};{}H{}", row + 1, 1, line_num));
-                }
-
-                let line = &self.content[content_row];
-                let line_len = line.len();
-                let start = self.offset_x;
-
-                if start < line_len {
-                    let visible_part = &line[start
-                        ../*std::cmp::min(start + self.screen_cols - line_num_width - 3, */line_len];//)];
                     output.push_str(&format!(
                         "\x1B[{};{}H{}",
                         row + 1,
-                        line_num_width + 3,
-                        visible_part
+                        self.sign_width() + 1,
+                        line_num
                     ));
                 }
+                let cursor_line = match self.theme.style("cursor_line") {
+                    Some(style) if content_row == self.cursor_y => style.sgr(self.theme.depth),
+                    _ => String::new(),
+                };
+                let used = line[segment.clone()].chars().count();
+                // diagnostics are shown as virtual text after the line
+                let virtual_text = match diagnostic {
+                    Some(d) if i == last_segment => {
+                        let message = d.message.lines().next().unwrap_or_default();
+                        let room = text_width.saturating_sub(used);
+                        let text: String = format!("  {}", message).chars().take(room).collect();
+                        text
+                    }
+                    _ => String::new(),
+                };
+                let padding = if cursor_line.is_empty() {
+                    0
+                } else {
+                    text_width.saturating_sub(used + virtual_text.chars().count())
+                };
+                output.push_str(&format!(
+                    "\x1B[{};{}H{}{}{}{}{}{}",
+                    row + 1,
+                    gutter_width + 1,
+                    self.styled_text(line, segment, &spans, &cursor_line),
+                    diagnostic.map_or(String::new(), |d| self.theme.sgr(d.severity.group())),
+                    virtual_text,
+                    cursor_line,
+                    " ".repeat(padding),
+                    RESET
+                ));
+                row += 1;
             }
+            content_row += 1;
         }
 
         let status_bar = self.build_status_bar();
         output.push_str(&status_bar);
 
-        let cursor_row = (self.cursor_y - self.offset_y) as u16 + 1;
-        let cursor_col = if self.show_line_numbers {
-            (self.cursor_x - self.offset_x) as u16 + line_num_width as u16 + 3
+        let col = self.display_col(self.cursor_y, self.cursor_x);
+        let (cursor_row, cursor_col) = if self.config.wrap {
+            let rows_above: usize = (self.offset_y..self.cursor_y)
+                .map(|y| self.line_height(y, text_width))
+                .sum();
+            (
+                rows_above + col / text_width + 1,
+                gutter_width + col % text_width + 1,
+            )
         } else {
-            (self.cursor_x - self.offset_x) as u16 + 1
+            (
+                self.cursor_y - self.offset_y + 1,
+                gutter_width + col - self.offset_x + 1,
+            )
         };
         match self.mode {
             Mode::Normal => execute!(self.stdout, SetCursorStyle::SteadyBlock)?,
             Mode::Insert => execute!(self.stdout, SetCursorStyle::SteadyBar)?,
-            Mode::Command => execute!(self.stdout, SetCursorStyle::SteadyBlock)?,
+            Mode::Replace => execute!(self.stdout, SetCursorStyle::SteadyUnderScore)?,
+            Mode::Command | Mode::Hex | Mode::Visual => {
+                execute!(self.stdout, SetCursorStyle::SteadyBlock)?
+            }
         }
-        let cursor_row = std::cmp::min(cursor_row, self.screen_rows as u16);
+        let cursor_row = std::cmp::min(cursor_row, self.screen_rows);
+        self.render_completion(&mut output, cursor_row, cursor_col);
+        let (cursor_row, cursor_col) = match self.command_line_column() {
+            Some(column) => (self.screen_rows + 1, column),
+            None => (cursor_row, cursor_col),
+        };
         output.push_str(&format!("\x1B[{};{}H", cursor_row, cursor_col));
 
         execute!(self.stdout, Print(output))?;
@@ -95,11 +217,84 @@ impl Editor {
         Ok(())
     }
 
-    fn build_status_bar(&self) -> String {
+    pub fn line_num_width(&self) -> usize {
+        if self.config.line_numbers {
+            (self.content.len() as f32).log10().floor() as usize + 1
+        } else {
+            0
+        }
+    }
+
+    /// Columns taken up by the gutter: signs, line numbers and padding.
+    pub fn gutter_width(&self) -> usize {
+        let numbers = if self.config.line_numbers {
+            self.line_num_width() + 2
+        } else {
+            0
+        };
+        self.sign_width() + numbers
+    }
+
+    /// Columns taken up by the diagnostic sign column, shown while a
+    /// language server is attached.
+    pub fn sign_width(&self) -> usize {
+        if self.lsp.is_some() {
+            2
+        } else {
+            0
+        }
+    }
+
+    pub fn text_width(&self) -> usize {
+        std::cmp::max(1, self.screen_cols.saturating_sub(self.gutter_width()))
+    }
+
+    /// Renders `line[range]` with each highlighted span in its theme style,
+    /// returning to `base` (the cursor line style, if any) in between.
+    fn styled_text(&self, line: &str, range: Range<usize>, spans: &[Span], base: &str) -> String {
+        let mut styled = String::from(base);
+        let mut pos = range.start;
+        for span in spans {
+            let start = std::cmp::max(span.start, pos);
+            let end = std::cmp::min(span.end, range.end);
+            if start >= end {
+                continue;
+            }
+            styled.push_str(&line[pos..start]);
+            styled.push_str(&self.theme.sgr(span.scope));
+            styled.push_str(&line[start..end]);
+            styled.push_str(RESET);
+            styled.push_str(base);
+            pos = end;
+        }
+        styled.push_str(&line[pos..range.end]);
+        styled
+    }
+
+    /// The count and keys typed so far of a command not yet run, shown in
+    /// the status bar like `2d`.
+    fn pending_command(&self) -> String {
+        let mut pending = self.count.map(|n| n.to_string()).unwrap_or_default();
+        if matches!(self.mode, Mode::Normal | Mode::Visual) {
+            let keys: Vec<Key> = self.pending_keys.iter().map(|k| k.key).collect();
+            pending.push_str(&format_keys(&keys));
+        }
+        match pending.is_empty() {
+            true => pending,
+            false => pending + "  ",
+        }
+    }
+
+    /// The file name and mode at the start of the status bar.
+    fn status_left(&self) -> String {
         let mode = match self.mode {
+            Mode::Normal if self.insert_oneshot => "(INSERT)",
             Mode::Normal => "NORMAL",
             Mode::Insert => "INSERT",
             Mode::Command => "COMMAND",
+            Mode::Hex => "HEX",
+            Mode::Visual => "VISUAL",
+            Mode::Replace => "REPLACE",
         };
 
         let filename = self
@@ -108,29 +303,68 @@ impl Editor {
             .map(|p| p.to_string_lossy().into_owned())
             .unwrap_or_else(|| "[No Name]".to_string());
 
-        let left_segment = format!(
-            "{}{}{} -- {}{}{} -- ",
-            STATUS_FILENAME_FG, filename, RESET, STATUS_MODE_FG, mode, RESET,
-        );
-
-        let right_segment = format!(
-            "{}{}Ln {}/{} Col {}",
-            STATUS_INFO_FG,
-            STATUS_TRANSPARENT_BG,
-            self.cursor_y + 1,
-            self.content.len(),
-            self.cursor_x + 1
-        );
-
-        let middle_content = if !self.status_msg.is_empty() {
+        let recording = match self.recording {
+            Some((c, _)) => format!(" recording @{}", c),
+            None => String::new(),
+        };
+        format!(
+            "{}{}{} -- {}{}{}{} -- ",
+            self.theme.sgr("status_filename"),
+            filename,
+            RESET,
+            self.theme.sgr("status_mode"),
+            mode,
+            recording,
+            RESET,
+        )
+    }
+
+    /// The screen column of the cursor on the command line, while it is
+    /// being edited.
+    fn command_line_column(&self) -> Option<usize> {
+        if self.mode != Mode::Command || !self.show_command {
+            return None;
+        }
+        let typed = self.command_buffer[..self.command_cursor].chars().count();
+        let column = visible_length(&self.status_left()) + typed + 2;
+        Some(std::cmp::min(column, self.screen_cols))
+    }
+
+    fn build_status_bar(&self) -> String {
+        let left_segment = self.status_left();
+
+        let right_segment = match self.hex {
+            Some(ref view) => format!(
+                "{}Off {:#x}/{:#x}{}",
+                self.theme.sgr("status_info"),
+                view.cursor,
+                view.bytes.len(),
+                RESET
+            ),
+            None => format!(
+                "{}{}Ln {}/{} Col {}{}",
+                self.theme.sgr("status_info"),
+                self.pending_command(),
+                self.cursor_y + 1,
+                self.content.len(),
+                self.cursor_x + 1,
+                RESET
+            ),
+        };
+
+        let middle_content = if self.show_command {
             format!(
-                "{}{}{}",
-                STATUS_MSG_FG, STATUS_TRANSPARENT_BG, self.status_msg
+                "{}:{}{}",
+                self.theme.sgr("status_cmd"),
+                self.command_buffer,
+                RESET
             )
-        } else if self.show_command {
+        } else if !self.status_msg.is_empty() {
             format!(
-                "{}{}:{}",
-                STATUS_CMD_FG, STATUS_TRANSPARENT_BG, self.command_buffer
+                "{}{}{}",
+                self.theme.sgr("status_msg"),
+                self.status_msg,
+                RESET
             )
         } else {
             String::new()
@@ -170,6 +404,80 @@ impl Editor {
     }
 }
 
+/// `line` as drawn, each tab widened to the next multiple of `tabstop` with
+/// the first glyph followed by the second, along with where each byte
+/// offset of `line`, its end included, ends up in the result.
+fn expand_tabs(line: &str, tabstop: usize, glyphs: (char, char)) -> (String, Vec<usize>) {
+    let mut text = String::with_capacity(line.len());
+    let mut offsets = Vec::with_capacity(line.len() + 1);
+    let mut col = 0;
+    for (i, c) in line.char_indices() {
+        offsets.resize(i + 1, text.len());
+        if c == '\t' {
+            let width = tabstop - col % tabstop;
+            text.push(glyphs.0);
+            text.extend(std::iter::repeat_n(glyphs.1, width - 1));
+            col += width;
+        } else {
+            text.push(c);
+            col += 1;
+        }
+    }
+    offsets.resize(line.len() + 1, text.len());
+    (text, offsets)
+}
+
+/// Byte offset of the `n`th character of `text`, or its length.
+fn char_offset(text: &str, n: usize) -> usize {
+    text.char_indices().nth(n).map_or(text.len(), |(i, _)| i)
+}
+
+/// The part of `line` starting at column `start` that fits in `width`
+/// columns.
+fn visible_range(line: &str, start: usize, width: usize) -> Range<usize> {
+    char_offset(line, start)..char_offset(line, start + width)
+}
+
+/// `spans` with `range` carved out of them and covered by `scope` instead.
+fn overlay_span(spans: &[Span], range: Range<usize>, scope: &'static str) -> Vec<Span> {
+    let mut result = Vec::new();
+    for span in spans {
+        if span.start < range.start {
+            result.push(Span {
+                end: span.end.min(range.start),
+                ..span.clone()
+            });
+        }
+        if span.end > range.end {
+            result.push(Span {
+                start: span.start.max(range.end),
+                ..span.clone()
+            });
+        }
+    }
+    result.push(Span {
+        start: range.start,
+        end: range.end,
+        scope,
+    });
+    result.sort_by_key(|span| span.start);
+    result
+}
+
+fn wrap_line(line: &str, width: usize) -> Vec<Range<usize>> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+    while start < line.len() {
+        let end = start + char_offset(&line[start..], width);
+        segments.push(start..end);
+        start = end;
+    }
+    if segments.is_empty() {
+        segments.push(0..0);
+    }
+    segments
+}
+
 fn visible_length(s: &str) -> usize {
     let mut len = 0;
     let mut in_escape = false;
@@ -0,0 +1,102 @@
+use crate::*;
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// A change `.` can repeat: the keys of the command that made it, after
+/// mappings were expanded, and the count it was given.
+#[derive(Clone)]
+pub struct Change {
+    pub keys: Vec<QueuedKey>,
+    pub count: Option<usize>,
+}
+
+impl Editor {
+    /// Adds a key handled in normal, visual or insert mode to the command
+    /// being recorded for `.`.
+    pub fn record_change_key(&mut self, key: QueuedKey) {
+        if self.change_keys.is_empty() {
+            self.change_start = self.changes;
+            self.change_count = self.count;
+        }
+        // ex commands change the buffer too, but aren't repeated
+        if self.mode == Mode::Command {
+            self.change_repeatable = false;
+        }
+        self.change_keys.push(key);
+    }
+
+    /// Forgets the last `n` recorded keys, for keys that are handled again
+    /// or stand for a mapping whose keys are handled instead.
+    pub fn unrecord_change_keys(&mut self, n: usize) {
+        let len = self.change_keys.len();
+        self.change_keys.truncate(len.saturating_sub(n));
+    }
+
+    /// Ends the recorded command once the editor is back in normal mode
+    /// with nothing left pending, keeping it for `.` if it changed the
+    /// buffer. A command run with `Ctrl-o` is part of the insert around it.
+    pub fn finish_change(&mut self) {
+        if !self.between_commands() || self.insert_oneshot {
+            return;
+        }
+        let keys = std::mem::take(&mut self.change_keys);
+        if self.changes != self.change_start && self.change_repeatable && !keys.is_empty() {
+            self.last_change = Some(Change {
+                keys,
+                count: self.change_count,
+            });
+        }
+        self.change_start = self.changes;
+        self.change_repeatable = true;
+    }
+
+    /// `.`: repeats the last change, with a new count if one was typed.
+    pub fn repeat_change(&mut self) {
+        let Some(change) = self.last_change.clone() else {
+            self.set_status("No change to repeat");
+            return;
+        };
+        let count = self.take_count().or(change.count);
+        // the count goes in first, as though it were typed again
+        let digits = count.map(|n| n.to_string()).unwrap_or_default();
+        let digits = digits.chars().map(|c| QueuedKey {
+            key: Key::new(KeyCode::Char(c), KeyModifiers::NONE),
+            remap: false,
+        });
+        for key in digits
+            .chain(change.keys)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+        {
+            self.input_queue.push_front(key);
+        }
+    }
+
+    /// Leaving insert mode after `3ifoo`: types the inserted keys again for
+    /// the rest of the count, each time on a new line after `3ofoo`.
+    pub fn repeat_insert(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some((count, start, new_line)) = self.insert_repeat.take() else {
+            return Ok(());
+        };
+        // the key that left insert mode was recorded last
+        let end = self.change_keys.len().saturating_sub(1);
+        let typed = self
+            .change_keys
+            .get(start..end)
+            .unwrap_or_default()
+            .to_vec();
+        for _ in 1..count {
+            if new_line {
+                self.cursor_x = self.content[self.cursor_y].len();
+                self.insert_newline();
+            }
+            for &key in &typed {
+                self.handle_key(key)?;
+            }
+            self.flush_pending_keys()?;
+        }
+        // `.` replays the count rather than the repeated keys
+        self.change_keys.truncate(end + 1);
+        Ok(())
+    }
+}
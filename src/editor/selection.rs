@@ -0,0 +1,65 @@
+use crate::*;
+use std::ops::Range;
+
+/// A position in the buffer as (line, byte column).
+pub type Pos = (usize, usize);
+
+impl Editor {
+    pub fn enter_visual(&mut self) {
+        self.visual_anchor = (self.cursor_y, self.cursor_x);
+        self.mode = Mode::Visual;
+    }
+
+    /// The selected text as ordered, inclusive start and end positions. An
+    /// end at or past the end of its line takes in the line break.
+    pub fn selection(&self) -> (Pos, Pos) {
+        let cursor = (self.cursor_y, self.cursor_x);
+        if self.visual_anchor <= cursor {
+            (self.visual_anchor, cursor)
+        } else {
+            (cursor, self.visual_anchor)
+        }
+    }
+
+    /// Selects from `start` to `end` inclusive, leaving the cursor on `end`.
+    pub fn select(&mut self, start: Pos, end: Pos) {
+        self.visual_anchor = start;
+        (self.cursor_y, self.cursor_x) = end;
+        self.mode = Mode::Visual;
+    }
+
+    /// The selected bytes of line `y`, if visual mode covers any of it.
+    pub fn selection_range(&self, y: usize) -> Option<Range<usize>> {
+        if self.mode != Mode::Visual {
+            return None;
+        }
+        let ((start_y, start_x), (end_y, end_x)) = self.selection();
+        if y < start_y || y > end_y {
+            return None;
+        }
+        let line = &self.content[y];
+        let start = if y == start_y { start_x } else { 0 };
+        let end = if y == end_y {
+            line.ceil_char_boundary(end_x + 1).min(line.len())
+        } else {
+            line.len()
+        };
+        Some(start.min(end)..end)
+    }
+
+    pub fn delete_selection(&mut self) {
+        let ((start_y, start_x), (end_y, end_x)) = self.selection();
+        let end_line = &self.content[end_y];
+        let (last, rest) = if end_x >= end_line.len() && end_y + 1 < self.content.len() {
+            (end_y + 1, self.content[end_y + 1].clone())
+        } else {
+            let end = end_line.ceil_char_boundary(end_x + 1).min(end_line.len());
+            (end_y, end_line[end..].to_string())
+        };
+        let joined = self.content[start_y][..start_x].to_string() + &rest;
+        self.replace_lines(start_y, last + 1, vec![joined]);
+        self.cursor_y = start_y;
+        self.cursor_x = start_x;
+        self.mode = self.resting_mode();
+    }
+}
@@ -0,0 +1,200 @@
+use crate::*;
+use crossterm::terminal::is_raw_mode_enabled;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// The shell commands run with: `$SHELL`, or `sh`.
+fn shell() -> String {
+    std::env::var("SHELL")
+        .ok()
+        .filter(|shell| !shell.is_empty())
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+/// Splits output into lines for the buffer or the pager.
+fn output_lines(bytes: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(bytes)
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+impl Editor {
+    /// Runs `cmd` with the shell, writing `input` to its stdin, and waits
+    /// for it. The terminal is handed back while it runs, so the command
+    /// can prompt for things like a password.
+    pub fn run_shell(&mut self, cmd: &str, input: Option<String>) -> Result<Output, ExError> {
+        let suspended = is_raw_mode_enabled().unwrap_or(false);
+        if suspended {
+            self.leave_terminal()
+                .map_err(|e| ExError::Failed(e.to_string()))?;
+        }
+        let output = spawn_shell(cmd, input);
+        if suspended {
+            self.enter_terminal()
+                .map_err(|e| ExError::Failed(e.to_string()))?;
+        }
+        output.map_err(|e| ExError::Failed(format!("Cannot run {}: {}", shell(), e)))
+    }
+
+    /// Like [`Editor::run_shell`], but a failing command is an error.
+    fn run_shell_checked(&mut self, cmd: &str, input: Option<String>) -> Result<Output, ExError> {
+        let output = self.run_shell(cmd, input)?;
+        if !output.status.success() {
+            let stderr = output_lines(&output.stderr);
+            return Err(ExError::ShellFailed {
+                status: output.status,
+                stderr: stderr.into_iter().next().unwrap_or_default(),
+            });
+        }
+        Ok(output)
+    }
+
+    /// `:!{cmd}` runs a shell command and shows what it printed, and
+    /// `:{range}!{cmd}` filters the lines through it, replacing them with
+    /// its output.
+    pub fn ex_shell(&mut self, call: &ExCall) -> Result<(), ExError> {
+        if call.arg.is_empty() {
+            return Err(ExError::ArgumentRequired);
+        }
+        let Some(lines) = call.range.clone() else {
+            let output = self.run_shell(&call.arg, None)?;
+            let mut printed = output_lines(&output.stdout);
+            printed.extend(output_lines(&output.stderr));
+            if !output.status.success() {
+                let status = ExError::ShellFailed {
+                    status: output.status,
+                    stderr: String::new(),
+                };
+                printed.push(format!("[{}]", status));
+            }
+            self.show_lines(printed);
+            return Ok(());
+        };
+
+        let input: String = self.content[lines.clone()]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let output = self.run_shell_checked(&call.arg, Some(input))?;
+        let mut filtered = output_lines(&output.stdout);
+        if filtered.is_empty() && lines.len() == self.content.len() {
+            filtered.push(String::new());
+        }
+        if filtered[..] != self.content[lines.clone()] {
+            self.replace_lines(lines.start, lines.end, filtered);
+        }
+        self.cursor_y = std::cmp::min(lines.start, self.content.len() - 1);
+        self.cursor_to_indent();
+        Ok(())
+    }
+
+    /// `:w !{cmd}`: runs a shell command with the buffer as its input and
+    /// shows what it printed.
+    pub fn ex_write_shell(&mut self, cmd: &str) -> Result<(), ExError> {
+        let input: String = self
+            .content
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let output = self.run_shell_checked(cmd, Some(input))?;
+        let mut printed = output_lines(&output.stdout);
+        printed.extend(output_lines(&output.stderr));
+        self.show_lines(printed);
+        Ok(())
+    }
+
+    /// `:[line]r {file}` and `:[line]r !{cmd}`: inserts a file, or what a
+    /// shell command prints, below the line.
+    pub fn ex_read(&mut self, call: &ExCall) -> Result<(), ExError> {
+        let text = match call.arg.strip_prefix('!') {
+            Some(cmd) => {
+                let output = self.run_shell_checked(cmd.trim(), None)?;
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            None if call.arg.is_empty() => return Err(ExError::ArgumentRequired),
+            None => std::fs::read_to_string(&call.arg)
+                .map_err(|e| ExError::Failed(format!("Cannot read {}: {}", call.arg, e)))?,
+        };
+        let lines: Vec<String> = text.lines().map(String::from).collect();
+        if lines.is_empty() {
+            return Ok(());
+        }
+        let below = match call.line_zero {
+            true => 0,
+            false => call.lines(self).end,
+        };
+        self.replace_lines(below, below, lines);
+        self.cursor_y = below;
+        self.cursor_to_indent();
+        Ok(())
+    }
+}
+
+/// Starts `cmd` with the shell, feeding it `input` from another thread so
+/// a command that writes while it reads can't block on a full pipe.
+fn spawn_shell(cmd: &str, input: Option<String>) -> std::io::Result<Output> {
+    let mut child = Command::new(shell())
+        .arg("-c")
+        .arg(cmd)
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::inherit(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let writer = match (child.stdin.take(), input) {
+        (Some(mut stdin), Some(input)) => Some(std::thread::spawn(move || {
+            // a command that exits without reading all of it is fine
+            let _ = stdin.write_all(input.as_bytes());
+        })),
+        _ => None,
+    };
+    let output = child.wait_with_output()?;
+    if let Some(writer) = writer {
+        let _ = writer.join();
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    #[test]
+    fn filter_replaces_lines() {
+        let mut editor = editor_with("c\nb\na\nz");
+        editor.run_ex("%!sort").unwrap();
+        assert_eq!(editor.text(), "a\nb\nc\nz\n");
+        editor.run_ex("1,2!tr a-z A-Z").unwrap();
+        assert_eq!(editor.text(), "A\nB\nc\nz\n");
+        assert_eq!(editor.cursor_y, 0);
+    }
+
+    #[test]
+    fn read_command_output() {
+        let mut editor = editor_with("one\ntwo");
+        editor.run_ex("r !echo hi").unwrap();
+        assert_eq!(editor.text(), "one\nhi\ntwo\n");
+        assert_eq!(editor.cursor_y, 1);
+        editor.run_ex("0r !printf 'a\\nb\\n'").unwrap();
+        assert_eq!(editor.text(), "a\nb\none\nhi\ntwo\n");
+    }
+
+    #[test]
+    fn failing_filter_keeps_lines() {
+        let mut editor = editor_with("b\na");
+        let error = editor.run_ex("%!false").unwrap_err();
+        assert!(matches!(error, ExError::ShellFailed { .. }));
+        let error = editor.run_ex("%!echo oops >&2; exit 3").unwrap_err();
+        assert!(
+            matches!(error, ExError::ShellFailed { ref stderr, .. } if stderr == "oops"),
+            "{}",
+            error
+        );
+        assert_eq!(editor.text(), "b\na\n");
+        assert!(!editor.modified());
+    }
+}
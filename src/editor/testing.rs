@@ -0,0 +1,39 @@
+//! Helpers for tests that drive the editor the way a user would.
+use crate::*;
+
+/// An editor holding `text`, with the cursor at its start.
+pub fn editor_with(text: &str) -> Editor {
+    let mut editor = Editor::new();
+    editor.set_content(text);
+    editor
+}
+
+impl Editor {
+    /// Types `keys`, written in key notation like `dw<Esc>`, one at a time
+    /// as `run` would read them, then lets anything left pending time out.
+    pub fn type_keys(&mut self, keys: &str) {
+        for key in parse_keys(keys).unwrap() {
+            self.record_key(key);
+            self.input_queue.push_back(QueuedKey { key, remap: true });
+            self.handle_typed_keys();
+        }
+        self.flush_pending_keys().unwrap();
+        self.handle_typed_keys();
+    }
+
+    fn handle_typed_keys(&mut self) {
+        while let Some(key) = self.input_queue.pop_front() {
+            self.handle_key(key).unwrap();
+        }
+        self.map_depth = 0;
+        self.macro_depth = 0;
+    }
+
+    /// The buffer as one string, lines ending in newlines.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+}
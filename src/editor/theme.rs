@@ -0,0 +1,361 @@
+use crate::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+pub const RESET: &str = "\x1B[0m";
+
+/// Every highlight group a theme may define.
+pub const GROUPS: &[&str] = &[
+    "line_nr",
+    "cursor_line_nr",
+    "cursor_line",
+    "status_filename",
+    "status_mode",
+    "status_msg",
+    "status_cmd",
+    "status_info",
+    "selection",
+    "search_match",
+    "matching_bracket",
+    "popup",
+    "popup_selected",
+    "diagnostic_error",
+    "diagnostic_warning",
+    "diagnostic_info",
+    "diagnostic_hint",
+    "comment",
+    "string",
+    "number",
+    "constant",
+    "keyword",
+    "type",
+    "function",
+    "macro",
+    "attribute",
+    "variable",
+    "operator",
+    "punctuation",
+];
+
+const BUILTIN_THEMES: &[(&str, &str)] = &[
+    ("default", include_str!("themes/default.toml")),
+    ("gruvbox", include_str!("themes/gruvbox.toml")),
+];
+
+/// The xterm palette for the 16 basic colors, used to pick the nearest one.
+const BASIC_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    pub fn detect() -> Self {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Color {
+    Rgb(u8, u8, u8),
+    Indexed(u8),
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    /// Accepts `#rrggbb`, a 0-255 palette index, or one of the 16 color names.
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if let Some(hex) = s.strip_prefix('#') {
+            let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2).unwrap_or(""), 16);
+            return match (hex.len(), channel(0), channel(2), channel(4)) {
+                (6, Ok(r), Ok(g), Ok(b)) => Ok(Color::Rgb(r, g, b)),
+                _ => Err(format!("invalid hex color '{}'", s)),
+            };
+        }
+        if let Ok(index) = s.parse::<u8>() {
+            return Ok(Color::Indexed(index));
+        }
+        let names = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        let (bright, base) = match s.strip_prefix("bright_") {
+            Some(base) => (8, base),
+            None => (0, s.as_str()),
+        };
+        names
+            .iter()
+            .position(|name| *name == base)
+            .map(|i| Color::Indexed(i as u8 + bright))
+            .ok_or_else(|| format!("unknown color '{}'", s))
+    }
+}
+
+impl Color {
+    fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(i) if i < 16 => BASIC_COLORS[i as usize],
+            Color::Indexed(i) if i < 232 => {
+                let i = i - 16;
+                (
+                    CUBE_LEVELS[(i / 36) as usize],
+                    CUBE_LEVELS[(i / 6 % 6) as usize],
+                    CUBE_LEVELS[(i % 6) as usize],
+                )
+            }
+            Color::Indexed(i) => {
+                let level = 8 + (i - 232) * 10;
+                (level, level, level)
+            }
+        }
+    }
+
+    fn to_ansi256(self) -> u8 {
+        let (r, g, b) = match self {
+            Color::Indexed(i) => return i,
+            Color::Rgb(r, g, b) => (r, g, b),
+        };
+        let nearest_level = |v: u8| {
+            (0..6)
+                .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - v as i32).abs())
+                .unwrap() as u8
+        };
+        let cube = 16 + 36 * nearest_level(r) + 6 * nearest_level(g) + nearest_level(b);
+        let average = (r as u16 + g as u16 + b as u16) / 3;
+        let gray = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+        [cube, gray]
+            .into_iter()
+            .min_by_key(|&i| distance(Color::Indexed(i).to_rgb(), (r, g, b)))
+            .unwrap()
+    }
+
+    fn to_ansi16(self) -> u8 {
+        if let Color::Indexed(i) = self
+            && i < 16
+        {
+            return i;
+        }
+        let rgb = self.to_rgb();
+        (0..16)
+            .min_by_key(|&i| distance(BASIC_COLORS[i], rgb))
+            .unwrap() as u8
+    }
+
+    /// SGR parameters selecting this color as foreground (`background` false)
+    /// or background, degraded to what the terminal supports.
+    fn sgr(self, depth: ColorDepth, background: bool) -> String {
+        let base = if background { 40 } else { 30 };
+        match (depth, self) {
+            (ColorDepth::TrueColor, Color::Rgb(r, g, b)) => {
+                format!("{};2;{};{};{}", base + 8, r, g, b)
+            }
+            (ColorDepth::TrueColor | ColorDepth::Ansi256, color) => {
+                format!("{};5;{}", base + 8, color.to_ansi256())
+            }
+            (ColorDepth::Ansi16, color) => match color.to_ansi16() {
+                i if i < 8 => (base + i as u32).to_string(),
+                i => (base + 60 + (i - 8) as u32).to_string(),
+            },
+        }
+    }
+}
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (x as i32 - y as i32).pow(2) as u32;
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn sgr(&self, depth: ColorDepth) -> String {
+        let mut params = Vec::new();
+        if self.bold {
+            params.push("1".to_string());
+        }
+        if self.italic {
+            params.push("3".to_string());
+        }
+        if self.underline {
+            params.push("4".to_string());
+        }
+        if self.reverse {
+            params.push("7".to_string());
+        }
+        if let Some(fg) = self.fg {
+            params.push(fg.sgr(depth, false));
+        }
+        if let Some(bg) = self.bg {
+            params.push(bg.sgr(depth, true));
+        }
+        if params.is_empty() {
+            String::new()
+        } else {
+            format!("\x1B[{}m", params.join(";"))
+        }
+    }
+}
+
+pub struct Theme {
+    pub name: String,
+    pub depth: ColorDepth,
+    groups: HashMap<String, Style>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: "default".to_string(),
+            depth: ColorDepth::detect(),
+            groups: builtin_default_groups(),
+        }
+    }
+}
+
+fn builtin_default_groups() -> HashMap<String, Style> {
+    parse_toml(BUILTIN_THEMES[0].1, "<builtin>/default.toml".into())
+        .expect("built-in default theme is valid")
+}
+
+impl Theme {
+    /// Loads `name` from the user's `themes` directory, falling back to the
+    /// themes bundled with eep. Groups a theme leaves out keep the default
+    /// theme's styling.
+    pub fn load(name: &str) -> Result<Theme, ConfigError> {
+        let user_path = config_dir().map(|dir| dir.join("themes").join(format!("{}.toml", name)));
+        let (source, path) = match user_path {
+            Some(path) if path.is_file() => match fs::read_to_string(&path) {
+                Ok(source) => (source, path),
+                Err(e) => {
+                    return Err(ConfigError {
+                        path: Some(path),
+                        line: None,
+                        message: e.to_string(),
+                    });
+                }
+            },
+            _ => match BUILTIN_THEMES.iter().find(|(builtin, _)| *builtin == name) {
+                Some((_, source)) => (
+                    source.to_string(),
+                    format!("<builtin>/{}.toml", name).into(),
+                ),
+                None => {
+                    return Err(ConfigError {
+                        path: None,
+                        line: None,
+                        message: format!("Cannot find color scheme '{}'", name),
+                    });
+                }
+            },
+        };
+
+        let parsed: HashMap<String, Style> = parse_toml(&source, path.clone())?;
+        if let Some(unknown) = parsed
+            .keys()
+            .find(|group| !GROUPS.contains(&group.as_str()))
+        {
+            return Err(ConfigError {
+                path: Some(path),
+                line: source
+                    .lines()
+                    .position(|line| {
+                        let line = line.trim_start().trim_start_matches('[');
+                        line.starts_with(unknown.as_str())
+                    })
+                    .map(|i| i + 1),
+                message: format!("unknown highlight group `{}`", unknown),
+            });
+        }
+
+        let mut groups = builtin_default_groups();
+        groups.extend(parsed);
+        Ok(Theme {
+            name: name.to_string(),
+            depth: ColorDepth::detect(),
+            groups,
+        })
+    }
+
+    pub fn style(&self, group: &str) -> Option<&Style> {
+        self.groups.get(group)
+    }
+
+    /// The escape sequence that switches to `group`'s style, or an empty
+    /// string if the theme leaves it unstyled.
+    pub fn sgr(&self, group: &str) -> String {
+        self.style(group)
+            .map(|style| style.sgr(self.depth))
+            .unwrap_or_default()
+    }
+
+    /// Names of all themes that `:colorscheme` can load.
+    pub fn available() -> Vec<String> {
+        let mut names: Vec<String> = BUILTIN_THEMES
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect();
+        if let Some(Ok(entries)) = config_dir().map(|dir| fs::read_dir(dir.join("themes"))) {
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.extension().is_some_and(|ext| ext == "toml")
+                    && let Some(stem) = path.file_stem()
+                {
+                    names.push(stem.to_string_lossy().into_owned());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+impl Editor {
+    pub fn set_colorscheme(&mut self, name: &str) -> Result<(), ConfigError> {
+        self.theme = Theme::load(name)?;
+        self.config.theme = name.to_string();
+        Ok(())
+    }
+}
@@ -0,0 +1,555 @@
+use crate::*;
+use tree_sitter::{
+    InputEdit, Language, Node, Parser, Point, Query, QueryCursor, StreamingIterator, Tree,
+};
+
+/// A bundled tree-sitter grammar, picked by the name of the regex grammar
+/// the buffer is highlighted with.
+struct TreeLanguage {
+    name: &'static str,
+    language: fn() -> Language,
+    highlights: &'static str,
+    functions: &'static [&'static str],
+    classes: &'static [&'static str],
+}
+
+const TREE_LANGUAGES: &[TreeLanguage] = &[
+    TreeLanguage {
+        name: "rust",
+        language: || tree_sitter_rust::LANGUAGE.into(),
+        highlights: tree_sitter_rust::HIGHLIGHTS_QUERY,
+        functions: &[
+            "function_item",
+            "function_signature_item",
+            "closure_expression",
+        ],
+        classes: &[
+            "struct_item",
+            "enum_item",
+            "union_item",
+            "trait_item",
+            "impl_item",
+            "mod_item",
+        ],
+    },
+    TreeLanguage {
+        name: "toml",
+        language: || tree_sitter_toml_ng::LANGUAGE.into(),
+        highlights: tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+        functions: &[],
+        classes: &["table", "table_array_element"],
+    },
+];
+
+/// Theme group for a highlight query capture. Names without an entry here
+/// fall back to their first component, so `string.special` is a `string`.
+fn capture_group(name: &str) -> Option<&'static str> {
+    let group = match name {
+        "function.macro" => "macro",
+        "boolean" | "escape" | "constant.builtin" => "constant",
+        "constructor" | "label" => "type",
+        "property" => "variable",
+        _ => name.split('.').next().unwrap_or(name),
+    };
+    GROUPS.iter().find(|g| **g == group).copied()
+}
+
+fn point(pos: Pos) -> Point {
+    Point::new(pos.0, pos.1)
+}
+
+/// The byte offset of the start of line `y` in the parsed source, where
+/// every line is followed by a newline.
+fn line_offset(content: &[String], y: usize) -> usize {
+    content[..y].iter().map(|line| line.len() + 1).sum()
+}
+
+/// A syntax tree for the buffer, updated incrementally as lines change and
+/// reparsed lazily the next time it is needed.
+pub struct SyntaxTree {
+    pub language: &'static str,
+    parser: Parser,
+    tree: Tree,
+    source: String,
+    highlights: Query,
+    functions: &'static [&'static str],
+    classes: &'static [&'static str],
+    stale: bool,
+}
+
+impl SyntaxTree {
+    /// Parses `content` if there is a bundled grammar named `name`.
+    pub fn new(name: &str, content: &[String]) -> Option<SyntaxTree> {
+        let bundled = TREE_LANGUAGES.iter().find(|l| l.name == name)?;
+        let language = (bundled.language)();
+        let mut parser = Parser::new();
+        parser
+            .set_language(&language)
+            .expect("bundled grammar matches the tree-sitter version");
+        let highlights =
+            Query::new(&language, bundled.highlights).expect("bundled highlight query is valid");
+        let source = content.join("\n") + "\n";
+        let tree = parser.parse(&source, None)?;
+        Some(SyntaxTree {
+            language: bundled.name,
+            parser,
+            tree,
+            source,
+            highlights,
+            functions: bundled.functions,
+            classes: bundled.classes,
+            stale: false,
+        })
+    }
+
+    /// Records that lines `start..end` of `content` are about to be replaced
+    /// by `inserted`.
+    pub fn edit(&mut self, content: &[String], start: usize, end: usize, inserted: &[String]) {
+        let start_byte = line_offset(content, start);
+        let old_end_byte = start_byte + line_offset(&content[start..], end - start);
+        let new_end_byte = start_byte + line_offset(inserted, inserted.len());
+        self.tree.edit(&InputEdit {
+            start_byte,
+            old_end_byte,
+            new_end_byte,
+            start_position: Point::new(start, 0),
+            old_end_position: Point::new(end, 0),
+            new_end_position: Point::new(start + inserted.len(), 0),
+        });
+        self.stale = true;
+    }
+
+    fn refresh(&mut self, content: &[String]) {
+        if !self.stale {
+            return;
+        }
+        self.source = content.join("\n") + "\n";
+        if let Some(tree) = self.parser.parse(&self.source, Some(&self.tree)) {
+            self.tree = tree;
+        }
+        self.stale = false;
+    }
+
+    /// Highlight spans for lines `first..=last`. Where captures nest, the
+    /// innermost one wins; for the same node, the pattern listed first.
+    pub fn highlight_lines(
+        &mut self,
+        content: &[String],
+        first: usize,
+        last: usize,
+    ) -> Vec<Vec<Span>> {
+        self.refresh(content);
+        let last = std::cmp::min(last, content.len().saturating_sub(1));
+        let mut captures = Vec::new();
+        let mut cursor = QueryCursor::new();
+        cursor.set_point_range(Point::new(first, 0)..Point::new(last + 1, 0));
+        let mut matches = cursor.matches(
+            &self.highlights,
+            self.tree.root_node(),
+            self.source.as_bytes(),
+        );
+        while let Some(m) = matches.next() {
+            for capture in m.captures {
+                let name = self.highlights.capture_names()[capture.index as usize];
+                if let Some(group) = capture_group(name) {
+                    captures.push((capture.node, m.pattern_index, group));
+                }
+            }
+        }
+        captures.sort_by_key(|(node, pattern, _)| {
+            (
+                std::cmp::Reverse(node.end_byte() - node.start_byte()),
+                std::cmp::Reverse(*pattern),
+            )
+        });
+
+        let mut scopes: Vec<Vec<Option<&'static str>>> = (first..=last)
+            .map(|y| vec![None; content[y].len()])
+            .collect();
+        for (node, _, group) in captures {
+            let (start, end) = (node.start_position(), node.end_position());
+            for y in start.row.max(first)..=end.row.min(last) {
+                let line = &mut scopes[y - first];
+                let from = if y == start.row { start.column } else { 0 };
+                let to = if y == end.row { end.column } else { line.len() };
+                for scope in line.iter_mut().take(to).skip(from) {
+                    *scope = Some(group);
+                }
+            }
+        }
+
+        scopes
+            .into_iter()
+            .map(|line| {
+                let mut spans: Vec<Span> = Vec::new();
+                for (x, scope) in line.into_iter().enumerate() {
+                    let Some(scope) = scope else { continue };
+                    match spans.last_mut() {
+                        Some(span) if span.end == x && span.scope == scope => span.end += 1,
+                        _ => spans.push(Span {
+                            start: x,
+                            end: x + 1,
+                            scope,
+                        }),
+                    }
+                }
+                spans
+            })
+            .collect()
+    }
+
+    /// The range of the smallest named node that contains `start..end` and
+    /// is larger than it.
+    pub fn enclosing_node(
+        &mut self,
+        content: &[String],
+        start: Pos,
+        end: Pos,
+    ) -> Option<(Pos, Pos)> {
+        self.refresh(content);
+        let mut node = self
+            .tree
+            .root_node()
+            .named_descendant_for_point_range(point(start), point(end))?;
+        while node.start_position() == point(start) && node.end_position() == point(end) {
+            node = node.parent()?;
+        }
+        Some(node_range(&node))
+    }
+
+    /// Start positions of every function in the buffer, in order.
+    pub fn function_starts(&mut self, content: &[String]) -> Vec<Pos> {
+        self.refresh(content);
+        let mut starts = Vec::new();
+        let mut cursor = self.tree.walk();
+        'walk: loop {
+            let node = cursor.node();
+            if self.functions.contains(&node.kind()) {
+                starts.push((node.start_position().row, node.start_position().column));
+            }
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        starts
+    }
+
+    /// The function (or class, with `class`) around `pos`, or with `inner`
+    /// just its body inside any delimiters, as an end-exclusive range.
+    pub fn text_object(
+        &mut self,
+        content: &[String],
+        pos: Pos,
+        class: bool,
+        inner: bool,
+    ) -> Option<(Pos, Pos)> {
+        self.refresh(content);
+        let kinds = if class { self.classes } else { self.functions };
+        let mut node = self
+            .tree
+            .root_node()
+            .descendant_for_point_range(point(pos), point(pos))?;
+        while !kinds.contains(&node.kind()) {
+            node = node.parent()?;
+        }
+        if !inner {
+            return Some(node_range(&node));
+        }
+
+        // a TOML table's contents are everything after its header
+        let (first, last) = match node.child_by_field_name("body") {
+            Some(body) => (body, body),
+            None => (node.named_child(1)?, node),
+        };
+        let (mut start, mut end) = (node_range(&first).0, node_range(&last).1);
+        let source = self.source.as_bytes();
+        let (open, close) = (source[first.start_byte()], source[last.end_byte() - 1]);
+        if matches!((open, close), (b'{', b'}') | (b'(', b')') | (b'[', b']')) {
+            start.1 += 1;
+            end.1 -= 1;
+            if content[start.0][start.1..].trim().is_empty() {
+                start = (start.0 + 1, 0);
+            }
+            if content[end.0][..end.1].trim().is_empty() {
+                end.1 = 0;
+            }
+        }
+        (start < end).then_some((start, end))
+    }
+}
+
+fn node_range(node: &Node) -> (Pos, Pos) {
+    let (start, end) = (node.start_position(), node.end_position());
+    ((start.row, start.column), (end.row, end.column))
+}
+
+impl Editor {
+    /// Starts tracking a syntax tree if the buffer's grammar has a bundled
+    /// tree-sitter equivalent.
+    pub fn attach_syntax_tree(&mut self) {
+        self.syntax_tree = self
+            .highlighter
+            .as_ref()
+            .and_then(|h| SyntaxTree::new(&h.grammar.name, &self.content));
+    }
+
+    /// Selects `start..end`, given with an exclusive end as tree-sitter
+    /// reports it.
+    fn select_exclusive(&mut self, start: Pos, end: Pos) {
+        let end = match end {
+            (0, 0) => (0, 0),
+            (y, 0) => (y - 1, self.content[y - 1].len()),
+            (y, x) => (y, self.content[y].floor_char_boundary(x - 1)),
+        };
+        self.select(start, end);
+    }
+
+    pub fn expand_selection(&mut self) {
+        let (start, end) = match self.mode {
+            Mode::Visual => self.selection(),
+            _ => (
+                (self.cursor_y, self.cursor_x),
+                (self.cursor_y, self.cursor_x),
+            ),
+        };
+        let end_line = &self.content[end.0];
+        let end = match end.1 >= end_line.len() {
+            true if end.0 + 1 < self.content.len() && self.mode == Mode::Visual => (end.0 + 1, 0),
+            _ => (
+                end.0,
+                end_line.ceil_char_boundary(end.1 + 1).min(end_line.len()),
+            ),
+        };
+        let Some(ref mut tree) = self.syntax_tree else {
+            self.set_status("No syntax tree for this buffer");
+            return;
+        };
+        match tree.enclosing_node(&self.content, start, end) {
+            Some((start, end)) => self.select_exclusive(start, end),
+            None => self.set_status("No enclosing node"),
+        }
+    }
+
+    pub fn goto_function(&mut self, forward: bool) {
+        let Some(ref mut tree) = self.syntax_tree else {
+            self.set_status("No syntax tree for this buffer");
+            return;
+        };
+        let starts = tree.function_starts(&self.content);
+        let cursor = (self.cursor_y, self.cursor_x);
+        let target = if forward {
+            starts.into_iter().find(|start| *start > cursor)
+        } else {
+            starts.into_iter().rev().find(|start| *start < cursor)
+        };
+        match target {
+            Some((y, x)) => {
+                self.push_jump();
+                (self.cursor_y, self.cursor_x) = (y, x);
+            }
+            None => self.set_status("No more functions"),
+        }
+    }
+
+    /// The range of the function or class text object around the cursor,
+    /// or `None` after saying why there isn't one.
+    fn text_object_range(&mut self, class: bool, inner: bool) -> Option<(Pos, Pos)> {
+        let Some(ref mut tree) = self.syntax_tree else {
+            self.set_status("No syntax tree for this buffer");
+            return None;
+        };
+        let cursor = (self.cursor_y, self.cursor_x);
+        let range = tree.text_object(&self.content, cursor, class, inner);
+        if range.is_none() {
+            match class {
+                true => self.set_status("Not inside a class"),
+                false => self.set_status("Not inside a function"),
+            }
+        }
+        range
+    }
+
+    pub fn select_text_object(&mut self, class: bool, inner: bool) {
+        if let Some((start, end)) = self.text_object_range(class, inner) {
+            self.select_exclusive(start, end);
+        }
+    }
+
+    /// `daf` and the like: runs a pending operator over the text object
+    /// the action `name` selects in visual mode.
+    pub fn operate_on_text_object(&mut self, mut operator: PendingOperator, name: &'static str) {
+        let class = matches!(name, "select_class" | "select_class_body");
+        let inner = name.ends_with("_body");
+        let Some((start, end)) = self.text_object_range(class, inner) else {
+            return self.fail("");
+        };
+        operator.from = start;
+        operator.motion = Some(name);
+        self.apply_operator(operator, end, MotionKind::TextObject);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::testing::*;
+
+    const RUST: &str = "\
+fn one() {
+    let x = 1;
+}
+
+fn two(a: u8) -> u8 {
+    a
+}
+";
+
+    const TOML: &str = "\
+[package]
+name = \"eep\"
+
+[dependencies]
+regex = \"1\"
+";
+
+    fn rust_editor() -> Editor {
+        let mut editor = editor_with(RUST);
+        editor.set_syntax("rust").unwrap();
+        editor
+    }
+
+    /// Every node's kind and range, in order, to compare trees by.
+    fn nodes(tree: &Tree) -> Vec<String> {
+        let mut nodes = Vec::new();
+        let mut cursor = tree.walk();
+        'walk: loop {
+            nodes.push(format!(
+                "{} {:?}",
+                cursor.node().kind(),
+                cursor.node().range()
+            ));
+            if cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    break 'walk;
+                }
+            }
+        }
+        nodes
+    }
+
+    #[test]
+    fn tree_follows_edits() {
+        let mut editor = rust_editor();
+        editor.type_keys("jf1a + 2<Esc>");
+        editor.type_keys("Gofn three() {}<Esc>");
+        editor.type_keys("ggjdd");
+        editor.type_keys("GyyP");
+        let content = editor.content.clone();
+        let tree = editor.syntax_tree.as_mut().unwrap();
+        tree.refresh(&content);
+        let fresh = SyntaxTree::new("rust", &content).unwrap();
+        assert_eq!(nodes(&tree.tree), nodes(&fresh.tree));
+        assert!(!tree.tree.root_node().has_error());
+    }
+
+    #[test]
+    fn function_jumps() {
+        let mut editor = rust_editor();
+        editor.type_keys("]f");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (4, 0));
+        editor.type_keys("]f");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (4, 0));
+        assert_eq!(editor.status_msg, "No more functions");
+        editor.type_keys("j[f");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (4, 0));
+        editor.type_keys("[f");
+        assert_eq!((editor.cursor_y, editor.cursor_x), (0, 0));
+    }
+
+    #[test]
+    fn expand_selection_grows_to_enclosing_nodes() {
+        let mut editor = rust_editor();
+        editor.type_keys("jw<A-o>");
+        assert!(editor.mode == Mode::Visual);
+        assert_eq!(editor.selection(), ((1, 4), (1, 13)));
+        editor.type_keys("<A-o>");
+        assert_eq!(editor.selection(), ((0, 9), (2, 0)));
+    }
+
+    #[test]
+    fn rust_text_objects() {
+        let mut editor = rust_editor();
+        let content = editor.content.clone();
+        let tree = editor.syntax_tree.as_mut().unwrap();
+        assert_eq!(
+            tree.text_object(&content, (1, 4), false, false),
+            Some(((0, 0), (2, 1)))
+        );
+        assert_eq!(
+            tree.text_object(&content, (1, 4), false, true),
+            Some(((1, 0), (2, 0)))
+        );
+        // a body on one line keeps its surrounding spaces
+        let content: Vec<String> = vec!["fn f() { 1 }".into()];
+        let mut tree = SyntaxTree::new("rust", &content).unwrap();
+        assert_eq!(
+            tree.text_object(&content, (0, 9), false, true),
+            Some(((0, 8), (0, 11)))
+        );
+        assert_eq!(tree.text_object(&content, (0, 9), true, false), None);
+    }
+
+    #[test]
+    fn toml_text_objects() {
+        let content: Vec<String> = TOML.lines().map(String::from).collect();
+        let mut tree = SyntaxTree::new("toml", &content).unwrap();
+        assert_eq!(
+            tree.text_object(&content, (4, 0), true, false),
+            Some(((3, 0), (5, 0)))
+        );
+        assert_eq!(
+            tree.text_object(&content, (3, 3), true, true),
+            Some(((4, 0), (5, 0)))
+        );
+    }
+
+    #[test]
+    fn operators_take_text_objects() {
+        let mut editor = rust_editor();
+        editor.type_keys("jyif");
+        assert_eq!(editor.registers[&'"'], "    let x = 1;\n");
+        editor.type_keys("Gdaf");
+        assert_eq!(editor.text(), "fn one() {\n    let x = 1;\n}\n\n\n");
+        editor.type_keys("ggdif");
+        assert_eq!(editor.text(), "fn one() {\n}\n\n\n");
+
+        let mut editor = editor_with(TOML);
+        editor.set_syntax("toml").unwrap();
+        editor.type_keys("Gdic");
+        assert_eq!(
+            editor.text(),
+            "[package]\nname = \"eep\"\n\n[dependencies]\n"
+        );
+        editor.type_keys("ggdac");
+        assert_eq!(editor.text(), "[dependencies]\n");
+    }
+
+    #[test]
+    fn text_object_outside_one_fails() {
+        let mut editor = rust_editor();
+        editor.type_keys("3jdaf");
+        assert_eq!(editor.text(), RUST);
+        assert_eq!(editor.status_msg, "Not inside a function");
+        // plain `a` still appends
+        editor.type_keys("ggafoo<Esc>");
+        assert!(editor.text().starts_with("ffoon one()"));
+    }
+}
@@ -3,13 +3,69 @@ use eep::*;
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut editor = Editor::new();
 
-    if let Some(filename) = std::env::args().nth(1) {
+    match Config::load() {
+        Ok(config) => {
+            let theme = config.theme.clone();
+            editor.config = config;
+            if let Err(e) = editor.set_colorscheme(&theme) {
+                editor.set_status(&e.to_string());
+            }
+            if let Err(e) = editor.apply_keymap_config() {
+                editor.set_status(&e);
+            }
+            if let Err(e) = editor.load_user_grammars() {
+                editor.set_status(&e.to_string());
+            }
+        }
+        Err(e) => editor.set_status(&e.to_string()),
+    }
+    editor.load_command_history();
+
+    let mut filename = None;
+    let mut position = None;
+    for arg in std::env::args().skip(1) {
+        match arg.strip_prefix('+') {
+            // a bare `+` goes to the last line
+            Some(line) => position = Some((line.parse().unwrap_or(usize::MAX), None)),
+            None => {
+                let (name, line) = split_position(&arg);
+                filename = Some(name);
+                position = position.or(line);
+            }
+        }
+    }
+
+    if let Some(filename) = filename {
         if let Err(e) = editor.open_file(&filename) {
             eprintln!("Failed to open {}: {}", filename, e);
             std::process::exit(1);
         }
+        if let Some((line, column)) = position {
+            editor.goto_position(line, column);
+        }
     }
 
     editor.run()?;
     Ok(())
 }
+
+/// Splits `file:42` or `file:42:7` into the file name and a 1-based line
+/// and column, unless a file with the whole name exists.
+fn split_position(arg: &str) -> (String, Option<(usize, Option<usize>)>) {
+    if std::path::Path::new(arg).exists() {
+        return (arg.to_string(), None);
+    }
+    let mut parts = arg.rsplitn(3, ':');
+    let last = parts.next().and_then(|p| p.parse().ok());
+    let middle = parts.next();
+    match (middle.and_then(|p| p.parse().ok()), parts.next()) {
+        (Some(line), Some(name)) => (name.to_string(), Some((line, last))),
+        _ => match (last, middle) {
+            (Some(line), Some(_)) => {
+                let name = &arg[..arg.rfind(':').unwrap_or(arg.len())];
+                (name.to_string(), Some((line, None)))
+            }
+            _ => (arg.to_string(), None),
+        },
+    }
+}